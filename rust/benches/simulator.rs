@@ -0,0 +1,123 @@
+//! Compare the field implementations used by the simulator, on statements from `generate_metrics_data`.
+//!
+//! Run with `cargo bench --bench simulator`.
+
+use criterion::{criterion_group, criterion_main, Criterion, BenchmarkId};
+use num_bigint::BigUint;
+use num_traits::Num;
+use std::collections::HashMap;
+
+use zkinterface::{Messages, Variables};
+use zkinterface::consumers::simulator::Simulator;
+use zkinterface::field::{Field, BigUintField, U64Field, MontgomeryField};
+use zkinterface::producers::circuit_generator::generate_metrics_data;
+
+const PRIMES: [&str; 3] = [
+    "11", // 17
+    "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFF61", // 2**128 - 159
+    "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF43", // 2**256 - 189
+];
+
+fn generate(hexaprime: &str) -> Messages {
+    let mut messages = Messages::default();
+    generate_metrics_data(&mut messages, hexaprime, 1000, 100).unwrap();
+    messages
+}
+
+/// Evaluate all constraints like the simulator does, with a given field implementation.
+fn evaluate<F: Field>(field: &F, messages: &Messages) -> usize {
+    let mut values = HashMap::new();
+    values.insert(0, field.one());
+    let mut assign = |vars: &Variables| {
+        for var in vars.get_variables() {
+            values.insert(var.id, field.element_from_bytes_le(var.value));
+        }
+    };
+    for header in &messages.circuit_headers {
+        assign(&header.instance_variables);
+    }
+    for witness in &messages.witnesses {
+        assign(&witness.assigned_variables);
+    }
+
+    let sum = |lc: &Variables| {
+        let mut sum = field.zero();
+        for term in lc.get_variables() {
+            let term = field.mul(&field.element_from_bytes_le(term.value), &values[&term.id]);
+            sum = field.add(&sum, &term);
+        }
+        sum
+    };
+
+    let mut unsatisfied = 0;
+    for cs in &messages.constraint_systems {
+        for constraint in &cs.constraints {
+            let a = sum(&constraint.linear_combination_a);
+            let b = sum(&constraint.linear_combination_b);
+            let c = sum(&constraint.linear_combination_c);
+            if field.mul(&a, &b) != c {
+                unsatisfied += 1;
+            }
+        }
+    }
+    unsatisfied
+}
+
+fn simulate(messages: &Messages) -> usize {
+    let mut simulator = Simulator::default();
+    for header in &messages.circuit_headers {
+        simulator.ingest_header(header).unwrap();
+    }
+    for witness in &messages.witnesses {
+        simulator.ingest_witness(witness).unwrap();
+    }
+    for cs in &messages.constraint_systems {
+        simulator.ingest_constraint_system(cs).unwrap();
+    }
+    simulator.get_violations().len()
+}
+
+fn bench_fields(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluate");
+
+    for hexaprime in &PRIMES {
+        let modulus = BigUint::from_str_radix(hexaprime, 16).unwrap();
+        let messages = generate(hexaprime);
+        let bits = modulus.bits();
+
+        group.bench_with_input(BenchmarkId::new("BigUintField", bits), &messages, |b, messages| {
+            let field = BigUintField::new(modulus.clone());
+            b.iter(|| assert_eq!(evaluate(&field, messages), 0))
+        });
+
+        if let Some(field) = U64Field::new(&modulus) {
+            group.bench_with_input(BenchmarkId::new("U64Field", bits), &messages, |b, messages| {
+                b.iter(|| assert_eq!(evaluate(&field, messages), 0))
+            });
+        }
+
+        if let Some(field) = MontgomeryField::new(&modulus) {
+            group.bench_with_input(BenchmarkId::new("MontgomeryField", bits), &messages, |b, messages| {
+                b.iter(|| assert_eq!(evaluate(&field, messages), 0))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_simulator(c: &mut Criterion) {
+    let mut group = c.benchmark_group("simulate");
+
+    for hexaprime in &PRIMES {
+        let modulus = BigUint::from_str_radix(hexaprime, 16).unwrap();
+        let messages = generate(hexaprime);
+
+        group.bench_with_input(BenchmarkId::from_parameter(modulus.bits()), &messages, |b, messages| {
+            b.iter(|| assert_eq!(simulate(messages), 0))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_fields, bench_simulator);
+criterion_main!(benches);
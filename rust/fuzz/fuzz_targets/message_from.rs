@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use zkinterface::Message;
+
+fuzz_target!(|data: &[u8]| {
+    // Must never panic, whatever the input.
+    let _ = Message::from(data);
+});
@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use zkinterface::{Reader, Messages};
+
+fuzz_target!(|data: &[u8]| {
+    // Must never panic, whatever the input.
+    let mut reader = Reader::new();
+    if reader.read_from(data).is_ok() {
+        let _ = Messages::from(&reader);
+        let _ = format!("{:?}", reader);
+        let _ = reader.iter_constraints().count();
+        let _ = reader.iter_witness().count();
+    }
+});
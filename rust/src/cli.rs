@@ -1,17 +1,31 @@
 extern crate serde;
 extern crate serde_json;
 
-use std::fs::{File, create_dir_all, remove_file};
-use std::io::{stdin, stdout, Read, Write, copy};
+use std::fs::{File, create_dir_all, read_dir, remove_file};
+use std::io::{stdin, stdout, stderr, Read, Write, BufReader, BufWriter, copy};
 use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 use num_bigint::BigUint;
 use num_integer::Integer;
 
-use crate::{Reader, Workspace, Messages, consumers::stats::Stats, Result};
-use crate::consumers::workspace::{list_workspace_files, has_zkif_extension};
+use crate::{Reader, Workspace, WorkspaceSink, Messages, Message, consumers::stats::Stats, Result};
+use crate::consumers::workspace::{list_workspace_files, order_workspace_files, has_zkif_extension};
 use crate::consumers::validator::Validator;
 use crate::consumers::simulator::Simulator;
+use crate::consumers::parallel::batch_messages;
+use crate::consumers::mapped::MappedWorkspace;
+use crate::consumers::index::FileIndex;
+use crate::consumers::graph::{GraphWriter, GraphFormat};
+use crate::consumers::diff::diff;
+use crate::consumers::fingerprint::fingerprint;
+use crate::consumers::solver::solve_witness;
+use crate::consumers::determinism::check_determinism;
+use crate::transform::optimizer::optimize;
+use crate::formats::circom::{R1cs, Wtns, from_circom, to_circom};
+use crate::transform::renumber::renumber;
+use crate::transform::normalize::normalize;
+use crate::transform::merge::merge;
+use crate::transform::split::{split, SplitLimits};
 use crate::producers::circuit_generator::generate_sequence_metrics_data;
 
 const ABOUT: &str = "
@@ -28,14 +42,59 @@ Print a statement in different forms:
     zkif to-json workspace
     zkif to-yaml workspace
     zkif explain workspace
+    zkif graph   workspace > graph.dot
+    zkif graph   workspace --format json
+
+Convert a statement from JSON or YAML back into .zkif files:
+    zkif from-json statement.json workspace
+    zkif from-yaml statement.yaml - > workspace/statement.zkif
+
+Convert from and to the .r1cs and .wtns formats of circom:
+    zkif import-circom circuit.r1cs witness.wtns workspace
+    zkif export-circom workspace circuit.r1cs
+
+Optimize a statement by eliminating linear and redundant constraints:
+    zkif optimize workspace optimized_workspace
+
+Renumber variables densely, and optionally write the mapping from old to new IDs:
+    zkif renumber workspace renumbered_workspace --mapping mapping.json
+
+Normalize constraints and values into a canonical form:
+    zkif normalize workspace normalized_workspace --swap-ab
+
+Compute the missing witness values from the constraints:
+    zkif solve workspace solved_workspace
+
+Merge workspaces, or split messages into files of at most N constraints or bytes:
+    zkif merge workspace_1 workspace_2 merged_workspace
+    zkif split workspace split_workspace --max-constraints 1000000 --max-bytes 100000000
+
+Hash the circuit, the instance and the witness, independently of how they are split into messages:
+    zkif hash workspace
+    zkif hash workspace --format json
+
+Compare two statements, independently of how they are split into messages:
+    zkif diff workspace other_workspace
+    zkif diff workspace other_workspace --ignore-renumbering --format json    (constraints in the same order)
 
 Simulate a proving system:
     zkif stats       workspace
+    zkif stats       workspace --format table
     zkif validate    workspace
+    zkif validate    workspace --format json
     zkif simulate    workspace
+    zkif simulate    workspace --report-all
+    zkif simulate    workspace --parallel --mmap
     zkif fake_prove  workspace
     zkif fake_verify workspace
 
+Look for witness variables that are not determined by the constraints:
+    zkif check-determinism workspace
+    zkif check-determinism workspace --random-trials 10 --format json
+
+Index the files for random access by debugging tools, into .zkif.idx files next to them:
+    zkif index workspace
+
 Write all the statement files to stdout (to pipe to another program):
     zkif cat workspace
 
@@ -64,15 +123,45 @@ pub struct Options {
     ///
     /// to-yaml     Convert to YAML.
     ///
+    /// from-json   Convert from JSON into .zkif files. Give the JSON file, then the output workspace.
+    ///
+    /// from-yaml   Convert from YAML into .zkif files. Give the YAML file, then the output workspace.
+    ///
+    /// import-circom Convert from circom. Give the .r1cs file, optionally the .wtns file, then the output workspace.
+    ///
+    /// export-circom Convert to circom. Give the workspace, then the .r1cs file. The witness goes into a .wtns file next to it.
+    ///
+    /// optimize    Eliminate linear, duplicate and trivial constraints. Give the workspace, then the output workspace.
+    ///
+    /// renumber    Renumber variables densely. Give the workspace, then the output workspace.
+    ///
+    /// normalize   Normalize constraints and values into a canonical form. Give the workspace, then the output workspace.
+    ///
+    /// solve       Compute missing witness values from the constraints. Give the workspace, then the output workspace.
+    ///
+    /// merge       Merge the headers, witnesses and constraints of several workspaces. Give the workspaces, then the output workspace.
+    ///
+    /// split       Split constraints and witnesses into files of at most --max-constraints or --max-bytes. Give the workspace, then the output workspace.
+    ///
+    /// hash        Print hashes of the circuit, the instance and the witness, to identify them.
+    ///
+    /// diff        Compare the header, constraints and witness of two statements. Give the two workspaces.
+    ///
+    /// check-determinism Look for witness variables that are not determined by the constraints, structurally, and optionally with --random-trials.
+    ///
     /// explain     Print the content in a human-readable form.
     ///
+    /// graph       Print the variables and constraints as a graph in Graphviz DOT format.
+    ///
     /// validate    Validate the format and semantics of a statement, as seen by a verifier.
     ///
     /// simulate    Simulate a proving system as prover by verifying that the statement is true.
     ///
-    /// stats       Calculate statistics about the circuit.
+    /// stats       Calculate statistics about the circuit, in total, per file, and per namespace of constraints.
+    ///
+    /// index       Index the files for random access, into .zkif.idx files next to them.
     ///
-    /// clean       Clean workspace by deleting all *.zkif files in it.
+    /// clean       Clean workspace by deleting all *.zkif files in it, and their indexes.
     ///
     /// metrics-all Generate lots of R1CS constraint systems with pre-defined parameters to benchmark proof systems.
     ///
@@ -85,6 +174,8 @@ pub struct Options {
     ///
     /// Alternatively, a list of .zkif files can be provided explicitly.
     ///
+    /// Files are read with headers first, then witnesses, then constraints. To choose the order, list the files in a zkif_manifest.txt file in the workspace.
+    ///
     /// The dash - means either write to stdout or read from stdin.
     #[structopt(default_value = ".")]
     pub paths: Vec<PathBuf>,
@@ -97,6 +188,80 @@ pub struct Options {
 
     #[structopt(short, long, default_value = "100")]
     pub instance_nbr: u64,
+
+    /// simulate: report all unsatisfied constraints instead of stopping at the first one.
+    #[structopt(long)]
+    pub report_all: bool,
+
+    /// validate, simulate: check constraints on all cores.
+    /// The number of threads can be set with the environment variable RAYON_NUM_THREADS.
+    #[structopt(long)]
+    pub parallel: bool,
+
+    /// validate, simulate, stats: read files through memory maps instead of copying each message.
+    #[structopt(long)]
+    pub mmap: bool,
+
+    /// Output format of the tool, if it supports several.
+    ///
+    /// validate: text (default) or json.
+    ///
+    /// graph: dot (default) or json, with one node or edge per line.
+    ///
+    /// diff, hash, check-determinism: text (default) or json.
+    ///
+    /// stats: json (default), csv, or table, with the totals and a breakdown per file.
+    #[structopt(long)]
+    pub format: Option<String>,
+
+    /// renumber: write the mapping from old to new variable IDs into this JSON file.
+    #[structopt(long)]
+    pub mapping: Option<PathBuf>,
+
+    /// normalize: also order A and B of each constraint canonically.
+    #[structopt(long)]
+    pub swap_ab: bool,
+
+    /// split: the maximum number of constraints per file, or of variables per witness file.
+    #[structopt(long)]
+    pub max_constraints: Option<u64>,
+
+    /// split: the maximum size of files in bytes.
+    #[structopt(long)]
+    pub max_bytes: Option<u64>,
+
+    /// diff: renumber the variables of both statements canonically before comparing them.
+    /// Witness variables are numbered in the order of their first use in constraints,
+    /// so this only matches statements whose constraints come in the same order.
+    #[structopt(long)]
+    pub ignore_renumbering: bool,
+
+    /// check-determinism: how many random values to try for each witness variable, or 0 to skip this test.
+    #[structopt(long, default_value = "0")]
+    pub random_trials: u32,
+}
+
+impl Default for Options {
+    /// The same defaults as the command line.
+    fn default() -> Self {
+        Options {
+            tool: "help".to_string(),
+            paths: vec![PathBuf::from(".")],
+            field_order: BigUint::from(101u32),
+            witness_nbr: 100,
+            instance_nbr: 100,
+            report_all: false,
+            parallel: false,
+            mmap: false,
+            format: None,
+            mapping: None,
+            swap_ab: false,
+            max_constraints: None,
+            max_bytes: None,
+            ignore_renumbering: false,
+            random_trials: 0,
+        }
+    }
 }
 
 pub fn cli(options: &Options) -> Result<()> {
@@ -105,10 +270,25 @@ pub fn cli(options: &Options) -> Result<()> {
         "cat" => main_cat(options),
         "to-json" => main_json(&load_messages(options)?),
         "to-yaml" => main_yaml(&load_messages(options)?),
+        "from-json" => main_from_text(options, Messages::from_json),
+        "from-yaml" => main_from_text(options, Messages::from_yaml),
+        "import-circom" => main_import_circom(options),
+        "export-circom" => main_export_circom(options),
+        "optimize" => main_optimize(options),
+        "renumber" => main_renumber(options),
+        "normalize" => main_normalize(options),
+        "solve" => main_solve(options),
+        "merge" => main_merge(options),
+        "split" => main_split(options),
+        "hash" => main_hash(&stream_messages(options)?, options.format.as_deref()),
+        "diff" => main_diff(options),
+        "check-determinism" => main_check_determinism(options),
         "explain" => main_explain(&load_messages(options)?),
-        "validate" => main_validate(&stream_messages(options)?),
-        "simulate" => main_simulate(&stream_messages(options)?),
-        "stats" => main_stats(&stream_messages(options)?),
+        "graph" => main_graph(&stream_messages(options)?, options.format.as_deref()),
+        "validate" => with_messages(options, |messages| main_validate(messages, options.format.as_deref(), options.parallel)),
+        "simulate" => with_messages(options, |messages| main_simulate(messages, options.report_all, options.parallel)),
+        "stats" => main_stats(options),
+        "index" => main_index(&stream_messages(options)?),
         "clean" => main_clean(options),
         "fake_prove" => main_fake_prove(&load_messages(options)?),
         "fake_verify" => main_fake_verify(&load_messages(options)?),
@@ -131,7 +311,7 @@ pub fn cli(options: &Options) -> Result<()> {
 fn load_messages(opts: &Options) -> Result<Reader> {
     let mut reader = Reader::new();
 
-    for path in list_workspace_files(&opts.paths)? {
+    for path in order_files(&opts.paths)? {
         if path == Path::new("-") {
             eprintln!("Loading from stdin");
             reader.read_from(stdin())?;
@@ -145,8 +325,59 @@ fn load_messages(opts: &Options) -> Result<Reader> {
     Ok(reader)
 }
 
+/// Find the files of a workspace, see `order_workspace_files`, and print warnings about them.
+fn order_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let (files, warnings) = order_workspace_files(paths)?;
+    print_warnings(&warnings);
+    Ok(files)
+}
+
+fn open_workspace(paths: &[PathBuf]) -> Result<Workspace> {
+    let workspace = Workspace::from_dirs_and_files(paths)?;
+    print_warnings(workspace.warnings());
+    Ok(workspace)
+}
+
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
 fn stream_messages(opts: &Options) -> Result<Workspace> {
-    Workspace::from_dirs_and_files(&opts.paths)
+    open_workspace(&opts.paths)
+}
+
+/// Read messages one by one, from memory-mapped files with --mmap, or as streams.
+/// Messages that cannot be read are passed on as `Message::Err`, and fail the tool at the end.
+fn with_messages(opts: &Options, run: impl FnOnce(&mut dyn Iterator<Item=Message>) -> Result<()>) -> Result<()> {
+    with_messages_in(order_files(&opts.paths)?, opts.mmap, run)
+}
+
+/// Like `with_messages`, from files already in order.
+fn with_messages_in(files: Vec<PathBuf>, mmap: bool, run: impl FnOnce(&mut dyn Iterator<Item=Message>) -> Result<()>) -> Result<()> {
+    let mut read_errors = 0;
+    let mut count_errors = |msg: &Message| if let Message::Err(_) = msg { read_errors += 1; };
+
+    let result = if mmap {
+        let ws = MappedWorkspace::from_ordered_filenames(files)?;
+        let mut messages = ws.try_iter_messages()
+            .map(|msg| msg.unwrap_or_else(|err| Message::Err(err.to_string())))
+            .inspect(&mut count_errors);
+        run(&mut messages)
+    } else {
+        let ws = Workspace::from_ordered_filenames(files);
+        let mut messages = ws.try_iter_messages()
+            .map(|msg| msg.unwrap_or_else(|err| Message::Err(err.to_string())))
+            .inspect(&mut count_errors);
+        run(&mut messages)
+    };
+    result?;
+
+    if read_errors > 0 {
+        return Err(format!("Failed to read {} messages.", read_errors).into());
+    }
+    Ok(())
 }
 
 fn field_order_to_maximum(order: &BigUint) -> Result<Vec<u8>> {
@@ -197,7 +428,7 @@ fn main_example(opts: &Options) -> Result<()> {
 }
 
 fn main_cat(opts: &Options) -> Result<()> {
-    for path in list_workspace_files(&opts.paths)? {
+    for path in order_files(&opts.paths)? {
         let mut file = File::open(&path)?;
         let mut stdout = stdout();
         copy(&mut file, &mut stdout)?;
@@ -219,30 +450,334 @@ fn main_yaml(reader: &Reader) -> Result<()> {
     Ok(())
 }
 
+fn main_from_text(opts: &Options, parse: fn(Box<dyn Read>) -> Result<Messages>) -> Result<()> {
+    if opts.paths.is_empty() || opts.paths.len() > 2 {
+        return Err("Specify the file to convert, and optionally where to write the messages.".into());
+    }
+
+    let in_path = &opts.paths[0];
+    let messages = if in_path == Path::new("-") {
+        eprintln!("Loading from stdin");
+        parse(Box::new(stdin()))?
+    } else {
+        eprintln!("Loading file {}", in_path.display());
+        parse(Box::new(File::open(in_path)?))?
+    };
+
+    let out_path = opts.paths.get(1).map(|p| p.as_path()).unwrap_or(Path::new("-"));
+    write_messages(&messages, out_path)
+}
+
+/// Write messages to stdout (-), to a single .zkif file, or into a workspace directory.
+fn write_messages(messages: &Messages, out_path: &Path) -> Result<()> {
+    if out_path == Path::new("-") {
+        messages.write_into(&mut stdout())?;
+    } else if has_zkif_extension(out_path) {
+        messages.write_into(&mut File::create(out_path)?)?;
+        eprintln!("Written {}", out_path.display());
+    } else {
+        messages.push_into(&mut WorkspaceSink::new(out_path)?)?;
+        eprintln!("Written workspace {}", out_path.display());
+    }
+    Ok(())
+}
+
+fn main_import_circom(opts: &Options) -> Result<()> {
+    let (out_path, r1cs_path, wtns_path) = match &opts.paths[..] {
+        [r1cs, out] => (out, r1cs, None),
+        [r1cs, wtns, out] => (out, r1cs, Some(wtns)),
+        _ => return Err("Specify the .r1cs file, optionally the .wtns file, then where to write the messages.".into()),
+    };
+
+    eprintln!("Loading file {}", r1cs_path.display());
+    let r1cs = R1cs::read(BufReader::new(File::open(r1cs_path)?))?;
+    let wtns = match wtns_path {
+        Some(path) => {
+            eprintln!("Loading file {}", path.display());
+            Some(Wtns::read(BufReader::new(File::open(path)?))?)
+        }
+        None => None,
+    };
+
+    let messages = from_circom(&r1cs, wtns.as_ref())?;
+    write_messages(&messages, out_path)
+}
+
+fn main_export_circom(opts: &Options) -> Result<()> {
+    if opts.paths.len() < 2 {
+        return Err("Specify the workspace to export, then the .r1cs file to write.".into());
+    }
+    let (r1cs_path, in_paths) = opts.paths.split_last().unwrap();
+
+    let messages = open_workspace(in_paths)?.try_read_all_messages()?;
+    let (r1cs, wtns) = to_circom(&messages)?;
+
+    let mut writer = BufWriter::new(File::create(r1cs_path)?);
+    r1cs.write(&mut writer)?;
+    writer.flush()?;
+    eprintln!("Written {}", r1cs_path.display());
+
+    if let Some(wtns) = wtns {
+        let wtns_path = r1cs_path.with_extension("wtns");
+        let mut writer = BufWriter::new(File::create(&wtns_path)?);
+        wtns.write(&mut writer)?;
+        writer.flush()?;
+        eprintln!("Written {}", wtns_path.display());
+    }
+    Ok(())
+}
+
+fn main_optimize(opts: &Options) -> Result<()> {
+    if opts.paths.len() < 2 {
+        return Err("Specify the workspace to optimize, then where to write the optimized messages.".into());
+    }
+    let (out_path, in_paths) = opts.paths.split_last().unwrap();
+
+    let messages = open_workspace(in_paths)?.try_read_all_messages()?;
+    let (optimized, report) = optimize(&messages)?;
+    write_messages(&optimized, out_path)?;
+
+    if out_path == Path::new("-") {
+        serde_json::to_writer_pretty(stderr(), &report)?;
+        eprintln!();
+    } else {
+        serde_json::to_writer_pretty(stdout(), &report)?;
+        println!();
+    }
+    Ok(())
+}
+
+fn main_renumber(opts: &Options) -> Result<()> {
+    if opts.paths.len() < 2 {
+        return Err("Specify the workspace to renumber, then where to write the renumbered messages.".into());
+    }
+    let (out_path, in_paths) = opts.paths.split_last().unwrap();
+
+    let messages = open_workspace(in_paths)?.try_read_all_messages()?;
+    let (renumbered, mapping) = renumber(&messages)?;
+    write_messages(&renumbered, out_path)?;
+
+    if let Some(mapping_path) = &opts.mapping {
+        serde_json::to_writer_pretty(File::create(mapping_path)?, &mapping)?;
+        eprintln!("Written {}", mapping_path.display());
+    }
+    Ok(())
+}
+
+fn main_normalize(opts: &Options) -> Result<()> {
+    if opts.paths.len() < 2 {
+        return Err("Specify the workspace to normalize, then where to write the normalized messages.".into());
+    }
+    let (out_path, in_paths) = opts.paths.split_last().unwrap();
+    let workspace = open_workspace(in_paths)?;
+
+    if out_path == Path::new("-") || has_zkif_extension(out_path) {
+        let mut messages = Messages::default();
+        normalize(&workspace, opts.swap_ab, &mut messages)?;
+        write_messages(&messages, out_path)
+    } else {
+        normalize(&workspace, opts.swap_ab, WorkspaceSink::new(out_path)?)?;
+        eprintln!("Written workspace {}", out_path.display());
+        Ok(())
+    }
+}
+
+fn main_solve(opts: &Options) -> Result<()> {
+    if opts.paths.len() < 2 {
+        return Err("Specify the workspace to solve, then where to write the solved messages.".into());
+    }
+    let (out_path, in_paths) = opts.paths.split_last().unwrap();
+
+    let mut messages = open_workspace(in_paths)?.try_read_all_messages()?;
+    let (witness, report) = solve_witness(&messages)?;
+    messages.witnesses = vec![witness];
+    write_messages(&messages, out_path)?;
+
+    if out_path == Path::new("-") {
+        serde_json::to_writer_pretty(stderr(), &report)?;
+        eprintln!();
+    } else {
+        serde_json::to_writer_pretty(stdout(), &report)?;
+        println!();
+    }
+
+    if report.unsolved_variables.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Could not determine the values of {} variables.", report.unsolved_variables.len()).into())
+    }
+}
+
+fn main_merge(opts: &Options) -> Result<()> {
+    if opts.paths.len() < 2 {
+        return Err("Specify the workspaces to merge, then where to write the merged workspace.".into());
+    }
+    let (out_path, in_paths) = opts.paths.split_last().unwrap();
+
+    let workspaces = in_paths.iter()
+        .map(|path| open_workspace(std::slice::from_ref(path)))
+        .collect::<Result<Vec<_>>>()?;
+    check_empty_output(out_path)?;
+    merge(&workspaces, WorkspaceSink::new(out_path)?)?;
+    eprintln!("Written workspace {}", out_path.display());
+    Ok(())
+}
+
+fn main_split(opts: &Options) -> Result<()> {
+    if opts.paths.len() < 2 {
+        return Err("Specify the workspace to split, then where to write the split workspace.".into());
+    }
+    if opts.max_constraints.is_none() && opts.max_bytes.is_none() {
+        return Err("Specify --max-constraints or --max-bytes.".into());
+    }
+    let (out_path, in_paths) = opts.paths.split_last().unwrap();
+
+    let limits = SplitLimits { max_items: opts.max_constraints, max_bytes: opts.max_bytes };
+    check_empty_output(out_path)?;
+    split(&open_workspace(in_paths)?, &limits, WorkspaceSink::new_with_witness_files(out_path)?)?;
+    eprintln!("Written workspace {}", out_path.display());
+    Ok(())
+}
+
+/// Refuse to write a workspace into a directory with files, which would be mixed with the new ones.
+fn check_empty_output(path: &Path) -> Result<()> {
+    if path.is_dir() && read_dir(path)?.next().is_some() {
+        return Err(format!("The output directory {} is not empty.", path.display()).into());
+    }
+    Ok(())
+}
+
+fn main_hash(ws: &Workspace, format: Option<&str>) -> Result<()> {
+    let fingerprint = fingerprint(ws)?;
+    match format.unwrap_or("text") {
+        "text" => {
+            println!("circuit  {}", fingerprint.circuit);
+            println!("instance {}", fingerprint.instance);
+            println!("witness  {}", fingerprint.witness);
+        }
+        "json" => {
+            serde_json::to_writer_pretty(stdout(), &fingerprint)?;
+            println!();
+        }
+        other => return Err(format!("Unknown format {} for hash, expected text or json", other).into()),
+    }
+    Ok(())
+}
+
+fn main_diff(opts: &Options) -> Result<()> {
+    if opts.paths.len() != 2 {
+        return Err("Specify the two workspaces to compare.".into());
+    }
+    let left = open_workspace(&opts.paths[..1])?.try_read_all_messages()?;
+    let right = open_workspace(&opts.paths[1..])?.try_read_all_messages()?;
+    let diff = diff(&left, &right, opts.ignore_renumbering)?;
+
+    match opts.format.as_deref().unwrap_or("text") {
+        "text" => print!("{}", diff),
+        "json" => {
+            serde_json::to_writer_pretty(stdout(), &diff)?;
+            println!();
+        }
+        other => return Err(format!("Unknown format {} for diff, expected text or json", other).into()),
+    }
+
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        Err("The statements are different.".into())
+    }
+}
+
+fn main_check_determinism(opts: &Options) -> Result<()> {
+    let messages = open_workspace(&opts.paths)?.try_read_all_messages()?;
+    let report = check_determinism(&messages, opts.random_trials)?;
+
+    match opts.format.as_deref().unwrap_or("text") {
+        "text" => print!("{}", report),
+        "json" => {
+            serde_json::to_writer_pretty(stdout(), &report)?;
+            println!();
+        }
+        other => return Err(format!("Unknown format {} for check-determinism, expected text or json", other).into()),
+    }
+
+    if report.is_determined() {
+        Ok(())
+    } else {
+        Err("Some witness variables may not be determined by the constraints.".into())
+    }
+}
+
 fn main_explain(reader: &Reader) -> Result<()> {
     eprintln!("{:?}", reader);
     Ok(())
 }
 
-fn main_validate(ws: &Workspace) -> Result<()> {
+fn main_graph(ws: &Workspace, format: Option<&str>) -> Result<()> {
+    let format = match format.unwrap_or("dot") {
+        "dot" => GraphFormat::Dot,
+        "json" => GraphFormat::Json,
+        other => return Err(format!("Unknown format {} for graph, expected dot or json", other).into()),
+    };
+
+    let mut graph = GraphWriter::new(BufWriter::new(stdout()), format)?;
+    for msg in ws.try_iter_messages() {
+        graph.ingest_message(&msg?)?;
+    }
+    graph.finish()?;
+    Ok(())
+}
+
+fn main_validate(messages: &mut dyn Iterator<Item=Message>, format: Option<&str>, parallel: bool) -> Result<()> {
     // Validate semantics as verifier.
     let mut validator = Validator::new_as_verifier();
-    for msg in ws.iter_messages() {
-        validator.ingest_message(&msg);
+    if parallel {
+        for batch in batch_messages(messages) {
+            validator.ingest_batch(&batch);
+        }
+    } else {
+        for msg in messages {
+            validator.ingest_message(&msg);
+        }
+    }
+
+    match format.unwrap_or("text") {
+        "text" => print_violations(&validator.get_violations(), "COMPLIANT with the specification"),
+        "json" => {
+            let reports = validator.get_violation_reports();
+            serde_json::to_writer_pretty(stdout(), &reports)?;
+            println!();
+            if !reports.is_empty() {
+                Err(format!("Found {} violations.", reports.len()).into())
+            } else {
+                Ok(())
+            }
+        }
+        other => Err(format!("Unknown format {} for validate, expected text or json", other).into()),
     }
-    print_violations(&validator.get_violations(), "COMPLIANT with the specification")
 }
 
-fn main_simulate(ws: &Workspace) -> Result<()> {
+fn main_simulate(messages: &mut dyn Iterator<Item=Message>, report_all: bool, parallel: bool) -> Result<()> {
     // Validate semantics as prover.
     let mut validator = Validator::new_as_prover();
     // Check whether the statement is true.
-    let mut simulator = Simulator::default();
+    let mut simulator = if report_all {
+        Simulator::new_reporting_all()
+    } else {
+        Simulator::default()
+    };
 
     // Must validate and simulate in parallel to support stdin.
-    for msg in ws.iter_messages() {
-        validator.ingest_message(&msg);
-        simulator.ingest_message(&msg);
+    if parallel {
+        for batch in batch_messages(messages) {
+            validator.ingest_batch(&batch);
+            simulator.ingest_batch(&batch);
+        }
+    } else {
+        for msg in messages {
+            validator.ingest_message(&msg);
+            simulator.ingest_message(&msg);
+        }
     }
 
     let result_val = print_violations(&validator.get_violations(), "COMPLIANT with the specification");
@@ -261,11 +796,35 @@ fn print_violations(errors: &[String], what_it_is_supposed_to_be: &str) -> Resul
     }
 }
 
-fn main_stats(ws: &Workspace) -> Result<()> {
+fn main_stats(opts: &Options) -> Result<()> {
     let mut stats = Stats::default();
-    stats.ingest_workspace(ws);
-    serde_json::to_writer_pretty(stdout(), &stats)?;
-    println!();
+    for path in order_files(&opts.paths)? {
+        with_messages_in(vec![path.clone()], opts.mmap, |messages| {
+            stats.ingest_file_messages(&path, messages);
+            Ok(())
+        })?;
+    }
+
+    match opts.format.as_deref().unwrap_or("json") {
+        "json" => {
+            serde_json::to_writer_pretty(stdout(), &stats)?;
+            println!();
+        }
+        "csv" => print!("{}", stats.to_csv()),
+        "table" => print!("{}", stats.to_table()),
+        other => return Err(format!("Unknown format {} for stats, expected json, csv or table", other).into()),
+    }
+    Ok(())
+}
+
+fn main_index(ws: &Workspace) -> Result<()> {
+    let index = ws.write_index()?;
+    for file in &index.files {
+        eprintln!("Indexed {}: {} messages, {} constraints.", file.path.display(), file.messages.len(), file.num_constraints);
+        if let Some(err) = &file.error {
+            eprintln!("Warning: {}", err);
+        }
+    }
     Ok(())
 }
 
@@ -279,6 +838,12 @@ fn main_clean(opts: &Options) -> Result<()> {
             }
             _ => { /* OK */ }
         }
+
+        let index = FileIndex::sidecar_path(file);
+        if index.exists() {
+            eprintln!("Removing {}", index.display());
+            remove_file(index)?;
+        }
     }
 
     Ok(())
@@ -335,26 +900,262 @@ fn test_cli() -> Result<()> {
     cli(&Options {
         tool: "example".to_string(),
         paths: vec![workspace.clone()],
-        field_order: BigUint::from(101 as u32),
-        witness_nbr: 0,
-        instance_nbr: 0,
+        ..Default::default()
     })?;
 
     cli(&Options {
         tool: "validate".to_string(),
         paths: vec![workspace.clone()],
-        field_order: BigUint::from(101 as u32),
-        witness_nbr: 0,
-        instance_nbr: 0,
+        ..Default::default()
+    })?;
+
+    cli(&Options {
+        tool: "validate".to_string(),
+        paths: vec![workspace.clone()],
+        format: Some("json".to_string()),
+        ..Default::default()
+    })?;
+
+    cli(&Options {
+        tool: "simulate".to_string(),
+        paths: vec![workspace.clone()],
+        ..Default::default()
+    })?;
+
+    cli(&Options {
+        tool: "simulate".to_string(),
+        paths: vec![workspace.clone()],
+        report_all: true,
+        parallel: true,
+        mmap: true,
+        ..Default::default()
+    })?;
+
+    cli(&Options {
+        tool: "index".to_string(),
+        paths: vec![workspace.clone()],
+        ..Default::default()
+    })?;
+    assert!(workspace.join("header.zkif.idx").exists());
+
+    cli(&Options {
+        tool: "graph".to_string(),
+        paths: vec![workspace.clone()],
+        format: Some("json".to_string()),
+        ..Default::default()
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_from_json() -> Result<()> {
+    use std::fs::remove_dir_all;
+
+    let workspace = PathBuf::from("local/test_cli_from_json");
+    let _ = remove_dir_all(&workspace);
+    create_dir_all(&workspace)?;
+
+    let json_path = workspace.join("statement.json");
+    let yaml_path = workspace.join("statement.yaml");
+    let messages = {
+        use crate::producers::examples::*;
+        Messages {
+            circuit_headers: vec![example_circuit_header()],
+            constraint_systems: vec![example_constraints()],
+            witnesses: vec![example_witness()],
+        }
+    };
+    serde_json::to_writer(File::create(&json_path)?, &messages)?;
+    serde_yaml::to_writer(File::create(&yaml_path)?, &messages)?;
+
+    cli(&Options {
+        tool: "from-json".to_string(),
+        paths: vec![json_path, workspace.join("from_json")],
+        ..Default::default()
+    })?;
+
+    cli(&Options {
+        tool: "from-yaml".to_string(),
+        paths: vec![yaml_path, workspace.join("from_yaml.zkif")],
+        ..Default::default()
+    })?;
+
+    for path in &[workspace.join("from_json"), workspace.join("from_yaml.zkif")] {
+        let ws = Workspace::from_dirs_and_files(std::slice::from_ref(path))?;
+        assert_eq!(ws.read_all_messages(), messages);
+
+        cli(&Options {
+            tool: "simulate".to_string(),
+            paths: vec![path.clone()],
+            report_all: true,
+            ..Default::default()
+        })?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_optimize_renumber() -> Result<()> {
+    use std::fs::remove_dir_all;
+
+    let workspace = PathBuf::from("local/test_cli_optimize_renumber");
+    let _ = remove_dir_all(&workspace);
+    let optimized = workspace.join("optimized");
+
+    cli(&Options {
+        tool: "example".to_string(),
+        paths: vec![workspace.clone()],
+        ..Default::default()
+    })?;
+
+    cli(&Options {
+        tool: "optimize".to_string(),
+        paths: vec![workspace.join("header.zkif"), workspace.join("witness.zkif"), workspace.join("constraints.zkif"), optimized.clone()],
+        ..Default::default()
+    })?;
+
+    let messages = Workspace::from_dir(&optimized)?.read_all_messages();
+    assert_eq!(messages.constraint_systems[0].constraints.len(), 2);
+
+    let renumbered = workspace.join("renumbered");
+    let mapping = workspace.join("mapping.json");
+    cli(&Options {
+        tool: "renumber".to_string(),
+        paths: vec![optimized.clone(), renumbered.clone()],
+        mapping: Some(mapping.clone()),
+        ..Default::default()
     })?;
 
+    let messages = Workspace::from_dir(&renumbered)?.read_all_messages();
+    assert_eq!(messages.circuit_headers[0].free_variable_id, 5);
+    let mapping: std::collections::BTreeMap<u64, u64> = serde_json::from_reader(File::open(&mapping)?)?;
+    assert_eq!(mapping.len(), 5);
+
     cli(&Options {
         tool: "simulate".to_string(),
+        paths: vec![renumbered.clone()],
+        ..Default::default()
+    })?;
+
+    let diff_options = |ignore_renumbering| Options {
+        tool: "diff".to_string(),
+        paths: vec![optimized.clone(), renumbered.clone()],
+        ignore_renumbering,
+        ..Default::default()
+    };
+    assert!(cli(&diff_options(false)).is_err());
+    cli(&diff_options(true))?;
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_circom() -> Result<()> {
+    use std::fs::remove_dir_all;
+
+    let workspace = PathBuf::from("local/test_cli_circom");
+    let _ = remove_dir_all(&workspace);
+    let imported = workspace.join("imported");
+
+    cli(&Options {
+        tool: "example".to_string(),
         paths: vec![workspace.clone()],
-        field_order: BigUint::from(101 as u32),
-        witness_nbr: 0,
-        instance_nbr: 0,
+        ..Default::default()
+    })?;
+
+    cli(&Options {
+        tool: "export-circom".to_string(),
+        paths: vec![workspace.join("header.zkif"), workspace.join("witness.zkif"), workspace.join("constraints.zkif"), workspace.join("circuit.r1cs")],
+        ..Default::default()
+    })?;
+
+    cli(&Options {
+        tool: "import-circom".to_string(),
+        paths: vec![workspace.join("circuit.r1cs"), workspace.join("circuit.wtns"), imported.clone()],
+        ..Default::default()
     })?;
 
+    cli(&Options {
+        tool: "simulate".to_string(),
+        paths: vec![imported],
+        ..Default::default()
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_merge_split_normalize() -> Result<()> {
+    use std::fs::remove_dir_all;
+
+    let workspace = PathBuf::from("local/test_cli_merge_split_normalize");
+    let _ = remove_dir_all(&workspace);
+    let example = workspace.join("example");
+    let merged = workspace.join("merged");
+    let more = workspace.join("more");
+    let split = workspace.join("split");
+
+    let options = |tool: &str, paths: Vec<PathBuf>| Options {
+        tool: tool.to_string(),
+        paths,
+        max_constraints: Some(2),
+        ..Default::default()
+    };
+
+    // Constraints from another run.
+    cli(&options("example", vec![example.clone()]))?;
+    create_dir_all(&more)?;
+    std::fs::copy(example.join("constraints.zkif"), more.join("constraints.zkif"))?;
+
+    cli(&options("merge", vec![example.clone(), more.clone(), merged.clone()]))?;
+    cli(&options("split", vec![merged.clone(), split.clone()]))?;
+    cli(&options("simulate", vec![split.clone()]))?;
+    // The output directories are not overwritten.
+    assert!(cli(&options("merge", vec![example.clone(), more.clone(), merged.clone()])).is_err());
+    assert!(cli(&options("split", vec![merged.clone(), split.clone()])).is_err());
+
+    let messages = Workspace::from_dir(&split)?.try_read_all_messages()?;
+    assert_eq!(messages.circuit_headers.len(), 1);
+    assert_eq!(messages.constraint_systems.len(), 4);
+    assert_eq!(messages.witnesses.len(), 1);
+
+    let normalized = workspace.join("normalized.zkif");
+    cli(&options("normalize", vec![split.clone(), normalized.clone()]))?;
+    cli(&options("simulate", vec![normalized.clone()]))?;
+    cli(&options("diff", vec![merged.clone(), normalized.clone()]))?;
+    cli(&options("hash", vec![normalized.clone()]))?;
+
+    for format in &["json", "csv", "table"] {
+        cli(&Options { format: Some(format.to_string()), mmap: true, ..options("stats", vec![split.clone()]) })?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_solve() -> Result<()> {
+    use std::fs::remove_dir_all;
+
+    let workspace = PathBuf::from("local/test_cli_solve");
+    let _ = remove_dir_all(&workspace);
+    let example = workspace.join("example");
+    let solved = workspace.join("solved");
+
+    let options = |tool: &str, paths: Vec<PathBuf>| Options {
+        tool: tool.to_string(),
+        paths,
+        ..Default::default()
+    };
+
+    // Forget the witness.
+    cli(&options("example", vec![example.clone()]))?;
+    remove_file(example.join("witness.zkif"))?;
+
+    cli(&options("solve", vec![example.clone(), solved.clone()]))?;
+    cli(&options("simulate", vec![solved.clone()]))?;
+    cli(&Options { random_trials: 10, ..options("check-determinism", vec![solved.clone()]) })?;
+
     Ok(())
 }
@@ -0,0 +1,303 @@
+//! Find witness variables that are not determined by the constraints.
+//!
+//! A prover can choose any value for such a variable, which usually breaks the soundness of the circuit.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use num_bigint::{BigUint, RandBigInt};
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::{Result, CircuitHeader, ConstraintSystem, Variables, Witness, Messages};
+use crate::consumers::simulator::Simulator;
+use crate::consumers::solver::Propagation;
+
+type Var = u64;
+
+/// Witness variables that may not be determined by the constraints.
+#[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct DeterminismReport {
+    /// Witness variables that are not determined by propagation from the instance, see `find_undetermined`.
+    pub undetermined_variables: Vec<Var>,
+    /// How many random values were tried for each witness variable.
+    pub random_trials: u32,
+    /// Witness variables that admit another value, see `find_alternative_assignments`.
+    pub alternative_assignments: Vec<AlternativeAssignment>,
+}
+
+/// Another value of a witness variable that satisfies all constraints, with the other values unchanged.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct AlternativeAssignment {
+    pub id: Var,
+    pub value: String,
+    pub alternative: String,
+}
+
+/// Run both analyses, with `random_trials` random values per witness variable.
+/// The randomized testing is skipped if `random_trials` is 0.
+///
+/// # Example
+/// ```
+/// use zkinterface::Messages;
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::consumers::determinism::check_determinism;
+///
+/// let messages = Messages {
+///     circuit_headers: vec![example_circuit_header()],
+///     constraint_systems: vec![example_constraints()],
+///     witnesses: vec![example_witness()],
+/// };
+///
+/// let report = check_determinism(&messages, 10).unwrap();
+/// assert!(report.is_determined());
+/// ```
+pub fn check_determinism(messages: &Messages, random_trials: u32) -> Result<DeterminismReport> {
+    let undetermined_variables = find_undetermined(messages)?;
+    let alternative_assignments = if random_trials > 0 {
+        find_alternative_assignments(messages, random_trials)?
+    } else {
+        vec![]
+    };
+    Ok(DeterminismReport { undetermined_variables, random_trials, alternative_assignments })
+}
+
+impl DeterminismReport {
+    /// Whether no variable was found to be undetermined.
+    pub fn is_determined(&self) -> bool {
+        self.undetermined_variables.is_empty() && self.alternative_assignments.is_empty()
+    }
+}
+
+/// How many variables are printed in the summary.
+const SUMMARY_LIMIT: usize = 10;
+
+impl fmt::Display for DeterminismReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.undetermined_variables.is_empty() {
+            writeln!(f, "Witness variables not determined by propagation from the instance: {}", self.undetermined_variables.len())?;
+            for id in self.undetermined_variables.iter().take(SUMMARY_LIMIT) {
+                writeln!(f, "- variable {}", id)?;
+            }
+            write_more(f, self.undetermined_variables.len())?;
+        }
+
+        if self.random_trials > 0 {
+            writeln!(f, "Witness variables with alternative values, trying {} random values each: {}", self.random_trials, self.alternative_assignments.len())?;
+            for alternative in self.alternative_assignments.iter().take(SUMMARY_LIMIT) {
+                writeln!(f, "- variable {}: {} or {}", alternative.id, alternative.value, alternative.alternative)?;
+            }
+            write_more(f, self.alternative_assignments.len())?;
+        }
+
+        if self.is_determined() {
+            writeln!(f, "The witness is DETERMINED.")
+        } else {
+            writeln!(f, "The witness may be UNDER-CONSTRAINED.")
+        }
+    }
+}
+
+fn write_more(f: &mut fmt::Formatter, count: usize) -> fmt::Result {
+    if count > SUMMARY_LIMIT {
+        writeln!(f, "- ... and {} more", count - SUMMARY_LIMIT)?;
+    }
+    Ok(())
+}
+
+/// Find the witness variables that are not determined structurally by the constraints.
+///
+/// Starting from the instance variables, a constraint with a single undetermined variable
+/// determines it, unless it appears in both A and B, or only in a product with an empty combination.
+/// This is repeated until no more progress is possible.
+/// Values are not looked at, so a factor of the variable that evaluates to zero is not noticed.
+///
+/// Variables that are only determined jointly by several constraints, such as the bits of
+/// a decomposition, are reported as well. Use randomized testing to look into them.
+///
+/// The witness variables are those in constraints or in witness messages, except instance variables.
+pub fn find_undetermined(messages: &Messages) -> Result<Vec<Var>> {
+    let header = single_header(messages)?;
+    let modulus = header.modulus();
+
+    let mut determined = header.instance_variables.variable_ids.iter().cloned().collect::<HashSet<_>>();
+    determined.insert(0);
+
+    let mut witness_ids = BTreeSet::new();
+    for witness in &messages.witnesses {
+        witness_ids.extend(witness.assigned_variables.variable_ids.iter().filter(|id| !determined.contains(id)));
+    }
+
+    // The variables in A, B and C of each constraint.
+    let mut combinations = vec![];
+    let mut propagation = Propagation::default();
+
+    for cs in &messages.constraint_systems {
+        for constraint in cs.normalize(modulus.as_ref(), false).constraints {
+            let a = constraint.linear_combination_a.variable_ids;
+            let b = constraint.linear_combination_b.variable_ids;
+            let c = constraint.linear_combination_c.variable_ids;
+
+            let unknown = a.iter().chain(&b).chain(&c)
+                .filter(|id| !determined.contains(id))
+                .cloned()
+                .collect::<BTreeSet<_>>();
+            witness_ids.extend(&unknown);
+            propagation.add_constraint(unknown);
+            combinations.push((a, b, c));
+        }
+    }
+
+    propagation.run(|index, id| {
+        // Ids of normalized combinations are sorted.
+        let (a, b, c) = &combinations[index];
+        let (in_a, in_b, in_c) = (a.binary_search(&id).is_ok(), b.binary_search(&id).is_ok(), c.binary_search(&id).is_ok());
+        // Quadratic, or multiplied by zero.
+        if (in_a && in_b) || (!in_c && ((in_a && b.is_empty()) || (in_b && a.is_empty()))) {
+            return false;
+        }
+        determined.insert(id)
+    });
+
+    Ok(witness_ids.into_iter().filter(|id| !determined.contains(id)).collect())
+}
+
+/// Find witness variables that admit another value, by randomized testing.
+///
+/// The witness must satisfy the constraints. Then each witness variable is given up to `trials`
+/// random values in turn, with the other values unchanged, and the constraints that use it are
+/// checked with the `Simulator`. Random values are generated from a fixed seed, so that results are reproducible.
+pub fn find_alternative_assignments(messages: &Messages, trials: u32) -> Result<Vec<AlternativeAssignment>> {
+    let header = single_header(messages)?;
+    let modulus = header.modulus().ok_or("No field_maximum specified")?;
+
+    let mut simulator = Simulator::default();
+    simulator.ingest_header(header)?;
+    let mut values = BTreeMap::new();
+    for witness in &messages.witnesses {
+        simulator.ingest_witness(witness)?;
+        for var in witness.assigned_variables.get_variables() {
+            values.insert(var.id, var.value.to_vec());
+        }
+    }
+
+    // Check the witness, and index the constraints by witness variable.
+    let mut constraints = vec![];
+    let mut used_in = HashMap::<Var, Vec<usize>>::new();
+    for cs in &messages.constraint_systems {
+        simulator.ingest_constraint_system(cs)
+            .map_err(|err| format!("The witness does not satisfy the constraints: {}", err))?;
+
+        for constraint in &cs.constraints {
+            let ids = constraint.linear_combination_a.variable_ids.iter()
+                .chain(&constraint.linear_combination_b.variable_ids)
+                .chain(&constraint.linear_combination_c.variable_ids)
+                .filter(|id| values.contains_key(id))
+                .cloned()
+                .collect::<BTreeSet<_>>();
+            for id in ids {
+                used_in.entry(id).or_default().push(constraints.len());
+            }
+            constraints.push(constraint);
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut alternatives = vec![];
+
+    for (id, value) in &values {
+        let using = ConstraintSystem {
+            constraints: used_in.get(id).into_iter().flatten()
+                .map(|index| constraints[*index].clone())
+                .collect(),
+            info: None,
+        };
+        let value = BigUint::from_bytes_le(value) % &modulus;
+
+        for _ in 0..trials {
+            let alternative = rng.gen_biguint_below(&modulus);
+            if alternative == value { continue; }
+
+            simulator.ingest_witness(&assign(*id, &alternative))?;
+            if simulator.ingest_constraint_system(&using).is_ok() {
+                alternatives.push(AlternativeAssignment {
+                    id: *id,
+                    value: value.to_string(),
+                    alternative: alternative.to_string(),
+                });
+                break;
+            }
+        }
+        simulator.ingest_witness(&assign(*id, &value))?;
+    }
+    Ok(alternatives)
+}
+
+fn single_header(messages: &Messages) -> Result<&CircuitHeader> {
+    match &messages.circuit_headers[..] {
+        [header] => Ok(header),
+        [] => Err("check-determinism requires a header.".into()),
+        _ => Err("check-determinism requires a single header.".into()),
+    }
+}
+
+fn assign(id: Var, value: &BigUint) -> Witness {
+    Witness {
+        assigned_variables: Variables {
+            variable_ids: vec![id],
+            values: Some(value.to_bytes_le()),
+            info: None,
+        }
+    }
+}
+
+
+#[test]
+fn test_determinism() -> Result<()> {
+    use crate::producers::examples::*;
+
+    let mut header = example_circuit_header();
+    header.free_variable_id = 9;
+
+    let mut constraints = example_constraints();
+    constraints.constraints.extend(ConstraintSystem::from(&[
+        // A boolean, determined by nothing else: b * (1 - b) = 0
+        ((vec![6], vec![1]), (vec![0, 6], vec![1, 100]), (vec![], vec![])),
+        // Multiplied by zero: 0 * v7 = 0
+        ((vec![], vec![]), (vec![7], vec![1]), (vec![], vec![])),
+        // Zero terms are ignored: 1 * v8 = xx + 0 v6
+        ((vec![0], vec![1]), (vec![8], vec![1]), (vec![4, 6], vec![1, 0])),
+    ][..]).constraints);
+
+    let mut witness = example_witness();
+    witness.assigned_variables.variable_ids.extend(&[6, 7, 8]);
+    witness.assigned_variables.values.as_mut().unwrap().extend(&[
+        1, 0, 0, 0,
+        5, 0, 0, 0,
+        9, 0, 0, 0,
+    ]);
+
+    let messages = Messages {
+        circuit_headers: vec![header],
+        constraint_systems: vec![constraints],
+        witnesses: vec![witness],
+    };
+
+    assert_eq!(find_undetermined(&messages)?, vec![6, 7]);
+
+    let alternatives = find_alternative_assignments(&messages, 10)?;
+    let ids = alternatives.iter().map(|alternative| alternative.id).collect::<Vec<_>>();
+    // The boolean has a single alternative value 0, which random values are unlikely to hit.
+    assert_eq!(ids, vec![7]);
+    assert_eq!(alternatives[0].value, "5");
+
+    let report = check_determinism(&messages, 10)?;
+    assert!(!report.is_determined());
+    assert!(report.to_string().contains("UNDER-CONSTRAINED"));
+
+    // A witness that does not satisfy the constraints cannot be tested.
+    let mut wrong = messages;
+    wrong.witnesses[0].assigned_variables.values.as_mut().unwrap()[0] = 10;
+    assert!(find_alternative_assignments(&wrong, 10).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,406 @@
+//! Compare two statements semantically, independently of how they are split into messages.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use num_bigint::BigUint;
+
+use crate::{Result, CircuitHeader, Variables, Messages};
+use crate::transform::renumber::renumber;
+
+type Var = u64;
+/// A linear combination normalized with `Variables::normalize`.
+type Lc = Vec<(Var, BigUint)>;
+/// A constraint A * B = C, normalized with A and B in canonical order.
+type ConstraintKey = (Lc, Lc, Lc);
+
+/// Differences between two statements, called left and right.
+#[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct StatementDiff {
+    /// Whether variables were renumbered canonically before comparing.
+    pub renumbered: bool,
+    pub header: Vec<HeaderDifference>,
+    pub instance_values: Vec<ValueDifference>,
+    pub constraints_left: u64,
+    pub constraints_right: u64,
+    /// Constraints found more times in the left statement than in the right.
+    pub only_in_left: Vec<DiffConstraint>,
+    /// Constraints found more times in the right statement than in the left.
+    pub only_in_right: Vec<DiffConstraint>,
+    pub witness_values: Vec<ValueDifference>,
+}
+
+/// A header field, with its value in each statement, or None if it is missing.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct HeaderDifference {
+    pub name: String,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+/// The value of a variable in each statement, or None if it is not assigned.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct ValueDifference {
+    pub id: Var,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+/// A normalized constraint A * B = C, with coefficients in decimal.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct DiffConstraint {
+    pub a: Vec<Term>,
+    pub b: Vec<Term>,
+    pub c: Vec<Term>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Term {
+    pub id: Var,
+    pub coefficient: String,
+}
+
+/// Compare two statements.
+///
+/// Constraints are compared as multisets, after normalization: terms are sorted by ID,
+/// duplicate IDs are merged, coefficients are reduced modulo the field and zero terms removed,
+/// and A and B are ordered. Values are compared modulo the field.
+///
+/// With `ignore_renumbering`, both statements are first renumbered with `transform::renumber`,
+/// so that statements that only differ by variable IDs are equal,
+/// provided that they use variables in the same order. Since witness variables are numbered
+/// in the order of their first use, statements with reordered constraints are reported as different.
+///
+/// # Example
+/// ```
+/// use zkinterface::Messages;
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::consumers::diff::diff;
+///
+/// let left = Messages {
+///     circuit_headers: vec![example_circuit_header()],
+///     constraint_systems: vec![example_constraints()],
+///     witnesses: vec![example_witness()],
+/// };
+/// let mut right = left.clone();
+/// right.constraint_systems[0].constraints.pop();
+///
+/// let diff = diff(&left, &right, false).unwrap();
+/// assert_eq!(diff.only_in_left.len(), 1);
+/// assert!(!diff.is_empty());
+/// ```
+pub fn diff(left: &Messages, right: &Messages, ignore_renumbering: bool) -> Result<StatementDiff> {
+    if ignore_renumbering {
+        let (left, _) = renumber(left)?;
+        let (right, _) = renumber(right)?;
+        return Ok(StatementDiff { renumbered: true, ..diff(&left, &right, false)? });
+    }
+
+    let left = Normalized::new(left);
+    let right = Normalized::new(right);
+
+    let mut header = vec![];
+    for ((name, left_value), (_, right_value)) in left.header_fields().into_iter().zip(right.header_fields()) {
+        if left_value != right_value {
+            header.push(HeaderDifference { name: name.to_string(), left: left_value, right: right_value });
+        }
+    }
+
+    let mut counts = BTreeMap::<&ConstraintKey, i64>::new();
+    for (key, count) in &left.constraints {
+        *counts.entry(key).or_default() += *count as i64;
+    }
+    for (key, count) in &right.constraints {
+        *counts.entry(key).or_default() -= *count as i64;
+    }
+    let mut only_in_left = vec![];
+    let mut only_in_right = vec![];
+    for (key, count) in counts {
+        let only_in = if count > 0 { &mut only_in_left } else { &mut only_in_right };
+        for _ in 0..count.abs() {
+            only_in.push(DiffConstraint::from(key));
+        }
+    }
+
+    Ok(StatementDiff {
+        renumbered: false,
+        header,
+        instance_values: diff_values(&left.instance_values, &right.instance_values),
+        constraints_left: left.num_constraints,
+        constraints_right: right.num_constraints,
+        only_in_left,
+        only_in_right,
+        witness_values: diff_values(&left.witness_values, &right.witness_values),
+    })
+}
+
+impl StatementDiff {
+    /// Whether the statements are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty() &&
+            self.instance_values.is_empty() &&
+            self.only_in_left.is_empty() &&
+            self.only_in_right.is_empty() &&
+            self.witness_values.is_empty()
+    }
+}
+
+/// How many differences of each kind are printed in the summary.
+const SUMMARY_LIMIT: usize = 10;
+
+impl fmt::Display for StatementDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.renumbered {
+            writeln!(f, "Variables were renumbered before comparing.")?;
+        }
+        writeln!(f, "Constraints: {} in left, {} in right.", self.constraints_left, self.constraints_right)?;
+
+        if !self.header.is_empty() {
+            writeln!(f, "Header differences:")?;
+            for difference in &self.header {
+                writeln!(f, "- {}: {} in left, {} in right", difference.name, show(&difference.left), show(&difference.right))?;
+            }
+        }
+
+        let lists = [
+            ("Instance values that differ", &self.instance_values),
+            ("Witness values that differ", &self.witness_values),
+        ];
+        for (title, values) in lists.iter() {
+            if values.is_empty() { continue; }
+            writeln!(f, "{}: {}", title, values.len())?;
+            for difference in values.iter().take(SUMMARY_LIMIT) {
+                writeln!(f, "- variable {}: {} in left, {} in right", difference.id, show(&difference.left), show(&difference.right))?;
+            }
+            write_more(f, values.len())?;
+        }
+
+        let lists = [
+            ("Constraints only in left", &self.only_in_left),
+            ("Constraints only in right", &self.only_in_right),
+        ];
+        for (title, constraints) in lists.iter() {
+            if constraints.is_empty() { continue; }
+            writeln!(f, "{}: {}", title, constraints.len())?;
+            for constraint in constraints.iter().take(SUMMARY_LIMIT) {
+                writeln!(f, "- {}", constraint)?;
+            }
+            write_more(f, constraints.len())?;
+        }
+
+        if self.is_empty() {
+            writeln!(f, "The statements are EQUIVALENT.")
+        } else {
+            writeln!(f, "The statements are DIFFERENT.")
+        }
+    }
+}
+
+fn show(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("missing")
+}
+
+fn write_more(f: &mut fmt::Formatter, count: usize) -> fmt::Result {
+    if count > SUMMARY_LIMIT {
+        writeln!(f, "- ... and {} more", count - SUMMARY_LIMIT)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for DiffConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}) * ({}) = ({})", show_terms(&self.a), show_terms(&self.b), show_terms(&self.c))
+    }
+}
+
+fn show_terms(terms: &[Term]) -> String {
+    if terms.is_empty() {
+        return "0".to_string();
+    }
+    terms.iter()
+        .map(|term| format!("{}*v{}", term.coefficient, term.id))
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+impl From<&ConstraintKey> for DiffConstraint {
+    fn from((a, b, c): &ConstraintKey) -> DiffConstraint {
+        let terms = |lc: &Lc| lc.iter()
+            .map(|(id, coeff)| Term { id: *id, coefficient: coeff.to_string() })
+            .collect();
+        DiffConstraint { a: terms(a), b: terms(b), c: terms(c) }
+    }
+}
+
+fn diff_values(left: &BTreeMap<Var, BigUint>, right: &BTreeMap<Var, BigUint>) -> Vec<ValueDifference> {
+    let ids: BTreeSet<Var> = left.keys().chain(right.keys()).cloned().collect();
+    ids.into_iter()
+        .filter(|id| left.get(id) != right.get(id))
+        .map(|id| ValueDifference {
+            id,
+            left: left.get(&id).map(BigUint::to_string),
+            right: right.get(&id).map(BigUint::to_string),
+        })
+        .collect()
+}
+
+/// The content of a statement, in a form that does not depend on its encoding.
+struct Normalized {
+    num_headers: usize,
+    header: Option<CircuitHeader>,
+    modulus: Option<BigUint>,
+    instance_values: BTreeMap<Var, BigUint>,
+    constraints: BTreeMap<ConstraintKey, u64>,
+    num_constraints: u64,
+    witness_values: BTreeMap<Var, BigUint>,
+}
+
+impl Normalized {
+    fn new(messages: &Messages) -> Normalized {
+        let header = messages.circuit_headers.first().cloned();
+        let modulus = header.as_ref().and_then(CircuitHeader::modulus);
+
+        let mut normalized = Normalized {
+            num_headers: messages.circuit_headers.len(),
+            header,
+            modulus,
+            instance_values: BTreeMap::new(),
+            constraints: BTreeMap::new(),
+            num_constraints: 0,
+            witness_values: BTreeMap::new(),
+        };
+
+        for header in &messages.circuit_headers {
+            let values = normalized.values(&header.instance_variables);
+            normalized.instance_values.extend(values);
+        }
+        for witness in &messages.witnesses {
+            let values = normalized.values(&witness.assigned_variables);
+            normalized.witness_values.extend(values);
+        }
+        for cs in &messages.constraint_systems {
+            for constraint in &cs.normalize(normalized.modulus.as_ref(), true).constraints {
+                let key = (
+                    lc(&constraint.linear_combination_a),
+                    lc(&constraint.linear_combination_b),
+                    lc(&constraint.linear_combination_c),
+                );
+                *normalized.constraints.entry(key).or_default() += 1;
+                normalized.num_constraints += 1;
+            }
+        }
+        normalized
+    }
+
+    fn header_fields(&self) -> Vec<(&'static str, Option<String>)> {
+        let header = self.header.as_ref();
+        vec![
+            ("headers", Some(self.num_headers.to_string())),
+            ("field_maximum", self.modulus.as_ref().map(|modulus| (modulus - 1u8).to_string())),
+            ("free_variable_id", header.map(|header| header.free_variable_id.to_string())),
+            ("instance_variables", header.map(|header| format!("{:?}", header.instance_variables.variable_ids))),
+        ]
+    }
+
+    fn values(&self, vars: &Variables) -> Vec<(Var, BigUint)> {
+        if vars.values.is_none() {
+            return vec![];
+        }
+        lc(&vars.normalize_values(self.modulus.as_ref()))
+    }
+}
+
+/// The terms of a normalized linear combination, or the values of normalized variables.
+fn lc(vars: &Variables) -> Lc {
+    vars.get_variables().iter()
+        .map(|var| (var.id, BigUint::from_bytes_le(var.value)))
+        .collect()
+}
+
+
+#[test]
+fn test_diff() -> Result<()> {
+    use crate::{ConstraintSystem, Witness};
+    use crate::producers::examples::*;
+
+    let left = Messages {
+        circuit_headers: vec![example_circuit_header()],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness()],
+    };
+    assert!(diff(&left, &left, false)?.is_empty());
+
+    // The same constraints in another order, split differently, and written differently.
+    let mut constraints = example_constraints().constraints;
+    constraints.reverse();
+    // y * y = yy  written as  y * (50y + 52y) = yy + 0*x
+    constraints[1].linear_combination_b = Variables { variable_ids: vec![2, 2], values: Some(vec![50, 52]), info: None };
+    constraints[1].linear_combination_c = Variables { variable_ids: vec![5, 1], values: Some(vec![1, 0]), info: None };
+    let right = Messages {
+        circuit_headers: left.circuit_headers.clone(),
+        constraint_systems: vec![
+            ConstraintSystem { constraints: constraints[..1].to_vec(), info: None },
+            ConstraintSystem { constraints: constraints[1..].to_vec(), info: None },
+        ],
+        witnesses: left.witnesses.clone(),
+    };
+    let same = diff(&left, &right, false)?;
+    assert!(same.is_empty(), "{}", same);
+    assert!(same.to_string().contains("EQUIVALENT"));
+
+    // Different constraints, witness, and header.
+    let mut right = right;
+    right.constraint_systems[0].constraints[0].linear_combination_c.values = Some(vec![2]);
+    right.witnesses[0].assigned_variables.values = Some(vec![
+        9, 0, 0, 0,
+        25, 0, 0, 0,
+    ]);
+    right.circuit_headers[0].free_variable_id = 7;
+
+    let different = diff(&left, &right, false)?;
+    assert_eq!(different.header, vec![HeaderDifference {
+        name: "free_variable_id".to_string(),
+        left: Some("6".to_string()),
+        right: Some("7".to_string()),
+    }]);
+    assert_eq!(different.only_in_left.len(), 1);
+    assert_eq!(different.only_in_right.len(), 1);
+    assert_eq!(different.only_in_right[0].to_string(), "(1*v0) * (1*v4 + 1*v5) = (2*v3)");
+    assert_eq!(different.witness_values, vec![ValueDifference {
+        id: 5,
+        left: Some("16".to_string()),
+        right: Some("25".to_string()),
+    }]);
+    assert!(different.to_string().contains("DIFFERENT"));
+
+    // The same statement with witness variables 4 and 5 swapped.
+    let swap = |vars: &Variables| Variables {
+        variable_ids: vars.variable_ids.iter().map(|id| match id { 4 => 5, 5 => 4, id => *id }).collect(),
+        ..vars.clone()
+    };
+    let swapped = Messages {
+        circuit_headers: left.circuit_headers.clone(),
+        constraint_systems: left.constraint_systems.iter().map(|cs| ConstraintSystem {
+            constraints: cs.constraints.iter().map(|constraint| crate::BilinearConstraint {
+                linear_combination_a: swap(&constraint.linear_combination_a),
+                linear_combination_b: swap(&constraint.linear_combination_b),
+                linear_combination_c: swap(&constraint.linear_combination_c),
+            }).collect(),
+            info: None,
+        }).collect(),
+        witnesses: left.witnesses.iter().map(|witness| Witness {
+            assigned_variables: swap(&witness.assigned_variables),
+        }).collect(),
+    };
+    assert!(!diff(&left, &swapped, false)?.is_empty());
+    let renumbered = diff(&left, &swapped, true)?;
+    assert!(renumbered.renumbered);
+    assert!(renumbered.is_empty(), "{}", renumbered);
+
+    // Renumbering depends on the order of constraints.
+    let mut reordered = swapped;
+    reordered.constraint_systems[0].constraints.swap(0, 1);
+    assert!(!diff(&left, &reordered, true)?.is_empty());
+
+    Ok(())
+}
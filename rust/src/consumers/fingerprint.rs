@@ -0,0 +1,210 @@
+//! Identify statements by hashes of their content, independently of how they are encoded and split into files.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use num_bigint::BigUint;
+
+use crate::{Result, CircuitHeader, ConstraintSystem, Witness, Variables, Message, Workspace};
+
+/// Hashes of a statement, in hexadecimal.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Fingerprint {
+    /// The field, the instance variable IDs, free_variable_id, and the constraints in order.
+    /// This identifies the setup of a proving system.
+    pub circuit: String,
+    /// The values of instance variables.
+    pub instance: String,
+    /// The values of witness variables.
+    pub witness: String,
+    pub num_constraints: u64,
+}
+
+/// Hash the statement in a workspace, reading one message at a time.
+///
+/// Messages are normalized first, see `transform::normalize`, so that the hashes do not depend
+/// on the encoding of values or on how constraints are split into messages.
+/// The order of constraints matters. The instance and witness values are sorted by ID within
+/// each message, then hashed message by message without buffering them, so the order of messages matters as well.
+///
+/// # Example
+/// ```
+/// use zkinterface::{Workspace, WorkspaceSink, Sink};
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::consumers::fingerprint::fingerprint;
+///
+/// let mut sink = WorkspaceSink::new("local/test_fingerprint_example").unwrap();
+/// sink.push_header(example_circuit_header()).unwrap();
+/// sink.push_witness(example_witness()).unwrap();
+/// sink.push_constraints(example_constraints()).unwrap();
+///
+/// let workspace = Workspace::from_dir("local/test_fingerprint_example".as_ref()).unwrap();
+/// let fingerprint = fingerprint(&workspace).unwrap();
+/// assert_eq!(fingerprint.num_constraints, 3);
+/// assert_eq!(fingerprint.circuit.len(), 64);
+/// ```
+pub fn fingerprint(workspace: &Workspace) -> Result<Fingerprint> {
+    let mut hasher = Fingerprinter::default();
+    for msg in workspace.try_iter_messages() {
+        match msg? {
+            Message::Header(header) => hasher.ingest_header(&header),
+            Message::ConstraintSystem(cs) => hasher.ingest_constraint_system(&cs),
+            Message::Witness(witness) => hasher.ingest_witness(&witness),
+            Message::Command(_) => {}
+            Message::Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(hasher.finish())
+}
+
+#[derive(Default)]
+struct Fingerprinter {
+    circuit: Sha256,
+    modulus: Option<BigUint>,
+    num_constraints: u64,
+    instance_values: ValueHasher,
+    witness_values: ValueHasher,
+}
+
+impl Fingerprinter {
+    fn ingest_header(&mut self, header: &CircuitHeader) {
+        if self.modulus.is_none() {
+            self.modulus = header.modulus();
+        }
+        let header = header.normalize();
+
+        self.circuit.update(b"header");
+        match &header.field_maximum {
+            Some(max) => update_bytes(&mut self.circuit, max),
+            None => update_bytes(&mut self.circuit, b""),
+        }
+        let ids = &header.instance_variables.variable_ids;
+        update_u64(&mut self.circuit, ids.len() as u64);
+        for id in ids {
+            update_u64(&mut self.circuit, *id);
+        }
+        update_u64(&mut self.circuit, header.free_variable_id);
+
+        self.instance_values.update(&header.instance_variables);
+    }
+
+    fn ingest_constraint_system(&mut self, cs: &ConstraintSystem) {
+        for constraint in &cs.normalize(self.modulus.as_ref(), false).constraints {
+            self.circuit.update(b"constraint");
+            update_lc(&mut self.circuit, &constraint.linear_combination_a);
+            update_lc(&mut self.circuit, &constraint.linear_combination_b);
+            update_lc(&mut self.circuit, &constraint.linear_combination_c);
+            self.num_constraints += 1;
+        }
+    }
+
+    fn ingest_witness(&mut self, witness: &Witness) {
+        self.witness_values.update(&witness.normalize(self.modulus.as_ref()).assigned_variables);
+    }
+
+    fn finish(self) -> Fingerprint {
+        Fingerprint {
+            circuit: format!("{:x}", self.circuit.finalize()),
+            instance: self.instance_values.finish(),
+            witness: self.witness_values.finish(),
+            num_constraints: self.num_constraints,
+        }
+    }
+}
+
+fn update_u64(hasher: &mut Sha256, value: u64) {
+    hasher.update(value.to_le_bytes());
+}
+
+fn update_bytes(hasher: &mut Sha256, bytes: &[u8]) {
+    update_u64(hasher, bytes.len() as u64);
+    hasher.update(bytes);
+}
+
+fn update_lc(hasher: &mut Sha256, lc: &Variables) {
+    let terms = lc.get_variables();
+    update_u64(hasher, terms.len() as u64);
+    for term in terms {
+        update_u64(hasher, term.id);
+        update_bytes(hasher, term.value);
+    }
+}
+
+/// Hash assigned values in the order they are read, whatever messages they come in.
+#[derive(Default)]
+struct ValueHasher {
+    hasher: Sha256,
+    count: u64,
+}
+
+impl ValueHasher {
+    /// Hash values without trailing zero bytes, so that they do not depend on the width of other values.
+    fn update(&mut self, vars: &Variables) {
+        if vars.values.is_none() { return; }
+        for var in vars.get_variables() {
+            update_u64(&mut self.hasher, var.id);
+            update_bytes(&mut self.hasher, &BigUint::from_bytes_le(var.value).to_bytes_le());
+            self.count += 1;
+        }
+    }
+
+    fn finish(mut self) -> String {
+        update_u64(&mut self.hasher, self.count);
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+
+#[test]
+fn test_fingerprint() -> Result<()> {
+    use std::fs::remove_dir_all;
+    use std::path::PathBuf;
+    use crate::{Messages, WorkspaceSink};
+    use crate::producers::examples::*;
+    use crate::transform::split::{split, SplitLimits};
+
+    let dir = PathBuf::from("local/test_fingerprint");
+    let _ = remove_dir_all(&dir);
+
+    let messages = Messages {
+        circuit_headers: vec![example_circuit_header()],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness()],
+    };
+    messages.push_into(&mut WorkspaceSink::new(dir.join("original"))?)?;
+    let original = fingerprint(&Workspace::from_dir(&dir.join("original"))?)?;
+    assert_eq!(original.num_constraints, 3);
+
+    // The same statement in more messages.
+    let limits = SplitLimits { max_items: Some(1), max_bytes: None };
+    split(&Workspace::from_dir(&dir.join("original"))?, &limits, WorkspaceSink::new_with_witness_files(dir.join("split"))?)?;
+    assert_eq!(fingerprint(&Workspace::from_dir(&dir.join("split"))?)?, original);
+
+    // Another witness for the same circuit.
+    let mut other = messages.clone();
+    other.witnesses = vec![example_witness_inputs(4, 3)];
+    other.push_into(&mut WorkspaceSink::new(dir.join("other_witness"))?)?;
+    let other_witness = fingerprint(&Workspace::from_dir(&dir.join("other_witness"))?)?;
+    assert_eq!(other_witness.circuit, original.circuit);
+    assert_eq!(other_witness.instance, original.instance);
+    assert_ne!(other_witness.witness, original.witness);
+
+    // The same witness values in messages in another order.
+    let mut reordered = messages.clone();
+    let (xx, yy) = (Variables { variable_ids: vec![4], values: Some(vec![9]), info: None },
+                    Variables { variable_ids: vec![5], values: Some(vec![16]), info: None });
+    reordered.witnesses = vec![Witness { assigned_variables: yy }, Witness { assigned_variables: xx }];
+    reordered.push_into(&mut WorkspaceSink::new(dir.join("reordered"))?)?;
+    let reordered = fingerprint(&Workspace::from_dir(&dir.join("reordered"))?)?;
+    assert_eq!(reordered.circuit, original.circuit);
+    assert_ne!(reordered.witness, original.witness);
+
+    // Another circuit.
+    let mut other = messages;
+    other.constraint_systems[0].constraints.swap(0, 1);
+    other.push_into(&mut WorkspaceSink::new(dir.join("other_circuit"))?)?;
+    let other_circuit = fingerprint(&Workspace::from_dir(&dir.join("other_circuit"))?)?;
+    assert_ne!(other_circuit.circuit, original.circuit);
+    assert_eq!(other_circuit.witness, original.witness);
+
+    Ok(())
+}
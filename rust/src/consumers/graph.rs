@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use num_bigint::BigUint;
+
+use crate::{Result, CircuitHeader, ConstraintSystem, Witness, Variables, Message};
+use crate::consumers::validator::Side;
+
+type Var = u64;
+
+/// Output formats of the graph.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GraphFormat {
+    /// Graphviz DOT.
+    Dot,
+    /// One JSON object per node or edge, on separate lines.
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    /// The constant one, variable 0.
+    Constant,
+    Instance,
+    Witness,
+    /// A constraint A * B = C, linking the variables of A and B to the variables of C.
+    Constraint,
+}
+
+/// A node or an edge of the graph.
+/// A node may be given again later with a better label.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum GraphElement {
+    Node { id: String, kind: NodeKind, label: String },
+    Edge { from: String, to: String, side: Side, coefficient: String },
+}
+
+/// GraphWriter writes the variables and constraints of a statement as a graph, message by message.
+///
+/// Variables are nodes styled by kind, and labeled with their names from `Variables.info` if any.
+/// Each constraint is a node with edges from the variables of A and B, and edges to the variables of C.
+///
+/// # Example
+/// ```
+/// use zkinterface::consumers::graph::{GraphWriter, GraphFormat};
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::Message;
+///
+/// let mut graph = GraphWriter::new(Vec::<u8>::new(), GraphFormat::Dot).unwrap();
+/// graph.ingest_message(&Message::Header(example_circuit_header())).unwrap();
+/// graph.ingest_message(&Message::ConstraintSystem(example_constraints())).unwrap();
+/// let dot = String::from_utf8(graph.finish().unwrap()).unwrap();
+///
+/// assert!(dot.starts_with("digraph zkif {"));
+/// assert!(dot.contains("v1 -> c1_0 [label=\"A\"]"));
+/// assert!(dot.contains("c1_0 -> v4 [label=\"C\"]"));
+/// ```
+pub struct GraphWriter<W: Write> {
+    writer: W,
+    format: GraphFormat,
+    instance_ids: HashSet<Var>,
+    /// Variables already written, and whether they were written with a name.
+    written: HashMap<Var, bool>,
+    message_index: u64,
+}
+
+impl<W: Write> GraphWriter<W> {
+    pub fn new(mut writer: W, format: GraphFormat) -> Result<Self> {
+        if format == GraphFormat::Dot {
+            writeln!(writer, "digraph zkif {{")?;
+            writeln!(writer, "  rankdir=LR;")?;
+        }
+        Ok(GraphWriter {
+            writer,
+            format,
+            instance_ids: HashSet::new(),
+            written: HashMap::new(),
+            message_index: 0,
+        })
+    }
+
+    /// Write the end of the graph, and return the inner writer.
+    pub fn finish(mut self) -> Result<W> {
+        if self.format == GraphFormat::Dot {
+            writeln!(self.writer, "}}")?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    pub fn ingest_message(&mut self, msg: &Message) -> Result<()> {
+        match msg {
+            Message::Header(h) => self.ingest_header(h)?,
+            Message::ConstraintSystem(cs) => self.ingest_constraint_system(cs)?,
+            Message::Witness(w) => self.ingest_witness(w)?,
+            Message::Command(_) => {}
+            Message::Err(_) => {}
+        }
+        self.message_index += 1;
+        Ok(())
+    }
+
+    pub fn ingest_header(&mut self, header: &CircuitHeader) -> Result<()> {
+        self.instance_ids.extend(header.instance_variables.variable_ids.iter().cloned());
+        self.write_variables(&header.instance_variables)
+    }
+
+    pub fn ingest_witness(&mut self, witness: &Witness) -> Result<()> {
+        self.write_variables(&witness.assigned_variables)
+    }
+
+    pub fn ingest_constraint_system(&mut self, system: &ConstraintSystem) -> Result<()> {
+        for (i, constraint) in system.constraints.iter().enumerate() {
+            let node = format!("c{}_{}", self.message_index, i);
+            self.write(GraphElement::Node {
+                id: node.clone(),
+                kind: NodeKind::Constraint,
+                label: format!("{}.{}", self.message_index, i),
+            })?;
+
+            for (side, terms) in &[
+                (Side::A, &constraint.linear_combination_a),
+                (Side::B, &constraint.linear_combination_b),
+                (Side::C, &constraint.linear_combination_c),
+            ] {
+                self.write_variables(terms)?;
+
+                for term in terms.get_variables() {
+                    let var = format!("v{}", term.id);
+                    let (from, to) = match side {
+                        Side::C => (node.clone(), var),
+                        _ => (var, node.clone()),
+                    };
+                    self.write(GraphElement::Edge {
+                        from,
+                        to,
+                        side: *side,
+                        coefficient: BigUint::from_bytes_le(term.value).to_string(),
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the nodes of variables not written yet, or that got a name.
+    fn write_variables(&mut self, variables: &Variables) -> Result<()> {
+        let names = variables.get_names().into_iter().collect::<HashMap<Var, &str>>();
+
+        for id in &variables.variable_ids {
+            let name = names.get(id);
+            match self.written.get(id) {
+                Some(true) => continue,
+                Some(false) if name.is_none() => continue,
+                _ => {}
+            }
+            self.written.insert(*id, name.is_some());
+
+            let kind = match *id {
+                0 => NodeKind::Constant,
+                _ if self.instance_ids.contains(id) => NodeKind::Instance,
+                _ => NodeKind::Witness,
+            };
+            let label = match name {
+                Some(name) => name.to_string(),
+                None if *id == 0 => "1".to_string(),
+                None => format!("v{}", id),
+            };
+            self.write(GraphElement::Node { id: format!("v{}", id), kind, label })?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, element: GraphElement) -> Result<()> {
+        match self.format {
+            GraphFormat::Json => {
+                serde_json::to_writer(&mut self.writer, &element)?;
+                writeln!(self.writer)?;
+            }
+            GraphFormat::Dot => match element {
+                GraphElement::Node { id, kind, label } => {
+                    let style = match kind {
+                        NodeKind::Constant => "shape=plaintext",
+                        NodeKind::Instance => "shape=box, style=filled, fillcolor=lightblue",
+                        NodeKind::Witness => "shape=ellipse",
+                        NodeKind::Constraint => "shape=circle, style=filled, fillcolor=lightgrey, fontsize=8",
+                    };
+                    writeln!(self.writer, "  {} [label=\"{}\", {}];", id, escape(&label), style)?;
+                }
+                GraphElement::Edge { from, to, side, coefficient } => {
+                    let label = match &coefficient[..] {
+                        "1" => format!("{:?}", side),
+                        _ => format!("{:?}: {}", side, coefficient),
+                    };
+                    writeln!(self.writer, "  {} -> {} [label=\"{}\"];", from, to, label)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+
+#[test]
+fn test_graph() -> Result<()> {
+    use crate::KeyValue;
+    use crate::producers::examples::*;
+
+    let mut header = example_circuit_header();
+    header.instance_variables.info = Some(vec![KeyValue::from(("3", "z\"z"))]);
+    let mut witness = example_witness();
+    witness.assigned_variables.info = Some(vec![KeyValue::from(("4", "xx"))]);
+
+    let messages = vec![
+        Message::Header(header),
+        Message::Witness(witness),
+        Message::ConstraintSystem(example_constraints()),
+    ];
+
+    let mut graph = GraphWriter::new(Vec::<u8>::new(), GraphFormat::Dot)?;
+    for msg in &messages {
+        graph.ingest_message(msg)?;
+    }
+    let dot = String::from_utf8(graph.finish()?)?;
+    assert!(dot.contains("  v3 [label=\"z\\\"z\", shape=box, style=filled, fillcolor=lightblue];\n"));
+    assert!(dot.contains("  v4 [label=\"xx\", shape=ellipse];\n"));
+    assert!(dot.contains("  v0 [label=\"1\", shape=plaintext];\n"));
+    assert!(dot.contains("  v0 -> c2_2 [label=\"A\"];\n"));
+    assert!(dot.contains("  c2_2 -> v3 [label=\"C\"];\n"));
+    assert!(dot.ends_with("}\n"));
+
+    let mut graph = GraphWriter::new(Vec::<u8>::new(), GraphFormat::Json)?;
+    for msg in &messages {
+        graph.ingest_message(msg)?;
+    }
+    let json = String::from_utf8(graph.finish()?)?;
+    let elements = json.lines()
+        .map(serde_json::from_str)
+        .collect::<std::result::Result<Vec<GraphElement>, _>>()?;
+    // 6 variables, 3 constraints, 10 terms.
+    assert_eq!(elements.len(), 6 + 3 + 10);
+    assert_eq!(elements[0], GraphElement::Node { id: "v1".into(), kind: NodeKind::Instance, label: "v1".into() });
+    assert_eq!(elements[18], GraphElement::Edge { from: "c2_2".into(), to: "v3".into(), side: Side::C, coefficient: "1".into() });
+
+    Ok(())
+}
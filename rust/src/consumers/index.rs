@@ -0,0 +1,395 @@
+//! Random access into workspace files, using an index stored next to each file.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use flatbuffers::SIZE_UOFFSET;
+
+use crate::zkinterface_generated::zkinterface as fb;
+use crate::consumers::reader::read_buffer;
+use crate::consumers::verifier::read_root;
+use crate::structs::constraints::BilinearConstraint;
+use crate::{Result, Message};
+
+/// The extension added to the name of a .zkif file to get the name of its index.
+pub const INDEX_EXTENSION: &str = "idx";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageType {
+    Header,
+    ConstraintSystem,
+    Witness,
+    Command,
+    /// A complete message that could not be verified.
+    Invalid,
+}
+
+/// Where a message is, and what it contains.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct MessageIndex {
+    /// Position of the message in the file, including its size prefix.
+    pub offset: u64,
+    pub size: u64,
+    pub message_type: MessageType,
+    /// Number of constraints in the messages before this one, in the same file.
+    pub first_constraint: u64,
+    pub num_constraints: u64,
+    /// The smallest and largest variable IDs used or defined in the message, if any.
+    pub min_variable_id: Option<u64>,
+    pub max_variable_id: Option<u64>,
+}
+
+impl MessageIndex {
+    /// Whether the message may define or use a variable.
+    pub fn may_contain_variable(&self, id: u64) -> bool {
+        match (self.min_variable_id, self.max_variable_id) {
+            (Some(min), Some(max)) => min <= id && id <= max,
+            _ => false,
+        }
+    }
+}
+
+/// The index of one .zkif file.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct FileIndex {
+    pub path: PathBuf,
+    /// The size and modification time of the file when it was indexed, to detect changes.
+    pub file_size: u64,
+    #[serde(default)]
+    pub modified: Option<Duration>,
+    pub messages: Vec<MessageIndex>,
+    pub num_constraints: u64,
+    /// Why the rest of the file after the last message could not be indexed, if any.
+    pub error: Option<String>,
+}
+
+/// The index of all files of a workspace, in the order of the workspace.
+///
+/// # Example
+/// ```
+/// use zkinterface::{Workspace, WorkspaceSink, Sink};
+/// use zkinterface::producers::examples::*;
+/// use std::path::PathBuf;
+///
+/// let dir = PathBuf::from("local/test_index");
+/// let mut sink = WorkspaceSink::new(&dir).unwrap();
+/// sink.push_header(example_circuit_header()).unwrap();
+/// sink.push_witness(example_witness()).unwrap();
+/// sink.push_constraints(example_constraints()).unwrap();
+///
+/// let ws = Workspace::from_dir(&dir).unwrap();
+/// ws.write_index().unwrap();
+///
+/// // Later, or in another program.
+/// let index = ws.read_index().unwrap();
+/// assert_eq!(index.num_constraints(), 3);
+///
+/// let constraint = ws.get_constraint(&index, 2).unwrap();
+/// assert_eq!(constraint.linear_combination_c.variable_ids, vec![3]);
+///
+/// let value = ws.get_variable_value(&index, 5).unwrap();
+/// assert_eq!(value, Some(vec![16, 0, 0, 0]));
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub struct WorkspaceIndex {
+    pub files: Vec<FileIndex>,
+}
+
+impl WorkspaceIndex {
+    pub fn build(paths: &[PathBuf]) -> Result<WorkspaceIndex> {
+        let files = paths.iter()
+            .map(|path| FileIndex::build(path))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(WorkspaceIndex { files })
+    }
+
+    /// Read the index of each file from its sidecar file.
+    /// Fails if an index is missing or if a file changed since it was indexed.
+    pub fn read(paths: &[PathBuf]) -> Result<WorkspaceIndex> {
+        let files = paths.iter()
+            .map(|path| FileIndex::read(path))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(WorkspaceIndex { files })
+    }
+
+    /// Write the index of each file into a sidecar file next to it.
+    pub fn write(&self) -> Result<()> {
+        for file in &self.files {
+            file.write()?;
+        }
+        Ok(())
+    }
+
+    pub fn num_constraints(&self) -> u64 {
+        self.files.iter().map(|file| file.num_constraints).sum()
+    }
+
+    /// Find a constraint, counting constraints of all files in order.
+    /// Returns the file, the message, and the index of the constraint within the message.
+    pub fn locate_constraint(&self, mut constraint_index: u64) -> Option<(&FileIndex, &MessageIndex, u64)> {
+        for file in &self.files {
+            if constraint_index < file.num_constraints {
+                let message = file.messages.iter().find(|msg|
+                    msg.first_constraint <= constraint_index
+                        && constraint_index < msg.first_constraint + msg.num_constraints)?;
+                return Some((file, message, constraint_index - message.first_constraint));
+            }
+            constraint_index -= file.num_constraints;
+        }
+        None
+    }
+
+    /// The header and witness messages which may define a variable, according to their ranges of IDs.
+    pub fn locate_variable(&self, id: u64) -> impl Iterator<Item=(&FileIndex, &MessageIndex)> {
+        self.files.iter().flat_map(move |file|
+            file.messages.iter()
+                .filter(move |msg| match msg.message_type {
+                    MessageType::Header | MessageType::Witness => msg.may_contain_variable(id),
+                    _ => false,
+                })
+                .map(move |msg| (file, msg)))
+    }
+
+    /// Read the constraint at the given index, counting constraints of all files in order.
+    pub fn read_constraint(&self, constraint_index: u64) -> Result<BilinearConstraint> {
+        let (file, message, index_in_message) = self.locate_constraint(constraint_index)
+            .ok_or_else(|| format!("No constraint {} in a workspace of {} constraints", constraint_index, self.num_constraints()))?;
+
+        match file.read_message(message)? {
+            Message::ConstraintSystem(mut cs) => {
+                let num_constraints = cs.constraints.len();
+                if (index_in_message as usize) < num_constraints {
+                    Ok(cs.constraints.swap_remove(index_in_message as usize))
+                } else {
+                    Err(format!("Expected at least {} constraints at offset {} of {}, found {}; the index may be out of date",
+                                index_in_message + 1, message.offset, file.path.display(), num_constraints).into())
+                }
+            }
+            _ => Err(format!("Expected a constraint system at offset {} of {}", message.offset, file.path.display()).into()),
+        }
+    }
+
+    /// Read the value of an instance or witness variable, or None if no value is found.
+    pub fn read_variable_value(&self, id: u64) -> Result<Option<Vec<u8>>> {
+        for (file, message) in self.locate_variable(id) {
+            let variables = match file.read_message(message)? {
+                Message::Header(header) => header.instance_variables,
+                Message::Witness(witness) => witness.assigned_variables,
+                _ => continue,
+            };
+            if let Some(var) = variables.get_variables().iter().find(|var| var.id == id) {
+                return Ok(Some(var.value.to_vec()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl FileIndex {
+    /// Read a file message by message, and record what each one contains.
+    pub fn build(path: &Path) -> Result<FileIndex> {
+        let file = File::open(path)
+            .map_err(|err| format!("Error opening workspace file {}: {}", path.display(), err))?;
+        let metadata = file.metadata()?;
+        let mut reader = BufReader::new(file);
+
+        let mut index = FileIndex {
+            path: path.to_path_buf(),
+            file_size: metadata.len(),
+            modified: modified_time(&metadata),
+            messages: vec![],
+            num_constraints: 0,
+            error: None,
+        };
+        let mut offset = 0;
+
+        loop {
+            let buffer = match read_buffer(&mut reader) {
+                Ok(buffer) => buffer,
+                Err(err) => {
+                    index.error = Some(err.to_string());
+                    break;
+                }
+            };
+            if buffer.len() <= SIZE_UOFFSET { break; }
+
+            let message = index_message(&buffer, offset, index.num_constraints);
+            index.num_constraints += message.num_constraints;
+            offset += message.size;
+            index.messages.push(message);
+        }
+        Ok(index)
+    }
+
+    /// The path of the sidecar file of a .zkif file.
+    pub fn sidecar_path(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(".");
+        name.push(INDEX_EXTENSION);
+        PathBuf::from(name)
+    }
+
+    pub fn read(path: &Path) -> Result<FileIndex> {
+        let sidecar = Self::sidecar_path(path);
+        let file = File::open(&sidecar)
+            .map_err(|err| format!("Cannot open the index {} ({}), create it with `zkif index`", sidecar.display(), err))?;
+        let index: FileIndex = serde_json::from_reader(BufReader::new(file))?;
+
+        let metadata = std::fs::metadata(path)?;
+        if index.file_size != metadata.len() || index.modified != modified_time(&metadata) {
+            return Err(format!("The index {} is out of date, update it with `zkif index`", sidecar.display()).into());
+        }
+        Ok(FileIndex { path: path.to_path_buf(), ..index })
+    }
+
+    pub fn write(&self) -> Result<()> {
+        let file = File::create(Self::sidecar_path(&self.path))?;
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+
+    /// Read and convert a single message of the file.
+    pub fn read_message(&self, message: &MessageIndex) -> Result<Message> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(message.offset))?;
+        let buffer = read_buffer(file.take(message.size))?;
+        match Message::from(&buffer[..]) {
+            Message::Err(err) => Err(err.into()),
+            msg => Ok(msg),
+        }
+    }
+}
+
+/// The modification time of a file, if the platform provides it.
+fn modified_time(metadata: &std::fs::Metadata) -> Option<Duration> {
+    metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()
+}
+
+fn index_message(buffer: &[u8], offset: u64, first_constraint: u64) -> MessageIndex {
+    let mut index = MessageIndex {
+        offset,
+        size: buffer.len() as u64,
+        message_type: MessageType::Invalid,
+        first_constraint,
+        num_constraints: 0,
+        min_variable_id: None,
+        max_variable_id: None,
+    };
+
+    let root = match read_root(buffer) {
+        Ok(root) => root,
+        Err(_) => return index,
+    };
+
+    let mut ids = vec![];
+    match root.message_type() {
+        fb::Message::CircuitHeader => {
+            index.message_type = MessageType::Header;
+            if let Some(header) = root.message_as_circuit_header() {
+                ids.push(header.instance_variables().and_then(|vars| vars.variable_ids()));
+            }
+        }
+        fb::Message::ConstraintSystem => {
+            index.message_type = MessageType::ConstraintSystem;
+            if let Some(constraints) = root.message_as_constraint_system().and_then(|cs| cs.constraints()) {
+                index.num_constraints = constraints.len() as u64;
+                for i in 0..constraints.len() {
+                    let constraint = constraints.get(i);
+                    ids.push(constraint.linear_combination_a().and_then(|vars| vars.variable_ids()));
+                    ids.push(constraint.linear_combination_b().and_then(|vars| vars.variable_ids()));
+                    ids.push(constraint.linear_combination_c().and_then(|vars| vars.variable_ids()));
+                }
+            }
+        }
+        fb::Message::Witness => {
+            index.message_type = MessageType::Witness;
+            if let Some(witness) = root.message_as_witness() {
+                ids.push(witness.assigned_variables().and_then(|vars| vars.variable_ids()));
+            }
+        }
+        fb::Message::Command => index.message_type = MessageType::Command,
+        fb::Message::NONE => {}
+    }
+
+    for id in ids.into_iter().flatten().flat_map(|ids| ids.safe_slice()) {
+        index.min_variable_id = Some(index.min_variable_id.map_or(*id, |min| min.min(*id)));
+        index.max_variable_id = Some(index.max_variable_id.map_or(*id, |max| max.max(*id)));
+    }
+    index
+}
+
+
+#[test]
+fn test_index() -> Result<()> {
+    use std::fs::{remove_dir_all, OpenOptions};
+    use std::io::Write;
+    use crate::{Workspace, WorkspaceSink, Sink, Witness, Variables};
+    use crate::producers::examples::*;
+
+    let dir = PathBuf::from("local/test_index_files");
+    let _ = remove_dir_all(&dir);
+    let mut sink = WorkspaceSink::new(&dir)?;
+    sink.push_header(example_circuit_header())?;
+    sink.push_witness(example_witness())?;
+    sink.push_witness(Witness {
+        assigned_variables: Variables {
+            variable_ids: vec![10, 12],
+            values: Some(serialize_small(&[7, 8])),
+            info: None,
+        }
+    })?;
+    sink.push_constraints(example_constraints())?;
+    sink.push_constraints(example_constraints())?;
+
+    let ws = Workspace::from_dir(&dir)?;
+    assert!(ws.read_index().is_err());
+    let index = ws.write_index()?;
+    assert_eq!(ws.read_index()?, index);
+
+    assert_eq!(index.files.len(), 4);
+    let witness_file = &index.files[1];
+    assert_eq!(witness_file.messages.len(), 2);
+    assert_eq!(witness_file.messages[1].message_type, MessageType::Witness);
+    assert_eq!(witness_file.messages[1].offset, witness_file.messages[0].size);
+    assert_eq!((witness_file.messages[1].min_variable_id, witness_file.messages[1].max_variable_id), (Some(10), Some(12)));
+    assert_eq!(index.files[3].messages[0].min_variable_id, Some(0));
+
+    assert_eq!(index.num_constraints(), 6);
+    let (file, message, i) = index.locate_constraint(4).unwrap();
+    assert_eq!((file.path.file_name().unwrap().to_str(), message.first_constraint, i), (Some("constraints_1.zkif"), 0, 1));
+    assert_eq!(ws.get_constraint(&index, 4)?, example_constraints().constraints[1]);
+    assert!(ws.get_constraint(&index, 6).is_err());
+
+    // An index that does not match the file gives an error.
+    let mut tampered = index.clone();
+    tampered.files[3].messages[0].num_constraints = 5;
+    tampered.files[3].num_constraints = 5;
+    assert!(tampered.read_constraint(7).is_err());
+
+    assert_eq!(ws.get_variable_value(&index, 2)?, Some(vec![4, 0, 0, 0]));
+    assert_eq!(ws.get_variable_value(&index, 12)?, Some(vec![8, 0, 0, 0]));
+    assert_eq!(ws.get_variable_value(&index, 11)?, None);
+    assert_eq!(index.locate_variable(11).count(), 1);
+
+    // The index is out of date after a change of the file, even of the same size.
+    let file = OpenOptions::new().write(true).open(dir.join("header.zkif"))?;
+    file.set_modified(UNIX_EPOCH + Duration::from_secs(1))?;
+    drop(file);
+    assert!(ws.read_index().is_err());
+    ws.write_index()?;
+
+    let mut file = OpenOptions::new().append(true).open(dir.join("witness.zkif"))?;
+    file.write_all(&[100, 0, 0, 0])?;
+    drop(file);
+    assert!(ws.read_index().is_err());
+
+    // A truncated message is recorded as an error.
+    let index = ws.write_index()?;
+    assert_eq!(index.files[1].messages.len(), 2);
+    assert!(index.files[1].error.is_some());
+
+    Ok(())
+}
@@ -0,0 +1,279 @@
+//! Read workspaces through memory maps, without copying messages.
+
+use flatbuffers::SIZE_UOFFSET;
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::zkinterface_generated::zkinterface as fb;
+use crate::consumers::reader::read_size_prefix;
+use crate::consumers::verifier::{read_root, is_aligned, verify_size_prefix, VerifierOptions};
+use crate::consumers::workspace::{order_workspace_files, sort_workspace_files, ReadError};
+use crate::{Result, Message};
+
+/// MappedWorkspace reads zkInterface messages from memory-mapped files.
+///
+/// The files are indexed once when opened, by following the size prefixes of messages.
+/// Messages are then read in place: `iter_roots` gives borrowed FlatBuffers views,
+/// and `iter_messages` converts one message at a time into owned structures.
+/// Only messages at a position in a file that is not aligned to `verifier::MESSAGE_ALIGN`, which follow
+/// a message whose size is not a multiple of 8, are copied when the files are indexed,
+/// because FlatBuffers cannot read them in place.
+///
+/// The files must not be modified while they are mapped.
+///
+/// # Example
+/// ```
+/// use zkinterface::{WorkspaceSink, Sink};
+/// use zkinterface::consumers::mapped::MappedWorkspace;
+/// use zkinterface::producers::examples::*;
+/// use std::path::PathBuf;
+///
+/// let dir = PathBuf::from("local/test_mapped_workspace");
+/// let mut sink = WorkspaceSink::new(&dir).unwrap();
+/// sink.push_header(example_circuit_header()).unwrap();
+/// sink.push_witness(example_witness()).unwrap();
+/// sink.push_constraints(example_constraints()).unwrap();
+///
+/// let ws = MappedWorkspace::from_dir(&dir).unwrap();
+/// assert_eq!(ws.len(), 3);
+///
+/// let header = ws.iter_roots().next().unwrap().unwrap();
+/// assert_eq!(header.message_as_circuit_header().unwrap().free_variable_id(), 6);
+/// ```
+pub struct MappedWorkspace {
+    files: Vec<MappedFile>,
+    warnings: Vec<String>,
+}
+
+struct MappedFile {
+    path: PathBuf,
+    /// None for empty files, which cannot be mapped.
+    mmap: Option<Mmap>,
+    /// The position of each message in the file, including its size prefix.
+    messages: Vec<Range<usize>>,
+    /// Aligned copies of the messages at unaligned positions, by index in `messages`.
+    copies: HashMap<usize, Vec<u8>>,
+    /// Why the rest of the file after the last message could not be read, if any.
+    error: Option<String>,
+}
+
+impl MappedWorkspace {
+    pub fn from_dir(path: &Path) -> Result<Self> {
+        Self::from_dirs_and_files(&[path.to_path_buf()])
+    }
+
+    /// Find the files in directories, or read them from a manifest; see `order_workspace_files`.
+    pub fn from_dirs_and_files(paths: &[PathBuf]) -> Result<Self> {
+        let (paths, warnings) = order_workspace_files(paths)?;
+        Ok(MappedWorkspace { warnings, ..Self::from_ordered_filenames(paths)? })
+    }
+
+    /// Map and index the given files, in the same order as `Workspace`.
+    pub fn from_filenames(mut paths: Vec<PathBuf>) -> Result<Self> {
+        Self::check_not_stdin(&paths)?;
+        let warnings = sort_workspace_files(&mut paths);
+        Ok(MappedWorkspace { warnings, ..Self::from_ordered_filenames(paths)? })
+    }
+
+    /// Map and index the given files, in the given order.
+    pub fn from_ordered_filenames(paths: Vec<PathBuf>) -> Result<Self> {
+        Self::check_not_stdin(&paths)?;
+        let files = paths.into_iter()
+            .map(MappedFile::open)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(MappedWorkspace { files, warnings: vec![] })
+    }
+
+    fn check_not_stdin(paths: &[PathBuf]) -> Result<()> {
+        if paths.iter().any(|path| path == Path::new("-")) {
+            return Err("Cannot map stdin, read it as a stream instead".into());
+        }
+        Ok(())
+    }
+
+    /// The number of messages found in all files.
+    pub fn len(&self) -> usize {
+        self.files.iter().map(|file| file.messages.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Warnings about the files found, such as several files starting with a header.
+    /// Only the first message of each file is looked at; see `sort_workspace_files`.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Iterate over the size-prefixed messages, in place, or their copies for unaligned messages.
+    /// A file that ends with an incomplete message gives an error after its complete messages.
+    pub fn iter_buffers(&self) -> impl Iterator<Item=Result<&[u8]>> {
+        self.iter_located().map(|buffer| match buffer {
+            Ok((_, _, data)) => Ok(data),
+            Err(err) => Err(err.into()),
+        })
+    }
+
+    /// Iterate over the verified messages, as FlatBuffers views into the files.
+    pub fn iter_roots(&self) -> impl Iterator<Item=Result<fb::Root<'_>>> {
+        self.iter_buffers().map(|buffer| read_root(buffer?))
+    }
+
+    /// Iterate over the messages converted to owned structures, one at a time.
+    /// Errors are converted to `Message::Err`.
+    pub fn iter_messages(&self) -> impl Iterator<Item=Message> + '_ {
+        self.try_iter_messages().map(|msg|
+            msg.unwrap_or_else(|err| Message::Err(err.to_string())))
+    }
+
+    /// Iterate over the messages converted to owned structures, or errors with their position.
+    pub fn try_iter_messages(&self) -> impl Iterator<Item=std::result::Result<Message, ReadError>> + '_ {
+        self.iter_located().map(|buffer| {
+            let (file, offset, data) = buffer?;
+            read_root(data).map(Message::from).map_err(|err| ReadError {
+                path: Some(file.path.clone()),
+                offset,
+                invalid_message: true,
+                reason: err.to_string(),
+            })
+        })
+    }
+
+    /// Iterate over the files, offsets, and content of messages.
+    fn iter_located(&self) -> impl Iterator<Item=std::result::Result<(&MappedFile, u64, &[u8]), ReadError>> {
+        self.files.iter().flat_map(|file| {
+            let data: &[u8] = file.mmap.as_deref().unwrap_or(&[]);
+            let buffers = file.messages.iter().enumerate()
+                .map(move |(i, range)| {
+                    let buffer = file.copies.get(&i).map_or(&data[range.clone()], |copy| &copy[..]);
+                    Ok((file, range.start as u64, buffer))
+                });
+            let end = file.messages.last().map_or(0, |range| range.end as u64);
+            let error = file.error.iter()
+                .map(move |err| Err(ReadError {
+                    path: Some(file.path.clone()),
+                    offset: end,
+                    invalid_message: false,
+                    reason: err.clone(),
+                }));
+            buffers.chain(error)
+        })
+    }
+}
+
+impl MappedFile {
+    fn open(path: PathBuf) -> Result<MappedFile> {
+        let file = File::open(&path)
+            .map_err(|err| format!("Error opening workspace file {}: {}", path.display(), err))?;
+
+        let mmap = if file.metadata()?.len() == 0 {
+            None
+        } else {
+            // Safety: the files must not be modified while they are mapped, as documented above.
+            Some(unsafe { Mmap::map(&file)? })
+        };
+
+        let mut mapped = MappedFile { path, mmap, messages: vec![], copies: HashMap::new(), error: None };
+        mapped.index();
+        Ok(mapped)
+    }
+
+    /// Find the messages from their size prefixes.
+    fn index(&mut self) {
+        let data: &[u8] = self.mmap.as_deref().unwrap_or(&[]);
+        let options = VerifierOptions::default();
+        let mut offset = 0;
+
+        while offset < data.len() {
+            let rest = &data[offset..];
+            // Explicit size 0 as end marker.
+            if read_size_prefix(rest) == SIZE_UOFFSET { break; }
+
+            match verify_size_prefix(rest, &options) {
+                Ok(size) => {
+                    if !is_aligned(rest) {
+                        self.copies.insert(self.messages.len(), rest[..size].to_vec());
+                    }
+                    self.messages.push(offset..offset + size);
+                    offset += size;
+                }
+                Err(err) => {
+                    self.error = Some(err.to_string());
+                    break;
+                }
+            }
+        }
+    }
+}
+
+
+#[test]
+fn test_mapped_workspace() -> Result<()> {
+    use std::fs::{remove_dir_all, OpenOptions};
+    use std::io::Write;
+    use crate::{Workspace, WorkspaceSink, Sink};
+    use crate::producers::examples::*;
+
+    let dir = PathBuf::from("local/test_mapped");
+    let _ = remove_dir_all(&dir);
+    let mut sink = WorkspaceSink::new(&dir)?;
+    sink.push_header(example_circuit_header())?;
+    sink.push_witness(example_witness())?;
+    sink.push_witness(example_witness())?;
+    sink.push_constraints(example_constraints())?;
+    File::create(dir.join("empty.zkif"))?;
+
+    let ws = MappedWorkspace::from_dir(&dir)?;
+    assert_eq!(ws.len(), 4);
+    assert_eq!(
+        ws.iter_messages().collect::<Vec<_>>(),
+        Workspace::from_dir(&dir)?.iter_messages().collect::<Vec<_>>());
+
+    let roots = ws.iter_roots().collect::<Result<Vec<_>>>()?;
+    assert!(roots[1].message_as_witness().is_some());
+    assert!(roots[3].message_as_constraint_system().is_some());
+
+    // A truncated message at the end of a file.
+    let mut file = OpenOptions::new().append(true).open(dir.join("witness.zkif"))?;
+    file.write_all(&[100, 0, 0, 0, 1, 2, 3])?;
+    drop(file);
+
+    let ws = MappedWorkspace::from_dir(&dir)?;
+    assert_eq!(ws.len(), 4);
+    let messages = ws.iter_messages().collect::<Vec<_>>();
+    assert_eq!(messages.len(), 5);
+    match &messages[3] {
+        Message::Err(err) => assert!(err.contains("truncated message"), "{}", err),
+        other => panic!("Expected an error, got {:?}", other),
+    }
+    assert!(matches!(messages[4], Message::ConstraintSystem(_)));
+
+    assert!(MappedWorkspace::from_filenames(vec![PathBuf::from("-")]).is_err());
+
+    // A message at an unaligned position, after a command whose size is not a multiple of 8.
+    let unaligned = dir.join("unaligned.zkif");
+    let mut file = File::create(&unaligned)?;
+    let command = crate::Command {
+        constraints_generation: true,
+        witness_generation: false,
+        parameters: Some(vec![crate::KeyValue::from(("name", "x"))]),
+    };
+    let mut command_bytes = vec![];
+    command.write_into(&mut command_bytes)?;
+    assert_eq!(command_bytes.len() % 8, 4);
+    file.write_all(&command_bytes)?;
+    example_constraints().write_into(&mut file)?;
+    drop(file);
+
+    let ws = MappedWorkspace::from_filenames(vec![unaligned])?;
+    let roots = ws.iter_roots().collect::<Result<Vec<_>>>()?;
+    assert_eq!(roots[1].message_as_constraint_system().unwrap().constraints().unwrap().len(), 3);
+    assert_eq!(ws.iter_messages().nth(1), Some(Message::ConstraintSystem(example_constraints())));
+
+    Ok(())
+}
@@ -3,3 +3,12 @@ pub mod simulator;
 pub mod stats;
 pub mod reader;
 pub mod workspace;
+pub mod verifier;
+pub mod graph;
+pub mod parallel;
+pub mod mapped;
+pub mod index;
+pub mod diff;
+pub mod fingerprint;
+pub mod solver;
+pub mod determinism;
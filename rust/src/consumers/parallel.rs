@@ -0,0 +1,184 @@
+use rayon::prelude::*;
+use std::iter;
+
+use crate::{Message, ConstraintSystem};
+use crate::structs::constraints::BilinearConstraint;
+
+/// Number of constraints checked in one task.
+pub const CHUNK_SIZE: usize = 1 << 12;
+
+/// Number of constraints read into memory before checking them all in parallel.
+pub const BATCH_SIZE: usize = 1 << 20;
+
+/// Either a message to ingest on its own, or consecutive ConstraintSystem messages to check in parallel.
+#[derive(Clone, Debug)]
+pub enum Batch {
+    Message(Message),
+    ConstraintSystems(Vec<ConstraintSystem>),
+}
+
+/// Group consecutive ConstraintSystem messages into batches of about BATCH_SIZE constraints.
+/// Other messages are passed through one by one, so the order of messages is preserved.
+///
+/// # Example
+/// ```
+/// use zkinterface::{Workspace, WorkspaceSink, Sink};
+/// use zkinterface::consumers::parallel::batch_messages;
+/// use zkinterface::consumers::validator::Validator;
+/// use zkinterface::consumers::simulator::Simulator;
+/// use zkinterface::producers::examples::*;
+/// use std::path::PathBuf;
+///
+/// let dir = PathBuf::from("local/test_parallel");
+/// let mut sink = WorkspaceSink::new(&dir).unwrap();
+/// sink.push_header(example_circuit_header()).unwrap();
+/// sink.push_witness(example_witness()).unwrap();
+/// sink.push_constraints(example_constraints()).unwrap();
+///
+/// let mut validator = Validator::new_as_prover();
+/// let mut simulator = Simulator::default();
+///
+/// let ws = Workspace::from_dir(&dir).unwrap();
+/// for batch in batch_messages(ws.iter_messages()) {
+///     validator.ingest_batch(&batch);
+///     simulator.ingest_batch(&batch);
+/// }
+///
+/// assert_eq!(validator.get_violations(), Vec::<String>::new());
+/// assert_eq!(simulator.get_violations(), Vec::<String>::new());
+/// ```
+pub fn batch_messages(messages: impl Iterator<Item=Message>) -> impl Iterator<Item=Batch> {
+    let mut messages = messages.peekable();
+
+    iter::from_fn(move || {
+        let first = match messages.next()? {
+            Message::ConstraintSystem(cs) => cs,
+            other => return Some(Batch::Message(other)),
+        };
+
+        let mut size = first.constraints.len();
+        let mut systems = vec![first];
+        while size < BATCH_SIZE {
+            match messages.next_if(|msg| matches!(msg, Message::ConstraintSystem(_))) {
+                Some(Message::ConstraintSystem(cs)) => {
+                    size += cs.constraints.len();
+                    systems.push(cs);
+                }
+                _ => break,
+            }
+        }
+        Some(Batch::ConstraintSystems(systems))
+    })
+}
+
+/// Split the constraints of each system into chunks, and call `check` on all chunks in parallel.
+/// `check` receives the index of the system, the index of the first constraint of the chunk, and the chunk.
+/// The results are grouped by system, in order.
+pub(crate) fn map_chunks<T, F>(systems: &[ConstraintSystem], check: F) -> Vec<Vec<T>>
+    where T: Send,
+          F: Fn(usize, u64, &[BilinearConstraint]) -> T + Sync {
+    let mut tasks = vec![];
+    for (i, system) in systems.iter().enumerate() {
+        for (c, chunk) in system.constraints.chunks(CHUNK_SIZE).enumerate() {
+            tasks.push((i, (c * CHUNK_SIZE) as u64, chunk));
+        }
+    }
+
+    let results = tasks.par_iter()
+        .map(|(i, first_index, chunk)| check(*i, *first_index, chunk))
+        .collect::<Vec<T>>();
+
+    let mut grouped = systems.iter().map(|_| vec![]).collect::<Vec<Vec<T>>>();
+    for ((i, _, _), result) in tasks.iter().zip(results) {
+        grouped[*i].push(result);
+    }
+    grouped
+}
+
+
+#[test]
+fn test_parallel() -> crate::Result<()> {
+    use crate::{CircuitHeader, Witness, Variables};
+    use crate::consumers::validator::Validator;
+    use crate::consumers::simulator::Simulator;
+    use crate::producers::examples::serialize_small;
+
+    // Constraints x_i * 1 = y_i, some of them not satisfied, split into chunks and messages.
+    let n = 3 * CHUNK_SIZE as u64;
+    let header = CircuitHeader {
+        free_variable_id: 2 * n + 1,
+        field_maximum: Some(vec![100]),
+        ..CircuitHeader::default()
+    };
+    let ids = (1..=2 * n).collect::<Vec<u64>>();
+    let values = ids.iter().map(|id| match id {
+        // Unsatisfied constraints in the first and second chunks, and in the last message.
+        _ if *id == n + 2 || *id == n + CHUNK_SIZE as u64 + 1 || *id == 2 * n => 99,
+        _ if *id > n => (*id - n) % 100,
+        _ => *id % 100,
+    } as u32).collect::<Vec<u32>>();
+    let witness = Witness {
+        assigned_variables: Variables {
+            variable_ids: ids,
+            values: Some(serialize_small(&values)),
+            info: None,
+        }
+    };
+    let constraint = |i: u64| BilinearConstraint {
+        linear_combination_a: Variables { variable_ids: vec![i], values: Some(vec![1]), info: None },
+        linear_combination_b: Variables { variable_ids: vec![0], values: Some(vec![1]), info: None },
+        linear_combination_c: Variables { variable_ids: vec![n + i], values: Some(vec![1]), info: None },
+    };
+    let mut first = ConstraintSystem { constraints: (1..n).map(constraint).collect(), info: None };
+    // An undefined variable used in two chunks, and an out-of-field coefficient.
+    // The simulator stops at the undefined variable.
+    first.constraints[CHUNK_SIZE + 1].linear_combination_b.variable_ids = vec![2 * n + 5];
+    first.constraints[2 * CHUNK_SIZE + 1].linear_combination_b.variable_ids = vec![2 * n + 5];
+    first.constraints[CHUNK_SIZE + 2].linear_combination_b.values = Some(vec![200]);
+    let second = ConstraintSystem { constraints: vec![constraint(n)], info: None };
+
+    let messages = vec![
+        Message::Header(header),
+        Message::Witness(witness),
+        Message::ConstraintSystem(first),
+        Message::ConstraintSystem(ConstraintSystem::default()),
+        Message::ConstraintSystem(second),
+    ];
+
+    // Check in one thread, and in parallel.
+    let mut validator = Validator::new_as_prover();
+    let mut simulator = Simulator::new_reporting_all();
+    let mut first_error = Simulator::default();
+    for msg in &messages {
+        validator.ingest_message(msg);
+        simulator.ingest_message(msg);
+        first_error.ingest_message(msg);
+    }
+
+    let mut par_validator = Validator::new_as_prover();
+    let mut par_simulator = Simulator::new_reporting_all();
+    let mut par_first_error = Simulator::default();
+    let batches = batch_messages(messages.into_iter()).collect::<Vec<Batch>>();
+    assert_eq!(batches.len(), 3);
+    for batch in &batches {
+        par_validator.ingest_batch(batch);
+        par_simulator.ingest_batch(batch);
+        par_first_error.ingest_batch(batch);
+    }
+
+    let reports = validator.get_violation_reports();
+    assert_eq!(reports.len(), 3);
+    assert_eq!(par_validator.get_violation_reports(), reports);
+
+    let unsatisfied = simulator.get_unsatisfied_constraints();
+    let locations = unsatisfied.iter()
+        .map(|u| (u.message_index, u.constraint_index))
+        .collect::<Vec<_>>();
+    assert_eq!(locations, vec![(2, 1), (2, CHUNK_SIZE as u64)]);
+    assert_eq!(par_simulator.get_unsatisfied_constraints(), unsatisfied);
+    assert_eq!(par_simulator.get_violations(), simulator.get_violations());
+
+    assert_eq!(par_first_error.get_violations(), first_error.get_violations());
+
+    Ok(())
+}
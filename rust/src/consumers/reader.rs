@@ -1,6 +1,6 @@
 //! Helpers to read messages.
 
-use flatbuffers::{read_scalar_at, SIZE_UOFFSET, UOffsetT};
+use flatbuffers::SIZE_UOFFSET;
 use std::collections::HashMap;
 use std::fmt;
 use std::fs::File;
@@ -8,15 +8,16 @@ use std::io::Read;
 use std::path::Path;
 
 use crate::zkinterface_generated::zkinterface as fb;
+use crate::consumers::verifier::{read_root, verify_message, verify_size_prefix, VerifierOptions, MESSAGE_ALIGN};
 use crate::Result;
 
 pub fn read_circuit_header(msg: &[u8]) -> Result<fb::CircuitHeader> {
-    fb::get_size_prefixed_root_as_root(msg)
+    read_root(msg)?
         .message_as_circuit_header().ok_or("Not a CircuitHeader message".into())
 }
 
 pub fn parse_header(msg: &[u8]) -> Option<(fb::CircuitHeader, Vec<Variable>)> {
-    let header = fb::get_size_prefixed_root_as_root(msg).message_as_circuit_header()?;
+    let header = read_root(msg).ok()?.message_as_circuit_header()?;
     let input_var_ids = header.instance_variables()?.variable_ids()?.safe_slice();
 
     let assigned = match header.instance_variables()?.values() {
@@ -47,15 +48,16 @@ pub fn is_contiguous(mut first_id: u64, ids: &[u64]) -> bool {
 // Read a flatbuffers size prefix (4 bytes, little-endian). Size including the prefix.
 pub fn read_size_prefix(buf: &[u8]) -> usize {
     if buf.len() < SIZE_UOFFSET { return 0; }
-    let size = read_scalar_at::<UOffsetT>(buf, 0) as usize;
+    let size = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
     SIZE_UOFFSET + size
 }
 
+/// Split a buffer into size-prefixed messages. A truncated message at the end is ignored.
 pub fn split_messages(mut buf: &[u8]) -> Vec<&[u8]> {
     let mut bufs = vec![];
     loop {
         let size = read_size_prefix(buf);
-        if size <= SIZE_UOFFSET { break; }
+        if size <= SIZE_UOFFSET || size > buf.len() { break; }
         bufs.push(&buf[..size]);
         buf = &buf[size..];
     }
@@ -72,8 +74,15 @@ pub fn read_buffer(mut stream: impl Read) -> Result<Vec<u8>> {
     if size <= SIZE_UOFFSET {
         return Ok(Vec::new()); // Explicit size 0 as end marker.
     }
-    buffer.resize(size, 0);
-    stream.read_exact(&mut buffer[4..])?;
+    let options = VerifierOptions::default();
+    if size > options.max_message_size {
+        verify_size_prefix(&buffer, &options)?;
+    }
+    // Grow the buffer as data arrives, instead of trusting the size prefix to allocate.
+    stream.take((size - SIZE_UOFFSET) as u64).read_to_end(&mut buffer)?;
+    if buffer.len() != size {
+        return Err(format!("Truncated message: expected {} bytes, got {}", size, buffer.len()).into());
+    }
     //eprintln!("Read buffer: {:?}", buffer);
     Ok(buffer)
 }
@@ -159,8 +168,37 @@ impl Reader {
         }
     }
 
+    /// Add a buffer of one or more messages. All messages are verified first.
+    /// If a message is not at an aligned position in the buffer, each message is copied
+    /// into its own buffer, so that it can be read in place.
     pub fn push_message(&mut self, buf: Vec<u8>) -> Result<()> {
-        self.messages.push(buf);
+        let mut sizes = vec![];
+        let mut rest = &buf[..];
+        while read_size_prefix(rest) > SIZE_UOFFSET {
+            let size = verify_size_prefix(rest, &VerifierOptions::default())?;
+            sizes.push(size);
+            rest = &rest[size..];
+        }
+
+        // The offset of each message is the sum of the sizes before it.
+        if sizes.iter().rev().skip(1).all(|size| size % MESSAGE_ALIGN == 0) {
+            let mut rest = &buf[..];
+            for size in sizes {
+                verify_message(rest)?;
+                rest = &rest[size..];
+            }
+            self.messages.push(buf);
+        } else {
+            let mut copies = vec![];
+            let mut rest = &buf[..];
+            for size in sizes {
+                let copy = rest[..size].to_vec();
+                verify_message(&copy)?;
+                copies.push(copy);
+                rest = &rest[size..];
+            }
+            self.messages.extend(copies);
+        }
         Ok(())
     }
 
@@ -327,9 +365,14 @@ impl<'a> Iterator for MessageIterator<'a> {
             // Move to the next message in the current buffer.
             self.offset += size;
 
-            // Parse the current message.
-            let root = fb::get_size_prefixed_root_as_root(&buf[..size]);
-            return Some(root);
+            // Parse the current message, or skip the rest of the buffer if invalid.
+            match read_root(&buf[..size]) {
+                Ok(root) => return Some(root),
+                Err(_) => {
+                    self.bufs = &self.bufs[1..];
+                    self.offset = 0;
+                }
+            }
         }
     }
 }
@@ -375,8 +418,8 @@ impl<'a> Iterator for R1CSIterator<'a> {
             let message = self.messages_iter.next()?;
 
             // Parse the message, skip irrelevant message types, or fail if invalid.
-            let constraints = match message.message_as_constraint_system() {
-                Some(message) => message.constraints().unwrap(),
+            let constraints = match message.message_as_constraint_system().and_then(|m| m.constraints()) {
+                Some(constraints) => constraints,
                 None => continue,
             };
 
@@ -389,10 +432,13 @@ impl<'a> Iterator for R1CSIterator<'a> {
         let constraint = self.constraints.as_ref().unwrap().get(self.next_constraint);
         self.next_constraint += 1;
 
-        fn to_vec<'a>(lc: fb::Variables<'a>) -> Vec<Term<'a>> {
+        fn to_vec<'a>(lc: Option<fb::Variables<'a>>) -> Vec<Term<'a>> {
             let mut terms = vec![];
-            let var_ids: &[u64] = lc.variable_ids().unwrap().safe_slice();
-            let values: &[u8] = lc.values().unwrap();
+            let var_ids: &[u64] = match lc.and_then(|lc| lc.variable_ids()) {
+                Some(var_ids) => var_ids.safe_slice(),
+                None => &[],
+            };
+            let values: &[u8] = lc.and_then(|lc| lc.values()).unwrap_or(&[]);
 
             let stride = get_value_size(var_ids, values);
 
@@ -407,12 +453,11 @@ impl<'a> Iterator for R1CSIterator<'a> {
         }
 
         Some(Constraint {
-            a: to_vec(constraint.linear_combination_a().unwrap()),
-            b: to_vec(constraint.linear_combination_b().unwrap()),
-            c: to_vec(constraint.linear_combination_c().unwrap()),
+            a: to_vec(constraint.linear_combination_a()),
+            b: to_vec(constraint.linear_combination_b()),
+            c: to_vec(constraint.linear_combination_c()),
         })
     }
-    // TODO: Replace unwrap and panic with Result.
 }
 
 
@@ -501,14 +546,17 @@ impl<'a> Iterator for WitnessIterator<'a> {
             let message = self.messages_iter.next()?;
 
             // Parse the message, skip irrelevant message types, or fail if invalid.
-            let witness = match message.message_as_witness() {
-                Some(message) => message.assigned_variables().unwrap(),
+            let witness = match message.message_as_witness().and_then(|m| m.assigned_variables()) {
+                Some(witness) => witness,
                 None => continue,
             };
 
             // Start iterating the values of the current message.
-            self.var_ids = witness.variable_ids().unwrap().safe_slice();
-            self.values = witness.values().unwrap();
+            self.var_ids = match witness.variable_ids() {
+                Some(var_ids) => var_ids.safe_slice(),
+                None => &[],
+            };
+            self.values = witness.values().unwrap_or(&[]);
             self.next_element = 0;
         }
 
@@ -522,7 +570,6 @@ impl<'a> Iterator for WitnessIterator<'a> {
             value: &self.values[stride * i..stride * (i + 1)],
         })
     }
-    // TODO: Replace unwrap and panic with Result.
 }
 
 #[test]
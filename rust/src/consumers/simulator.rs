@@ -1,34 +1,77 @@
 use crate::{Result, CircuitHeader, Witness, ConstraintSystem, Variables, Message};
 use crate::structs::constraints::BilinearConstraint;
+use crate::field::{Field, U64Field, MontgomeryField, BigUintField};
+use crate::consumers::parallel::{Batch, map_chunks};
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
 use num_bigint::BigUint;
-use num_traits::identities::{Zero, One};
 
 type Var = u64;
-type Field = BigUint;
+
+/// A constraint that does not hold with the assigned values.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnsatisfiedConstraint {
+    /// Index of the message containing the constraint, counting all messages ingested.
+    pub message_index: u64,
+    /// Index of the constraint within its ConstraintSystem message.
+    pub constraint_index: u64,
+    /// Evaluated linear combinations, reduced modulo the field order.
+    pub a: BigUint,
+    pub b: BigUint,
+    pub c: BigUint,
+    pub ab: BigUint,
+    /// Sorted IDs of all variables involved in the constraint.
+    pub variable_ids: Vec<Var>,
+}
+
+impl fmt::Display for UnsatisfiedConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Constraint {} of message {} is not satisfied: A*B = {} * {} = {} but C = {} (variables {:?})",
+               self.constraint_index, self.message_index, self.a, self.b, self.ab, self.c, self.variable_ids)
+    }
+}
 
 #[derive(Clone, Default)]
 pub struct Simulator {
-    values: HashMap<Var, Field>,
-    modulus: Field,
+    /// The values of variables, set up by the header.
+    evaluator: Option<Box<dyn Evaluator>>,
+
+    report_all: bool,
+    message_index: u64,
 
     verified_at_least_one_constraint: bool,
+    unsatisfied_constraints: Vec<UnsatisfiedConstraint>,
     found_error: Option<String>,
 }
 
 impl Simulator {
+    /// A simulator that keeps going after an unsatisfied constraint,
+    /// and reports all of them instead of the first one only.
+    pub fn new_reporting_all() -> Simulator {
+        Simulator { report_all: true, ..Self::default() }
+    }
+
     pub fn get_violations(self) -> Vec<String> {
         let mut violations = vec![];
         if !self.verified_at_least_one_constraint {
             violations.push("Did not receive any constraint to verify.".to_string());
         }
+        for unsatisfied in &self.unsatisfied_constraints {
+            violations.push(unsatisfied.to_string());
+        }
         if let Some(err) = self.found_error {
             violations.push(err);
         }
         violations
     }
 
+    /// The constraints found to be unsatisfied so far, in order.
+    /// This is only collected by a simulator created with `new_reporting_all`.
+    pub fn get_unsatisfied_constraints(&self) -> &[UnsatisfiedConstraint] {
+        &self.unsatisfied_constraints
+    }
+
     pub fn ingest_message(&mut self, msg: &Message) {
         if self.found_error.is_some() { return; }
 
@@ -43,91 +86,235 @@ impl Simulator {
             Message::Header(h) => self.ingest_header(&h)?,
             Message::ConstraintSystem(cs) => self.ingest_constraint_system(&cs)?,
             Message::Witness(w) => self.ingest_witness(&w)?,
-            Message::Command(_) => {}
-            Message::Err(_) => {}
+            Message::Command(_) => { self.message_index += 1; }
+            Message::Err(_) => { self.message_index += 1; }
         }
         Ok(())
     }
 
     pub fn ingest_header(&mut self, header: &CircuitHeader) -> Result<()> {
-        // Set the field.
-        let max = header.field_maximum.as_ref().ok_or("No field_maximum specified")?;
-        self.modulus = BigUint::from_bytes_le(max) + 1 as u8;
+        self.message_index += 1;
 
-        self.set(0, Field::one());
+        // Set the field, choosing the fastest arithmetic for its size.
+        // Another header keeps the values assigned so far, in the same field.
+        let max = header.field_maximum.as_ref().ok_or("No field_maximum specified")?;
+        let modulus = BigUint::from_bytes_le(max) + 1u8;
+        let evaluator = match &mut self.evaluator {
+            Some(evaluator) if evaluator.modulus() != modulus =>
+                return Err("All headers must have the same field_maximum.".into()),
+            Some(evaluator) => evaluator,
+            None => self.evaluator.get_or_insert(new_evaluator(modulus)),
+        };
 
         // Set instance variable values.
         for var in header.instance_variables.get_variables() {
-            self.set_encoded(var.id, var.value);
+            evaluator.set(var.id, var.value);
         }
-
         Ok(())
     }
 
     pub fn ingest_witness(&mut self, witness: &Witness) -> Result<()> {
-        self.ensure_header()?;
+        self.message_index += 1;
+        let evaluator = self.evaluator.as_mut()
+            .ok_or("A header must be provided before other messages.")?;
 
         for var in witness.assigned_variables.get_variables() {
-            self.set_encoded(var.id, var.value);
+            evaluator.set(var.id, var.value);
         }
         Ok(())
     }
 
     pub fn ingest_constraint_system(&mut self, system: &ConstraintSystem) -> Result<()> {
-        self.ensure_header()?;
+        let message_index = self.message_index;
+        self.message_index += 1;
+        let evaluator = self.evaluator.as_deref()
+            .ok_or("A header must be provided before other messages.")?;
+
+        let checked = check_constraints(evaluator, self.report_all, message_index, 0, &system.constraints);
+        self.merge(system, vec![checked])
+    }
+
+    /// Check consecutive ConstraintSystem messages, with chunks of constraints checked in parallel.
+    /// The results are the same as with `ingest_constraint_system` on each message in order.
+    pub fn ingest_constraint_systems_parallel(&mut self, systems: &[ConstraintSystem]) -> Result<()> {
+        let first_message_index = self.message_index;
+        self.message_index += systems.len() as u64;
+        let evaluator = self.evaluator.as_deref()
+            .ok_or("A header must be provided before other messages.")?;
+
+        let report_all = self.report_all;
+        let checked = map_chunks(systems, |i, first_index, chunk|
+            check_constraints(evaluator, report_all, first_message_index + i as u64, first_index, chunk));
+
+        for (system, chunks) in systems.iter().zip(checked) {
+            self.merge(system, chunks)?;
+        }
+        Ok(())
+    }
+
+    /// Like `ingest_message`, but check batches of constraints in parallel.
+    pub fn ingest_batch(&mut self, batch: &Batch) {
+        match batch {
+            Batch::Message(msg) => self.ingest_message(msg),
+            Batch::ConstraintSystems(systems) => {
+                if self.found_error.is_some() { return; }
 
-        if system.constraints.len() > 0 {
+                if let Err(err) = self.ingest_constraint_systems_parallel(systems) {
+                    self.found_error = Some(err.to_string());
+                }
+            }
+        }
+    }
+
+    /// Collect the results of the chunks of a system, in order.
+    fn merge(&mut self, system: &ConstraintSystem, chunks: Vec<CheckedChunk>) -> Result<()> {
+        if !system.constraints.is_empty() {
             self.verified_at_least_one_constraint = true;
         }
 
-        for constraint in &system.constraints {
-            self.verify_constraint(constraint)?;
+        for (unsatisfied_constraints, error) in chunks {
+            for unsatisfied in unsatisfied_constraints {
+                if !self.report_all {
+                    return Err(unsatisfied.to_string().into());
+                }
+                self.unsatisfied_constraints.push(unsatisfied);
+            }
+            if let Some(err) = error {
+                return Err(err.into());
+            }
         }
         Ok(())
     }
+}
 
-    fn verify_constraint(&mut self, constraint: &BilinearConstraint) -> Result<()> {
-        let a = self.sum_terms(&constraint.linear_combination_a)?;
-        let b = self.sum_terms(&constraint.linear_combination_b)?;
-        let c = self.sum_terms(&constraint.linear_combination_c)?;
-        let ab = (a * b) % &self.modulus;
-        let c = c % &self.modulus;
-        if ab.eq(&c) {
-            Ok(())
-        } else {
-            Err(format!("Constraint is not satisfied ({:?})", constraint).into())
+/// The unsatisfied constraints of a chunk, and the error that stopped the check, if any.
+type CheckedChunk = (Vec<UnsatisfiedConstraint>, Option<String>);
+
+/// Check a chunk of constraints, stopping at the first error,
+/// or at the first unsatisfied constraint if not reporting all.
+fn check_constraints(
+    evaluator: &dyn Evaluator,
+    report_all: bool,
+    message_index: u64,
+    first_index: u64,
+    constraints: &[BilinearConstraint],
+) -> CheckedChunk {
+    let mut unsatisfied_constraints = vec![];
+
+    for (i, constraint) in constraints.iter().enumerate() {
+        match evaluator.check(constraint) {
+            Err(err) => return (unsatisfied_constraints, Some(err.to_string())),
+            Ok(None) => {}
+            Ok(Some((a, b, c, ab))) => {
+                unsatisfied_constraints.push(UnsatisfiedConstraint {
+                    message_index,
+                    constraint_index: first_index + i as u64,
+                    a,
+                    b,
+                    c,
+                    ab,
+                    variable_ids: involved_variables(constraint),
+                });
+                if !report_all { break; }
+            }
         }
     }
+    (unsatisfied_constraints, None)
+}
+
+fn involved_variables(constraint: &BilinearConstraint) -> Vec<Var> {
+    let ids = constraint.linear_combination_a.variable_ids.iter()
+        .chain(constraint.linear_combination_b.variable_ids.iter())
+        .chain(constraint.linear_combination_c.variable_ids.iter())
+        .cloned()
+        .collect::<BTreeSet<Var>>();
+    ids.into_iter().collect()
+}
+
+/// Evaluate constraints in a field, with the arithmetic chosen at runtime.
+trait Evaluator: Send + Sync {
+    fn modulus(&self) -> BigUint;
+
+    fn set(&mut self, id: Var, encoded: &[u8]);
+
+    /// Returns the values of A, B, C, and A*B if the constraint is not satisfied.
+    fn check(&self, constraint: &BilinearConstraint) -> Result<Option<(BigUint, BigUint, BigUint, BigUint)>>;
+
+    fn box_clone(&self) -> Box<dyn Evaluator>;
+}
+
+impl Clone for Box<dyn Evaluator> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+fn new_evaluator(modulus: BigUint) -> Box<dyn Evaluator> {
+    if let Some(field) = U64Field::new(&modulus) {
+        Box::new(FieldEvaluator::new(field))
+    } else if let Some(field) = MontgomeryField::new(&modulus) {
+        Box::new(FieldEvaluator::new(field))
+    } else {
+        Box::new(FieldEvaluator::new(BigUintField::new(modulus)))
+    }
+}
+
+#[derive(Clone)]
+struct FieldEvaluator<F: Field> {
+    field: F,
+    values: HashMap<Var, F::Element>,
+}
+
+impl<F: Field> FieldEvaluator<F> {
+    fn new(field: F) -> Self {
+        let mut values = HashMap::new();
+        values.insert(0, field.one());
+        FieldEvaluator { field, values }
+    }
 
-    fn sum_terms(&self, terms: &Variables) -> Result<Field> {
-        let mut sum = Field::zero();
+    fn sum_terms(&self, terms: &Variables) -> Result<F::Element> {
+        let mut sum = self.field.zero();
         for term in terms.get_variables() {
             let value = self.get(term.id)?;
-            let coeff = Field::from_bytes_le(term.value);
-            sum += coeff * value;
+            let coeff = self.field.element_from_bytes_le(term.value);
+            sum = self.field.add(&sum, &self.field.mul(&coeff, value));
         }
         Ok(sum)
     }
 
-    fn set_encoded(&mut self, id: Var, encoded: &[u8]) {
-        self.set(id, Field::from_bytes_le(encoded));
+    fn get(&self, id: Var) -> Result<&F::Element> {
+        self.values.get(&id)
+            .ok_or_else(|| format!("No value given for variable {}", id).into())
     }
+}
 
-    fn set(&mut self, id: Var, mut value: Field) {
-        value %= &self.modulus;
-        self.values.insert(id, value);
+impl<F: Field + 'static> Evaluator for FieldEvaluator<F> {
+    fn modulus(&self) -> BigUint {
+        self.field.modulus()
     }
 
-    fn get(&self, id: Var) -> Result<&Field> {
-        self.values.get(&id)
-            .ok_or(format!("No value given for variable {}", id).into())
+    fn set(&mut self, id: Var, encoded: &[u8]) {
+        self.values.insert(id, self.field.element_from_bytes_le(encoded));
     }
 
-    fn ensure_header(&self) -> Result<()> {
-        match self.modulus.is_zero() {
-            true => Err("A header must be provided before other messages.".into()),
-            _ => Ok(()),
+    fn check(&self, constraint: &BilinearConstraint) -> Result<Option<(BigUint, BigUint, BigUint, BigUint)>> {
+        let a = self.sum_terms(&constraint.linear_combination_a)?;
+        let b = self.sum_terms(&constraint.linear_combination_b)?;
+        let c = self.sum_terms(&constraint.linear_combination_c)?;
+        let ab = self.field.mul(&a, &b);
+        if ab == c {
+            return Ok(None);
         }
+        Ok(Some((
+            self.field.to_biguint(&a),
+            self.field.to_biguint(&b),
+            self.field.to_biguint(&c),
+            self.field.to_biguint(&ab),
+        )))
+    }
+
+    fn box_clone(&self) -> Box<dyn Evaluator> {
+        Box::new(self.clone())
     }
 }
 
@@ -144,5 +331,109 @@ fn test_simulator() -> Result<()> {
     simulator.ingest_witness(&witness)?;
     simulator.ingest_constraint_system(&cs)?;
 
+    // A second header keeps the witness, and must have the same field.
+    let mut simulator = Simulator::default();
+    simulator.ingest_header(&header)?;
+    simulator.ingest_witness(&witness)?;
+    simulator.ingest_header(&header)?;
+    simulator.ingest_constraint_system(&cs)?;
+
+    let mut other_field = header.clone();
+    other_field.field_maximum = Some(vec![200]);
+    assert!(simulator.ingest_header(&other_field).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_simulator_report_all() -> Result<()> {
+    use crate::producers::examples::*;
+
+    let header = example_circuit_header();
+    // Wrong values for xx and yy: x*x = xx and y*y = yy fail, but xx + yy = zz still holds.
+    let witness = Witness {
+        assigned_variables: Variables {
+            variable_ids: vec![4, 5],
+            values: Some(serialize_small(&[10u32, 15])),
+            info: None,
+        }
+    };
+    let cs = example_constraints();
+
+    // Stop at the first failure by default.
+    let mut simulator = Simulator::default();
+    simulator.ingest_message(&Message::Header(header.clone()));
+    simulator.ingest_message(&Message::Witness(witness.clone()));
+    simulator.ingest_message(&Message::ConstraintSystem(cs.clone()));
+    simulator.ingest_message(&Message::ConstraintSystem(cs.clone()));
+    assert_eq!(simulator.get_unsatisfied_constraints().len(), 0);
+    assert_eq!(simulator.get_violations().len(), 1);
+
+    // Collect all failures.
+    let mut simulator = Simulator::new_reporting_all();
+    simulator.ingest_message(&Message::Header(header));
+    simulator.ingest_message(&Message::Witness(witness));
+    simulator.ingest_message(&Message::ConstraintSystem(cs.clone()));
+    simulator.ingest_message(&Message::ConstraintSystem(cs));
+
+    let unsatisfied = simulator.get_unsatisfied_constraints();
+    assert_eq!(unsatisfied.len(), 4);
+    assert_eq!(unsatisfied[0], UnsatisfiedConstraint {
+        message_index: 2,
+        constraint_index: 0,
+        a: BigUint::from(3u32),
+        b: BigUint::from(3u32),
+        c: BigUint::from(10u32),
+        ab: BigUint::from(9u32),
+        variable_ids: vec![1, 4],
+    });
+    assert_eq!(unsatisfied[1].message_index, 2);
+    assert_eq!(unsatisfied[1].constraint_index, 1);
+    assert_eq!(unsatisfied[1].variable_ids, vec![2, 5]);
+    assert_eq!(unsatisfied[2].message_index, 3);
+    assert_eq!(unsatisfied[2].constraint_index, 0);
+    assert_eq!(unsatisfied[3].message_index, 3);
+    assert_eq!(unsatisfied[3].constraint_index, 1);
+
+    assert_eq!(simulator.get_violations().len(), 4);
+    Ok(())
+}
+
+#[test]
+fn test_simulator_fields() -> Result<()> {
+    use crate::Messages;
+    use crate::producers::circuit_generator::generate_metrics_data;
+
+    // Moduli of 2, 5, 128, and 256 bits, and of 257 bits beyond the fixed-width implementations.
+    for hexaprime in &["2", "11", "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFF61",
+        "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF43",
+        "0100000000000000000000000000000000000000000000000000000000000000AB"] {
+        let mut messages = Messages::default();
+        generate_metrics_data(&mut messages, hexaprime, 20, 10)?;
+
+        let mut simulator = Simulator::new_reporting_all();
+        for header in &messages.circuit_headers {
+            simulator.ingest_header(header)?;
+        }
+        for witness in &messages.witnesses {
+            simulator.ingest_witness(witness)?;
+        }
+        for cs in &messages.constraint_systems {
+            simulator.ingest_constraint_system(cs)?;
+        }
+        assert_eq!(simulator.get_unsatisfied_constraints().len(), 0);
+
+        // Change the value of an instance variable.
+        let instance_values = messages.circuit_headers[0].instance_variables.values.as_mut().unwrap();
+        instance_values[0] ^= 1;
+        simulator.ingest_header(&messages.circuit_headers[0])?;
+        for witness in &messages.witnesses {
+            simulator.ingest_witness(witness)?;
+        }
+        for cs in &messages.constraint_systems {
+            simulator.ingest_constraint_system(cs)?;
+        }
+        assert_eq!(simulator.get_unsatisfied_constraints().len(), 1);
+    }
     Ok(())
 }
@@ -0,0 +1,287 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use num_bigint::BigUint;
+
+use crate::{Result, Variables, Witness, Messages};
+use crate::field::{Field, BigUintField};
+
+type Var = u64;
+/// A normalized linear combination, see `Variables::normalize`.
+type Lc = Vec<(Var, BigUint)>;
+
+/// What the solver found.
+#[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct SolverReport {
+    /// Variables whose values were derived from constraints.
+    pub solved_variables: Vec<Var>,
+    /// Variables used in constraints whose values could not be derived.
+    pub unsolved_variables: Vec<Var>,
+}
+
+/// Compute the values of witness variables that are missing, from the constraints.
+///
+/// A constraint with a single unknown variable is used to derive its value, when the variable
+/// appears linearly: in C, or in A or B with a known factor. The derived values can make
+/// other constraints solvable, until no more progress is possible.
+///
+/// Returns a Witness with the given and derived values of all witness variables, sorted by ID,
+/// and encoded with the width of the field.
+///
+/// # Example
+/// ```
+/// use zkinterface::Messages;
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::consumers::solver::solve_witness;
+///
+/// // No witness is given.
+/// let messages = Messages {
+///     circuit_headers: vec![example_circuit_header()],
+///     constraint_systems: vec![example_constraints()],
+///     witnesses: vec![],
+/// };
+///
+/// let (witness, report) = solve_witness(&messages).unwrap();
+/// let modulus = messages.circuit_headers[0].modulus();
+/// assert_eq!(witness, example_witness().normalize(modulus.as_ref()));
+/// assert_eq!(report.solved_variables, vec![4, 5]);
+/// ```
+pub fn solve_witness(messages: &Messages) -> Result<(Witness, SolverReport)> {
+    let header = match &messages.circuit_headers[..] {
+        [header] => header,
+        [] => return Err("solve requires a header.".into()),
+        _ => return Err("solve requires a single header.".into()),
+    };
+    let max = header.field_maximum.as_ref().ok_or("No field_maximum specified")?;
+    let field = BigUintField::new(BigUint::from_bytes_le(max) + 1u8);
+    let modulus = field.modulus();
+
+    let mut solver = Solver {
+        field,
+        values: HashMap::new(),
+        constraints: vec![],
+    };
+    solver.values.insert(0, solver.field.one());
+    solver.set_values(&header.instance_variables);
+    for witness in &messages.witnesses {
+        solver.set_values(&witness.assigned_variables);
+    }
+    let instance_ids = header.instance_variables.variable_ids.iter().cloned().collect::<BTreeSet<_>>();
+    let given_ids = solver.values.keys().cloned().collect::<BTreeSet<_>>();
+
+    let mut propagation = Propagation::default();
+    for cs in &messages.constraint_systems {
+        for constraint in &cs.normalize(Some(&modulus), false).constraints {
+            let lcs = [
+                lc(&constraint.linear_combination_a),
+                lc(&constraint.linear_combination_b),
+                lc(&constraint.linear_combination_c),
+            ];
+            propagation.add_constraint(lcs.iter().flatten()
+                .map(|(id, _)| *id)
+                .filter(|id| !solver.values.contains_key(id)));
+            solver.constraints.push(lcs);
+        }
+    }
+    propagation.run(|index, id| solver.solve(index, id));
+
+    let mut report = SolverReport {
+        unsolved_variables: propagation.unknown_variables(),
+        ..SolverReport::default()
+    };
+
+    // All witness variables with a value, given or solved.
+    let witness_values = solver.values.iter()
+        .filter(|(id, _)| **id != 0 && !instance_ids.contains(id))
+        .map(|(id, value)| (*id, solver.field.to_biguint(value)))
+        .collect::<BTreeMap<_, _>>();
+    report.solved_variables = witness_values.keys()
+        .filter(|id| !given_ids.contains(id))
+        .cloned().collect();
+
+    // Encode values with the width of the field.
+    let width = BigUint::from_bytes_le(max).to_bytes_le().len().max(1);
+    let mut values = Vec::with_capacity(witness_values.len() * width);
+    for value in witness_values.values() {
+        let mut bytes = value.to_bytes_le();
+        bytes.resize(width, 0);
+        values.extend_from_slice(&bytes);
+    }
+    let witness = Witness {
+        assigned_variables: Variables {
+            variable_ids: witness_values.keys().cloned().collect(),
+            values: Some(values),
+            info: None,
+        }
+    };
+    Ok((witness, report))
+}
+
+/// Propagate through constraints which variables are known.
+///
+/// A constraint with a single unknown variable may determine it, and then other constraints
+/// that use it may have a single unknown variable left, until no more progress is possible.
+/// Constraints are numbered in the order they are added.
+#[derive(Default)]
+pub(crate) struct Propagation {
+    /// The variables of each constraint not known yet.
+    unknowns: Vec<BTreeSet<Var>>,
+    /// The constraints that use each variable not known yet.
+    used_in: HashMap<Var, Vec<usize>>,
+}
+
+impl Propagation {
+    /// Add a constraint with the given variables not known yet.
+    pub(crate) fn add_constraint(&mut self, unknowns: impl IntoIterator<Item=Var>) {
+        let index = self.unknowns.len();
+        let unknowns = unknowns.into_iter().collect::<BTreeSet<_>>();
+        for id in &unknowns {
+            self.used_in.entry(*id).or_default().push(index);
+        }
+        self.unknowns.push(unknowns);
+    }
+
+    /// Call `determine(index, id)` for constraints with a single unknown variable `id`.
+    /// If it returns true, the variable becomes known in all constraints.
+    pub(crate) fn run(&mut self, mut determine: impl FnMut(usize, Var) -> bool) {
+        let mut queue = (0..self.unknowns.len())
+            .filter(|i| self.unknowns[*i].len() == 1)
+            .collect::<VecDeque<_>>();
+
+        while let Some(index) = queue.pop_front() {
+            let id = match self.unknowns[index].iter().next() {
+                Some(id) if self.unknowns[index].len() == 1 => *id,
+                _ => continue,
+            };
+            if !determine(index, id) {
+                continue;
+            }
+
+            for user in self.used_in.remove(&id).unwrap_or_default() {
+                self.unknowns[user].remove(&id);
+                if self.unknowns[user].len() == 1 {
+                    queue.push_back(user);
+                }
+            }
+        }
+    }
+
+    /// The variables still unknown, sorted.
+    pub(crate) fn unknown_variables(&self) -> Vec<Var> {
+        self.unknowns.iter().flatten().cloned().collect::<BTreeSet<_>>().into_iter().collect()
+    }
+}
+
+struct Solver {
+    field: BigUintField,
+    values: HashMap<Var, BigUint>,
+    /// The linear combinations A, B, C of each constraint.
+    constraints: Vec<[Lc; 3]>,
+}
+
+impl Solver {
+    fn set_values(&mut self, vars: &Variables) {
+        if vars.values.is_none() { return; }
+        for var in vars.get_variables() {
+            self.values.insert(var.id, self.field.element_from_bytes_le(var.value));
+        }
+    }
+
+    /// Derive the value of the only unknown variable of a constraint, if possible.
+    fn solve(&mut self, index: usize, id: Var) -> bool {
+        match self.solve_for(index, id) {
+            Some(value) => {
+                self.values.insert(id, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Solve A * B = C for the only unknown variable, if it appears linearly.
+    fn solve_for(&self, index: usize, id: Var) -> Option<BigUint> {
+        let f = &self.field;
+        // Each combination as known + factor * x.
+        let [(ka, fa), (kb, fb), (kc, fc)] = {
+            let [a, b, c] = &self.constraints[index];
+            [self.split(a, id), self.split(b, id), self.split(c, id)]
+        };
+        if !f.is_zero(&fa) && !f.is_zero(&fb) {
+            // Quadratic in x.
+            return None;
+        }
+
+        // (ka + fa x) (kb + fb x) = kc + fc x
+        // (fa kb + fb ka - fc) x = kc - ka kb
+        let factor = f.sub(&f.add(&f.mul(&fa, &kb), &f.mul(&fb, &ka)), &fc);
+        let rest = f.sub(&kc, &f.mul(&ka, &kb));
+        Some(f.mul(&rest, &f.inv(&factor)?))
+    }
+
+    /// Evaluate the known part of a combination, and the coefficient of the unknown variable.
+    fn split(&self, lc: &Lc, unknown: Var) -> (BigUint, BigUint) {
+        let f = &self.field;
+        let mut known = f.zero();
+        let mut factor = f.zero();
+        for (id, coeff) in lc {
+            if *id == unknown {
+                factor = f.add(&factor, coeff);
+            } else if let Some(value) = self.values.get(id) {
+                known = f.add(&known, &f.mul(coeff, value));
+            }
+        }
+        (known, factor)
+    }
+}
+
+fn lc(vars: &Variables) -> Lc {
+    vars.get_variables().iter()
+        .map(|var| (var.id, BigUint::from_bytes_le(var.value)))
+        .collect()
+}
+
+
+#[test]
+fn test_solver() -> Result<()> {
+    use crate::ConstraintSystem;
+    use crate::producers::examples::*;
+    use crate::consumers::simulator::Simulator;
+
+    let mut header = example_circuit_header();
+    header.free_variable_id = 8;
+
+    let mut constraints = example_constraints();
+    constraints.constraints.extend(ConstraintSystem::from(&[
+        // A known factor: v6 * x = zz, so v6 = 25 / 3
+        ((vec![6], vec![1]), (vec![1], vec![1]), (vec![3], vec![1])),
+        // Quadratic: v7 * v7 = xx
+        ((vec![7], vec![1]), (vec![7], vec![1]), (vec![4], vec![1])),
+    ][..]).constraints);
+
+    let mut messages = Messages {
+        circuit_headers: vec![header],
+        constraint_systems: vec![constraints],
+        // Only xx is given.
+        witnesses: vec![Witness {
+            assigned_variables: Variables { variable_ids: vec![4], values: Some(vec![9]), info: None },
+        }],
+    };
+
+    let (witness, report) = solve_witness(&messages)?;
+    assert_eq!(report, SolverReport {
+        solved_variables: vec![5, 6],
+        unsolved_variables: vec![7],
+    });
+    assert_eq!(witness.assigned_variables.variable_ids, vec![4, 5, 6]);
+    // 3 * 42 = 126 = 25 mod 101
+    assert_eq!(witness.assigned_variables.values, Some(vec![9, 16, 42]));
+
+    // The solved witness satisfies the solvable constraints.
+    messages.constraint_systems[0].constraints.pop();
+    let mut simulator = Simulator::default();
+    simulator.ingest_header(&messages.circuit_headers[0])?;
+    simulator.ingest_witness(&witness)?;
+    simulator.ingest_constraint_system(&messages.constraint_systems[0])?;
+    assert_eq!(simulator.get_violations(), Vec::<String>::new());
+
+    Ok(())
+}
@@ -2,8 +2,14 @@ extern crate serde;
 extern crate serde_json;
 
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
 
-use crate::{Workspace, Message};
+use crate::{Workspace, Message, Variables, BilinearConstraint};
+use crate::structs::constraints::NAMESPACE_SEPARATOR;
+
+type Var = u64;
 
 #[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Stats {
@@ -14,41 +20,478 @@ pub struct Stats {
     pub additions_a: u64,
     pub additions_b: u64,
     pub additions_c: u64,
+
+    /// The number of bits of the field order.
+    pub field_bits: u64,
+    /// Constraints where A or B is a constant, so that no multiplication is needed.
+    pub linear_constraints: u64,
+    /// The number of terms with a nonzero coefficient, as entries of the matrices A, B, C.
+    pub nonzeros_a: u64,
+    pub nonzeros_b: u64,
+    pub nonzeros_c: u64,
+    /// How many linear combinations have each number of terms.
+    pub lc_lengths_a: BTreeMap<u64, u64>,
+    pub lc_lengths_b: BTreeMap<u64, u64>,
+    pub lc_lengths_c: BTreeMap<u64, u64>,
+    /// The width of coefficients in bytes, without trailing zeros.
+    pub coefficient_bytes_max: u64,
+    /// The sum of the widths of all coefficients, see `coefficient_bytes_average`.
+    pub coefficient_bytes_total: u64,
+    pub num_coefficients: u64,
+    pub distinct_coefficients: u64,
+    /// The largest number of constraints that use a variable, not counting the constant one.
+    pub fan_out_max: u64,
+    /// How many variables are used by each number of constraints.
+    pub fan_out: BTreeMap<u64, u64>,
+    pub witness_variables: u64,
+    /// The size of the witness values in bytes.
+    pub witness_bytes: u64,
+
+    /// The cost of constraints grouped by namespace, see `ConstraintSystem::namespace`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub namespaces: BTreeMap<String, NamespaceStats>,
+
+    /// The statistics of each file, if ingested with `ingest_file_messages`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileStats>,
+
+    /// The distinct coefficients, each with the number of the last file that used it.
+    #[serde(skip)]
+    coefficients: HashMap<Vec<u8>, usize>,
+    #[serde(skip)]
+    fan_out_per_variable: HashMap<Var, FanOut>,
+}
+
+/// The number of constraints that use a variable, in total and in the last file that used it.
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq)]
+struct FanOut {
+    total: u64,
+    file: usize,
+    in_file: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct FileStats {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub stats: Stats,
+}
+
+/// The cost of a namespace, including its nested namespaces, and without them ("self").
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct NamespaceStats {
+    pub multiplications: u64,
+    pub additions: u64,
+    pub self_multiplications: u64,
+    pub self_additions: u64,
 }
 
 impl Stats {
     pub fn ingest_workspace(&mut self, ws: &Workspace) {
         for msg in ws.iter_messages() {
-            match msg {
-                Message::Header(header) => {
-                    self.num_public_inputs = header.instance_variables.variable_ids.len() as u64;
-                    self.num_private_variables = header.free_variable_id - self.num_public_inputs - 1;
+            self.ingest_message(&msg);
+        }
+    }
+
+    /// Ingest the messages of a file into the totals, and into the statistics of this file.
+    ///
+    /// The distinct coefficients and the fan-out of variables are tracked once for all files.
+    pub fn ingest_file_messages(&mut self, path: &Path, messages: &mut dyn Iterator<Item=Message>) {
+        // Files are numbered from 1, so that 0 means no file.
+        let file = self.files.len() + 1;
+        let mut file_stats = Stats::default();
+        for msg in messages {
+            self.count_message(&msg);
+            file_stats.count_message(&msg);
+            self.ingest_distinct(&msg, Some((file, &mut file_stats)));
+        }
+        self.files.push(FileStats { path: path.to_path_buf(), stats: file_stats });
+    }
+
+    pub fn ingest_message(&mut self, msg: &Message) {
+        self.count_message(msg);
+        self.ingest_distinct(msg, None);
+    }
+
+    /// The average width of coefficients in bytes.
+    pub fn coefficient_bytes_average(&self) -> f64 {
+        if self.num_coefficients == 0 { return 0.0; }
+        self.coefficient_bytes_total as f64 / self.num_coefficients as f64
+    }
+
+    /// Update the statistics that do not depend on previous messages.
+    fn count_message(&mut self, msg: &Message) {
+        match msg {
+            Message::Header(header) => {
+                self.num_public_inputs = header.instance_variables.variable_ids.len() as u64;
+                self.num_private_variables = header.free_variable_id - self.num_public_inputs - 1;
+                if let Some(modulus) = header.modulus() {
+                    self.field_bits = modulus.bits();
                 }
+            }
 
-                Message::ConstraintSystem(cs) => {
-                    self.multiplications += cs.constraints.len() as u64;
+            Message::ConstraintSystem(cs) => {
+                let additions_before = self.additions_a + self.additions_b + self.additions_c;
+                self.multiplications += cs.constraints.len() as u64;
 
-                    for constraint in &cs.constraints {
-                        let len_a = constraint.linear_combination_a.variable_ids.len() as u64;
-                        if len_a > 0 {
-                            self.additions_a += len_a - 1;
-                        }
+                for constraint in &cs.constraints {
+                    let len_a = constraint.linear_combination_a.variable_ids.len() as u64;
+                    if len_a > 0 {
+                        self.additions_a += len_a - 1;
+                    }
 
-                        let len_b = constraint.linear_combination_b.variable_ids.len() as u64;
-                        if len_b > 0 {
-                            self.additions_b += len_b - 1;
-                        }
+                    let len_b = constraint.linear_combination_b.variable_ids.len() as u64;
+                    if len_b > 0 {
+                        self.additions_b += len_b - 1;
+                    }
 
-                        let len_c = constraint.linear_combination_c.variable_ids.len() as u64;
-                        if len_c > 0 {
-                            self.additions_c += len_c - 1;
-                        }
+                    let len_c = constraint.linear_combination_c.variable_ids.len() as u64;
+                    if len_c > 0 {
+                        self.additions_c += len_c - 1;
                     }
-                    self.additions = self.additions_a + self.additions_b + self.additions_c;
+
+                    self.count_constraint(constraint);
                 }
+                self.additions = self.additions_a + self.additions_b + self.additions_c;
+                if let Some(namespace) = cs.namespace() {
+                    self.ingest_namespace(namespace, cs.constraints.len() as u64, self.additions - additions_before);
+                }
+            }
+
+            Message::Witness(witness) => {
+                let vars = &witness.assigned_variables;
+                self.witness_variables += vars.variable_ids.len() as u64;
+                self.witness_bytes += vars.values.as_ref().map_or(0, |values| values.len() as u64);
+            }
+
+            _ => {}
+        }
+    }
+
+    fn count_constraint(&mut self, constraint: &BilinearConstraint) {
+        let a = &constraint.linear_combination_a;
+        let b = &constraint.linear_combination_b;
+        let c = &constraint.linear_combination_c;
+
+        let is_constant = |lc: &Variables| lc.variable_ids.iter().all(|id| *id == 0);
+        if is_constant(a) || is_constant(b) {
+            self.linear_constraints += 1;
+        }
+
+        *self.lc_lengths_a.entry(a.variable_ids.len() as u64).or_default() += 1;
+        *self.lc_lengths_b.entry(b.variable_ids.len() as u64).or_default() += 1;
+        *self.lc_lengths_c.entry(c.variable_ids.len() as u64).or_default() += 1;
+        self.nonzeros_a += self.count_coefficients(a);
+        self.nonzeros_b += self.count_coefficients(b);
+        self.nonzeros_c += self.count_coefficients(c);
+    }
+
+    /// Update the distinct coefficients and the fan-out, in the totals and in the statistics of a file.
+    fn ingest_distinct(&mut self, msg: &Message, mut file: Option<(usize, &mut Stats)>) {
+        let cs = match msg {
+            Message::ConstraintSystem(cs) => cs,
+            _ => return,
+        };
+        let file_index = file.as_ref().map_or(0, |(index, _)| *index);
 
-                _ => {}
+        for constraint in &cs.constraints {
+            let lcs = [&constraint.linear_combination_a, &constraint.linear_combination_b, &constraint.linear_combination_c];
+
+            for term in lcs.iter().flat_map(|lc| lc.get_variables()) {
+                let coeff = &term.value[..coefficient_width(term.value)];
+                let first_in_file = match self.coefficients.get_mut(coeff) {
+                    Some(last_file) => std::mem::replace(last_file, file_index) != file_index,
+                    None => {
+                        self.coefficients.insert(coeff.to_vec(), file_index);
+                        self.distinct_coefficients += 1;
+                        true
+                    }
+                };
+                if let Some((_, stats)) = &mut file {
+                    if first_in_file {
+                        stats.distinct_coefficients += 1;
+                    }
+                }
+            }
+
+            let mut ids = lcs.iter()
+                .flat_map(|lc| lc.variable_ids.iter())
+                .filter(|id| **id != 0)
+                .cloned()
+                .collect::<Vec<_>>();
+            ids.sort_unstable();
+            ids.dedup();
+            for id in ids {
+                let count = self.fan_out_per_variable.entry(id).or_default();
+                increment_fan_out(&mut self.fan_out, &mut self.fan_out_max, &mut count.total);
+                if let Some((_, stats)) = &mut file {
+                    if count.file != file_index {
+                        count.file = file_index;
+                        count.in_file = 0;
+                    }
+                    increment_fan_out(&mut stats.fan_out, &mut stats.fan_out_max, &mut count.in_file);
+                }
+            }
+        }
+    }
+
+    /// Add the cost to the namespace and to all its parents.
+    fn ingest_namespace(&mut self, namespace: &str, multiplications: u64, additions: u64) {
+        let names = namespace.split(NAMESPACE_SEPARATOR).filter(|name| !name.is_empty()).collect::<Vec<_>>();
+        for depth in 1..=names.len() {
+            let path = names[..depth].join(&NAMESPACE_SEPARATOR.to_string());
+            let stats = self.namespaces.entry(path).or_default();
+            stats.multiplications += multiplications;
+            stats.additions += additions;
+            if depth == names.len() {
+                stats.self_multiplications += multiplications;
+                stats.self_additions += additions;
             }
         }
     }
+
+    /// Returns the number of nonzero coefficients.
+    fn count_coefficients(&mut self, lc: &Variables) -> u64 {
+        let mut nonzeros = 0;
+        for term in lc.get_variables() {
+            let width = coefficient_width(term.value);
+            if width > 0 {
+                nonzeros += 1;
+            }
+            self.num_coefficients += 1;
+            self.coefficient_bytes_total += width as u64;
+            self.coefficient_bytes_max = self.coefficient_bytes_max.max(width as u64);
+        }
+        nonzeros
+    }
+
+    /// The main statistics as names and values, without the histograms.
+    pub fn summary(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("num_public_inputs", self.num_public_inputs.to_string()),
+            ("num_private_variables", self.num_private_variables.to_string()),
+            ("field_bits", self.field_bits.to_string()),
+            ("multiplications", self.multiplications.to_string()),
+            ("linear_constraints", self.linear_constraints.to_string()),
+            ("additions", self.additions.to_string()),
+            ("nonzeros_a", self.nonzeros_a.to_string()),
+            ("nonzeros_b", self.nonzeros_b.to_string()),
+            ("nonzeros_c", self.nonzeros_c.to_string()),
+            ("coefficient_bytes_max", self.coefficient_bytes_max.to_string()),
+            ("coefficient_bytes_average", format!("{:.2}", self.coefficient_bytes_average())),
+            ("distinct_coefficients", self.distinct_coefficients.to_string()),
+            ("fan_out_max", self.fan_out_max.to_string()),
+            ("witness_variables", self.witness_variables.to_string()),
+            ("witness_bytes", self.witness_bytes.to_string()),
+        ]
+    }
+
+    /// The summary in CSV, with a row for the totals then a row per file.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("file");
+        for (name, _) in self.summary() {
+            csv.push(',');
+            csv.push_str(name);
+        }
+        csv.push('\n');
+
+        let mut write_row = |name: &str, stats: &Stats| {
+            csv.push_str(&csv_field(name));
+            for (_, value) in stats.summary() {
+                csv.push(',');
+                csv.push_str(&value);
+            }
+            csv.push('\n');
+        };
+        write_row("total", self);
+        for file in &self.files {
+            write_row(&file.path.display().to_string(), &file.stats);
+        }
+        csv
+    }
+
+    /// The summary as a text table, with a column for the totals then a column per file,
+    /// followed by the histograms of the totals.
+    pub fn to_table(&self) -> String {
+        let mut columns = vec![("total".to_string(), self.summary())];
+        for file in &self.files {
+            let name = file.path.file_name().unwrap_or(file.path.as_os_str());
+            columns.push((name.to_string_lossy().to_string(), file.stats.summary()));
+        }
+
+        let names = self.summary().into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+        let name_width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+        let widths = columns.iter()
+            .map(|(title, values)| values.iter().map(|(_, value)| value.len()).chain(Some(title.len())).max().unwrap_or(0))
+            .collect::<Vec<_>>();
+
+        let mut table = String::new();
+        write!(table, "{:w$}", "", w = name_width).unwrap();
+        for ((title, _), width) in columns.iter().zip(&widths) {
+            write!(table, "  {:>w$}", title, w = width).unwrap();
+        }
+        table.push('\n');
+        for (i, name) in names.iter().enumerate() {
+            write!(table, "{:w$}", name, w = name_width).unwrap();
+            for ((_, values), width) in columns.iter().zip(&widths) {
+                write!(table, "  {:>w$}", values[i].1, w = width).unwrap();
+            }
+            table.push('\n');
+        }
+
+        if !self.namespaces.is_empty() {
+            write!(table, "\n{}", self.namespaces_to_table()).unwrap();
+        }
+
+        let histograms = [
+            ("Terms in A", &self.lc_lengths_a),
+            ("Terms in B", &self.lc_lengths_b),
+            ("Terms in C", &self.lc_lengths_c),
+            ("Constraints per variable", &self.fan_out),
+        ];
+        for (title, histogram) in histograms.iter() {
+            writeln!(table, "\n{}: count", title).unwrap();
+            for (size, count) in histogram.iter() {
+                writeln!(table, "{:>8}: {}", size, count).unwrap();
+            }
+        }
+        table
+    }
+
+    /// The cost per namespace as a tree, like the report of a profiler.
+    pub fn namespaces_to_table(&self) -> String {
+        let mut paths = self.namespaces.iter()
+            .map(|(path, stats)| (path.split(NAMESPACE_SEPARATOR).collect::<Vec<_>>(), stats))
+            .collect::<Vec<_>>();
+        // Children right after their parent.
+        paths.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let label = |names: &[&str]| format!("{:indent$}{}", "", names.last().unwrap_or(&""), indent = 2 * (names.len() - 1));
+        let label_width = paths.iter().map(|(names, _)| label(names).len()).chain(Some("Namespace".len())).max().unwrap_or(0);
+        let total = self.multiplications.max(1) as f64;
+
+        let mut table = String::new();
+        writeln!(table, "{:lw$}  {:>15}  {:>7}  {:>15}  {:>15}  {:>15}",
+                 "Namespace", "multiplications", "%", "self", "additions", "self", lw = label_width).unwrap();
+        for (names, stats) in paths {
+            writeln!(table, "{:lw$}  {:>15}  {:>6.1}%  {:>15}  {:>15}  {:>15}",
+                     label(&names), stats.multiplications, 100.0 * stats.multiplications as f64 / total,
+                     stats.self_multiplications, stats.additions, stats.self_additions, lw = label_width).unwrap();
+        }
+        table
+    }
+}
+
+/// The width of a little-endian coefficient, without trailing zeros.
+fn coefficient_width(value: &[u8]) -> usize {
+    value.iter().rposition(|byte| *byte != 0).map_or(0, |last| last + 1)
+}
+
+/// Count one more constraint for a variable, moving it to the next bucket of the histogram.
+fn increment_fan_out(histogram: &mut BTreeMap<u64, u64>, max: &mut u64, count: &mut u64) {
+    if *count > 0 {
+        let previous = histogram.entry(*count).or_default();
+        *previous -= 1;
+        if *previous == 0 {
+            histogram.remove(count);
+        }
+    }
+    *count += 1;
+    *histogram.entry(*count).or_default() += 1;
+    *max = (*max).max(*count);
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+
+#[test]
+fn test_stats() {
+    use crate::producers::examples::*;
+
+    let mut stats = Stats::default();
+    stats.ingest_file_messages("header.zkif".as_ref(), &mut vec![
+        Message::Header(example_circuit_header()),
+    ].into_iter());
+    stats.ingest_file_messages("witness.zkif".as_ref(), &mut vec![
+        Message::Witness(example_witness()),
+    ].into_iter());
+    stats.ingest_file_messages("constraints.zkif".as_ref(), &mut vec![
+        Message::ConstraintSystem(example_constraints()),
+    ].into_iter());
+
+    assert_eq!(stats.num_public_inputs, 3);
+    assert_eq!(stats.field_bits, 7);
+    assert_eq!(stats.multiplications, 3);
+    // 1 * (xx + yy) = zz
+    assert_eq!(stats.linear_constraints, 1);
+    assert_eq!(stats.nonzeros_a, 3);
+    assert_eq!(stats.nonzeros_b, 4);
+    assert_eq!(stats.lc_lengths_b, vec![(1, 2), (2, 1)].into_iter().collect());
+    assert_eq!(stats.coefficient_bytes_max, 1);
+    assert_eq!(stats.coefficient_bytes_average(), 1.0);
+    assert_eq!(stats.distinct_coefficients, 1);
+    // x, y, and zz are used once, xx and yy twice.
+    assert_eq!(stats.fan_out, vec![(1, 3), (2, 2)].into_iter().collect());
+    assert_eq!(stats.fan_out_max, 2);
+    assert_eq!(stats.witness_variables, 2);
+    assert_eq!(stats.witness_bytes, 8);
+
+    assert_eq!(stats.files.len(), 3);
+    assert_eq!(stats.files[2].stats.multiplications, 3);
+    assert_eq!(stats.files[2].stats.num_public_inputs, 0);
+
+    let csv = stats.to_csv();
+    assert_eq!(csv.lines().count(), 5);
+    assert!(csv.lines().nth(1).unwrap().starts_with("total,3,2,7,3,1,"));
+    assert!(stats.to_table().contains("constraints.zkif"));
+
+    // The same constraints in another file count on their own in that file.
+    stats.ingest_file_messages("constraints_2.zkif".as_ref(), &mut vec![
+        Message::ConstraintSystem(example_constraints()),
+    ].into_iter());
+    let file_stats = &stats.files[3].stats;
+    assert_eq!(file_stats.distinct_coefficients, 1);
+    assert_eq!(file_stats.fan_out, vec![(1, 3), (2, 2)].into_iter().collect());
+    assert_eq!(file_stats.fan_out_max, 2);
+    assert_eq!(stats.distinct_coefficients, 1);
+    assert_eq!(stats.fan_out, vec![(2, 3), (4, 2)].into_iter().collect());
+    assert_eq!(stats.fan_out_max, 4);
+}
+
+#[test]
+fn test_stats_namespaces() {
+    use crate::producers::examples::*;
+
+    let mut stats = Stats::default();
+    for namespace in &["pythagoras/squares", "pythagoras/squares", "pythagoras", "/other/", "pythagoras/sum/check"] {
+        stats.ingest_message(&Message::ConstraintSystem(example_constraints().with_namespace(namespace)));
+    }
+    stats.ingest_message(&Message::ConstraintSystem(example_constraints()));
+
+    assert_eq!(stats.namespaces.keys().collect::<Vec<_>>(), vec!["other", "pythagoras", "pythagoras/squares", "pythagoras/sum", "pythagoras/sum/check"]);
+    assert_eq!(stats.namespaces["pythagoras"], NamespaceStats {
+        multiplications: 12,
+        additions: 4,
+        self_multiplications: 3,
+        self_additions: 1,
+    });
+    assert_eq!(stats.namespaces["pythagoras/squares"].self_multiplications, 6);
+    assert_eq!(stats.namespaces["pythagoras/sum"].self_multiplications, 0);
+    assert_eq!(stats.namespaces["pythagoras/sum"].multiplications, 3);
+
+    let table = stats.namespaces_to_table();
+    let lines = table.lines().map(|line| line.split_whitespace().take(3).collect::<Vec<_>>()).collect::<Vec<_>>();
+    assert_eq!(lines[1..], [
+        vec!["other", "3", "16.7%"],
+        vec!["pythagoras", "12", "66.7%"],
+        vec!["squares", "6", "33.3%"],
+        vec!["sum", "3", "16.7%"],
+        vec!["check", "3", "16.7%"],
+    ]);
 }
@@ -1,7 +1,11 @@
-use crate::{CircuitHeader, Witness, ConstraintSystem, Variables, Message};
+use crate::{CircuitHeader, Witness, ConstraintSystem, Message};
+use crate::structs::constraints::BilinearConstraint;
+use crate::consumers::parallel::{Batch, map_chunks};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
 
 type Var = u64;
 type Field = BigUint;
@@ -16,6 +20,116 @@ enum Status {
 use Status::*;
 
 
+/// How bad a violation is.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum Severity {
+    /// The statement is not valid.
+    Error,
+    /// The statement is valid but probably not what was intended.
+    Warning,
+}
+
+/// A side of a bilinear constraint A * B = C.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum Side { A, B, C }
+
+/// Where a violation was found. Fields are None when they do not apply.
+#[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Location {
+    /// Index of the message, counting all messages ingested.
+    pub message_index: Option<u64>,
+    /// Index of the constraint within its ConstraintSystem message.
+    pub constraint_index: Option<u64>,
+    /// Which linear combination of the constraint.
+    pub side: Option<Side>,
+}
+
+/// What an encoded field element represents.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum ValueOf {
+    InstanceVariable { id: Var },
+    WitnessVariable { id: Var },
+    Coefficient { id: Var },
+}
+
+impl fmt::Display for ValueOf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueOf::InstanceVariable { id } => write!(f, "value of the instance variable_{}", id),
+            ValueOf::WitnessVariable { id } => write!(f, "value of the witness variable_{}", id),
+            ValueOf::Coefficient { id } => write!(f, "coefficient for variable_{}", id),
+        }
+    }
+}
+
+/// The kinds of problems detected by the Validator.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind")]
+pub enum Violation {
+    /// A message could not be parsed.
+    InvalidMessage { error: String },
+    MissingHeader,
+    MultipleHeaders,
+    /// A message was received before the header.
+    HeaderNotFirst,
+    MissingFieldMaximum,
+    /// A witness variable is used in a constraint, but the prover did not assign it.
+    UnassignedWitness { id: Var },
+    /// A variable was accessed but never defined.
+    UndefinedVariable { id: Var },
+    /// A variable was defined but never used in a constraint.
+    UnusedVariable { id: Var },
+    IdBeyondFreeVariable { id: Var, max: Var },
+    EmptyValue { of: ValueOf },
+    /// The value is larger than the field maximum. Numbers are in decimal.
+    ValueOutOfField { of: ValueOf, value: String, max: String },
+    MultipleDefinition { of: ValueOf },
+}
+
+impl Violation {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Violation::UnusedVariable { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::InvalidMessage { error } => write!(f, "{}", error),
+            Violation::MissingHeader => write!(f, "Missing header."),
+            Violation::MultipleHeaders => write!(f, "Multiple headers."),
+            Violation::HeaderNotFirst => write!(f, "A header must be provided before other messages."),
+            Violation::MissingFieldMaximum => write!(f, "No field_maximum provided."),
+            Violation::UnassignedWitness { id } => write!(f, "The witness variable_{} is used but was not assigned a value", id),
+            Violation::UndefinedVariable { id } => write!(f, "variable_{} was accessed but not defined.", id),
+            Violation::UnusedVariable { id } => write!(f, "variable_{} was defined but not used.", id),
+            Violation::IdBeyondFreeVariable { id, max } => write!(f, "Using variable ID {} beyond what was claimed in the header free_variable_id (should be less than {})", id, max),
+            Violation::EmptyValue { of } => write!(f, "The {} is empty.", of),
+            Violation::ValueOutOfField { of, value, max } => write!(f, "The {} cannot be represented in the field specified in CircuitHeader ({} > {}).", of, value, max),
+            Violation::MultipleDefinition { of } => write!(f, "Multiple definition of the {}", of),
+        }
+    }
+}
+
+/// A violation with its severity and where it was found.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct ViolationReport {
+    #[serde(flatten)]
+    pub violation: Violation,
+    pub severity: Severity,
+    pub location: Location,
+}
+
+impl fmt::Display for ViolationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.violation.fmt(f)
+    }
+}
+
+
 #[derive(Clone, Default)]
 pub struct Validator {
     as_prover: bool,
@@ -25,7 +139,10 @@ pub struct Validator {
     field_maximum: Option<Field>,
     free_variable_id: Option<Var>,
 
-    violations: Vec<String>,
+    location: Location,
+    next_message_index: u64,
+
+    violations: Vec<ViolationReport>,
 }
 
 impl Validator {
@@ -37,10 +154,17 @@ impl Validator {
         Validator { as_prover: true, ..Self::default() }
     }
 
-    pub fn get_violations(mut self) -> Vec<String> {
+    /// The violations as human-readable messages.
+    pub fn get_violations(self) -> Vec<String> {
+        self.get_violation_reports().iter().map(|v| v.to_string()).collect()
+    }
+
+    /// The violations as structured records, in the order they were found.
+    pub fn get_violation_reports(mut self) -> Vec<ViolationReport> {
+        self.location = Location::default();
         self.ensure_all_variables_used();
         if !self.got_header {
-            self.violate("Missing header.");
+            self.violate(Violation::MissingHeader);
         }
         self.violations
     }
@@ -50,14 +174,19 @@ impl Validator {
             Message::Header(h) => self.ingest_header(&h),
             Message::ConstraintSystem(cs) => self.ingest_constraint_system(&cs),
             Message::Witness(w) => self.ingest_witness(&w),
-            Message::Command(_) => {}
-            Message::Err(err) => self.violate(err.to_string()),
+            Message::Command(_) => { self.start_message(); }
+            Message::Err(err) => {
+                self.start_message();
+                self.violate(Violation::InvalidMessage { error: err.to_string() });
+            }
         }
     }
 
     pub fn ingest_header(&mut self, header: &CircuitHeader) {
+        self.start_message();
+
         if self.got_header {
-            self.violate("Multiple headers.");
+            self.violate(Violation::MultipleHeaders);
         }
         self.got_header = true;
 
@@ -65,7 +194,7 @@ impl Validator {
         if let Some(max) = header.field_maximum.as_ref() {
             self.field_maximum = Some(BigUint::from_bytes_le(max));
         } else {
-            self.violate("No field_maximum provided.");
+            self.violate(Violation::MissingFieldMaximum);
         }
 
         // Set a bound on variable count, if provided.
@@ -78,38 +207,111 @@ impl Validator {
 
         // Set instance variable values.
         for var in header.instance_variables.get_variables() {
-            self.define(var.id, var.value, || format!("value of the instance variable_{}", var.id));
+            self.define(var.id, var.value, ValueOf::InstanceVariable { id: var.id });
         }
     }
 
     pub fn ingest_witness(&mut self, witness: &Witness) {
+        self.start_message();
+
         if !self.as_prover { return; }
 
         self.ensure_header();
 
         for var in witness.assigned_variables.get_variables() {
-            self.define(var.id, var.value, || format!("value of the witness variable_{}", var.id));
+            self.define(var.id, var.value, ValueOf::WitnessVariable { id: var.id });
         }
     }
 
     pub fn ingest_constraint_system(&mut self, system: &ConstraintSystem) {
+        self.start_message();
+
         self.ensure_header();
 
-        for constraint in &system.constraints {
-            self.validate_terms(&constraint.linear_combination_a);
-            self.validate_terms(&constraint.linear_combination_b);
-            self.validate_terms(&constraint.linear_combination_c);
+        let checked = self.check_constraints(self.location.message_index, 0, &system.constraints);
+        self.merge(checked);
+    }
+
+    /// Validate consecutive ConstraintSystem messages, with chunks of constraints checked in parallel.
+    /// The violations are the same as with `ingest_constraint_system` on each message in order.
+    pub fn ingest_constraint_systems_parallel(&mut self, systems: &[ConstraintSystem]) {
+        let first_message_index = self.next_message_index;
+
+        let checked = map_chunks(systems, |i, first_index, chunk|
+            self.check_constraints(Some(first_message_index + i as u64), first_index, chunk));
+
+        for chunks in checked {
+            self.start_message();
+            self.ensure_header();
+            for chunk in chunks {
+                self.merge(chunk);
+            }
         }
     }
 
-    fn validate_terms(&mut self, terms: &Variables) {
-        for term in terms.get_variables() {
-            self.ensure_defined(term.id);
-            self.ensure_value_in_field(term.value, || format!("coefficient for variable_{}", term.id));
-            self.set_status(term.id, Used);
+    /// Like `ingest_message`, but check batches of constraints in parallel.
+    pub fn ingest_batch(&mut self, batch: &Batch) {
+        match batch {
+            Batch::Message(msg) => self.ingest_message(msg),
+            Batch::ConstraintSystems(systems) => self.ingest_constraint_systems_parallel(systems),
         }
     }
 
+    /// Check a chunk of constraints without modifying the state of the validator.
+    fn check_constraints(&self, message_index: Option<u64>, first_index: u64, constraints: &[BilinearConstraint]) -> CheckedChunk {
+        let mut checked = CheckedChunk::default();
+
+        for (i, constraint) in constraints.iter().enumerate() {
+            for (side, terms) in &[
+                (Side::A, &constraint.linear_combination_a),
+                (Side::B, &constraint.linear_combination_b),
+                (Side::C, &constraint.linear_combination_c),
+            ] {
+                let location = Location {
+                    message_index,
+                    constraint_index: Some(first_index + i as u64),
+                    side: Some(*side),
+                };
+
+                for term in terms.get_variables() {
+                    let first_use = checked.used.insert(term.id);
+                    let undefined = self.variables.get(&term.id).cloned().unwrap_or(Undefined) == Undefined;
+                    if first_use && undefined {
+                        for violation in self.check_defined(term.id) {
+                            checked.violations.push((report(violation, &location), Some(term.id)));
+                        }
+                    }
+                    for violation in self.check_value_in_field(term.value, ValueOf::Coefficient { id: term.id }) {
+                        checked.violations.push((report(violation, &location), None));
+                    }
+                }
+            }
+        }
+        checked
+    }
+
+    /// Collect the violations of a chunk, and mark its variables as used.
+    fn merge(&mut self, checked: CheckedChunk) {
+        for (report, first_use_of) in checked.violations {
+            // Undefined variables are reported at their first use only.
+            if let Some(id) = first_use_of {
+                if self.variables.get(&id) == Some(&Used) { continue; }
+            }
+            self.violations.push(report);
+        }
+        for id in checked.used {
+            self.set_status(id, Used);
+        }
+    }
+
+    fn start_message(&mut self) {
+        self.location = Location {
+            message_index: Some(self.next_message_index),
+            ..Location::default()
+        };
+        self.next_message_index += 1;
+    }
+
     fn status(&mut self, id: Var) -> Status {
         *self.variables.entry(id).or_insert(Undefined)
     }
@@ -118,68 +320,94 @@ impl Validator {
         self.variables.insert(id, status);
     }
 
-    fn define(&mut self, id: Var, value: &[u8], name: impl Fn() -> String) {
-        self.ensure_id_bound(id);
-        self.ensure_value_in_field(value, &name);
+    fn define(&mut self, id: Var, value: &[u8], of: ValueOf) {
+        for violation in self.check_id_bound(id).into_iter().chain(self.check_value_in_field(value, of)) {
+            self.violate(violation);
+        }
         if self.status(id) != Undefined {
-            self.violate(format!("Multiple definition of the {}", name()));
+            self.violate(Violation::MultipleDefinition { of });
         }
         self.set_status(id, Defined);
     }
 
-    fn ensure_defined(&mut self, id: Var) {
-        if self.status(id) == Undefined {
-            self.ensure_id_bound(id);
-
-            if self.as_prover {
-                self.violate(format!("The witness variable_{} is used but was not assigned a value", id));
-            }
+    /// The violations for the use of a variable that is not defined.
+    fn check_defined(&self, id: Var) -> Vec<Violation> {
+        let mut violations = self.check_id_bound(id).into_iter().collect::<Vec<_>>();
+        if self.as_prover {
+            violations.push(Violation::UnassignedWitness { id });
         }
+        violations
     }
 
-    fn ensure_id_bound(&mut self, id: Var) {
-        if let Some(max) = self.free_variable_id {
-            if id >= max {
-                self.violate(format!("Using variable ID {} beyond what was claimed in the header free_variable_id (should be less than {})", id, max));
-            }
+    fn check_id_bound(&self, id: Var) -> Option<Violation> {
+        match self.free_variable_id {
+            Some(max) if id >= max => Some(Violation::IdBeyondFreeVariable { id, max }),
+            _ => None,
         }
     }
 
-    fn ensure_value_in_field(&mut self, value: &[u8], name: impl Fn() -> String) {
+    fn check_value_in_field(&self, value: &[u8], of: ValueOf) -> Vec<Violation> {
+        let mut violations = vec![];
         if value.len() == 0 {
-            self.violate(format!("The {} is empty.", name()));
+            violations.push(Violation::EmptyValue { of });
         }
 
         if let Some(max) = self.field_maximum.as_ref() {
             let int = &Field::from_bytes_le(value);
             if int > max {
-                let msg = format!("The {} cannot be represented in the field specified in CircuitHeader ({} > {}).", name(), int, max);
-                self.violate(msg);
+                violations.push(Violation::ValueOutOfField {
+                    of,
+                    value: int.to_string(),
+                    max: max.to_string(),
+                });
             }
         }
+        violations
     }
 
     fn ensure_header(&mut self) {
         if !self.got_header {
-            self.violate("A header must be provided before other messages.");
+            self.violate(Violation::HeaderNotFirst);
         }
     }
 
     fn ensure_all_variables_used(&mut self) {
-        for (id, status) in self.variables.iter() {
-            match *status {
-                Undefined => self.violations.push(format!("variable_{} was accessed but not defined.", id)),
-                Defined => self.violations.push(format!("variable_{} was defined but not used.", id)),
+        let mut ids = self.variables.keys().cloned().collect::<Vec<Var>>();
+        ids.sort_unstable();
+
+        for id in ids {
+            match self.variables[&id] {
+                Undefined => self.violate(Violation::UndefinedVariable { id }),
+                // The constant one does not need to be used.
+                Defined if id == 0 => { /* ok */ }
+                Defined => self.violate(Violation::UnusedVariable { id }),
                 Used => { /* ok */ }
             }
         }
     }
 
-    fn violate(&mut self, msg: impl Into<String>) {
-        self.violations.push(msg.into());
+    fn violate(&mut self, violation: Violation) {
+        let report = report(violation, &self.location);
+        self.violations.push(report);
     }
 }
 
+fn report(violation: Violation, location: &Location) -> ViolationReport {
+    ViolationReport {
+        severity: violation.severity(),
+        violation,
+        location: location.clone(),
+    }
+}
+
+/// The violations found in a chunk of constraints, and the variables it uses.
+#[derive(Default)]
+struct CheckedChunk {
+    /// Each violation, with the variable whose first use caused it, if any.
+    violations: Vec<(ViolationReport, Option<Var>)>,
+    used: HashSet<Var>,
+}
+
 
 #[test]
 fn test_validator() -> crate::Result<()> {
@@ -201,3 +429,71 @@ fn test_validator() -> crate::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_validator_reports() -> crate::Result<()> {
+    use crate::Variables;
+    use crate::producers::examples::*;
+
+    let header = example_circuit_header();
+
+    let mut validator = Validator::new_as_verifier();
+    validator.ingest_header(&header);
+    validator.ingest_header(&header);
+
+    let reports = validator.get_violation_reports();
+    let violations = reports.iter().map(|r| r.violation.clone()).collect::<Vec<_>>();
+
+    assert_eq!(violations, vec![
+        Violation::MultipleHeaders,
+        Violation::MultipleDefinition { of: ValueOf::InstanceVariable { id: 1 } },
+        Violation::MultipleDefinition { of: ValueOf::InstanceVariable { id: 2 } },
+        Violation::MultipleDefinition { of: ValueOf::InstanceVariable { id: 3 } },
+        Violation::UnusedVariable { id: 1 },
+        Violation::UnusedVariable { id: 2 },
+        Violation::UnusedVariable { id: 3 },
+    ]);
+    assert_eq!(reports[0].location, Location { message_index: Some(1), constraint_index: None, side: None });
+    assert_eq!(reports[3].to_string(), "Multiple definition of the value of the instance variable_3");
+    assert_eq!(reports[3].severity, Severity::Error);
+    assert_eq!(reports[4].severity, Severity::Warning);
+    assert_eq!(reports[4].location, Location::default());
+
+    // Drop y * y = yy, and use variable 6 which is beyond free_variable_id, with an out-of-field coefficient.
+    let mut constraints = example_constraints();
+    constraints.constraints.remove(1);
+    constraints.constraints[1].linear_combination_b = Variables {
+        variable_ids: vec![4, 6],
+        values: Some(vec![1, 200]),
+        info: None,
+    };
+
+    let mut validator = Validator::new_as_prover();
+    validator.ingest_header(&header);
+    validator.ingest_witness(&example_witness());
+    validator.ingest_constraint_system(&constraints);
+
+    let reports = validator.get_violation_reports();
+    let violations = reports.iter().map(|r| r.violation.clone()).collect::<Vec<_>>();
+
+    assert_eq!(violations, vec![
+        Violation::IdBeyondFreeVariable { id: 6, max: 6 },
+        Violation::UnassignedWitness { id: 6 },
+        Violation::ValueOutOfField {
+            of: ValueOf::Coefficient { id: 6 },
+            value: "200".to_string(),
+            max: "100".to_string(),
+        },
+        Violation::UnusedVariable { id: 2 },
+        Violation::UnusedVariable { id: 5 },
+    ]);
+    let location = Location { message_index: Some(2), constraint_index: Some(1), side: Some(Side::B) };
+    assert_eq!(reports[0].location, location);
+    assert_eq!(reports[2].location, location);
+
+    let json = serde_json::to_string(&reports[0])?;
+    assert_eq!(json, r#"{"kind":"IdBeyondFreeVariable","id":6,"max":6,"severity":"Error","location":{"message_index":2,"constraint_index":1,"side":"B"}}"#);
+    assert_eq!(serde_json::from_str::<ViolationReport>(&json)?, reports[0]);
+
+    Ok(())
+}
@@ -0,0 +1,518 @@
+//! Verify untrusted messages before reading them.
+//!
+//! The generated FlatBuffers accessors assume a well-formed buffer and panic, or worse,
+//! on truncated or hostile input. The functions below check every offset, vector and string
+//! reachable from the root against the bounds of the buffer, following the zkInterface schema,
+//! so that the accessors can be used safely afterwards.
+//! This includes the alignment of scalars, relative to the start of the message. The accessors
+//! also rely on the message starting at an address aligned to `MESSAGE_ALIGN`; a message found at
+//! another position, for instance after a message whose size is not a multiple of 8, must be copied first.
+
+use flatbuffers::{SIZE_UOFFSET, VOffsetT};
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::str::from_utf8;
+
+use crate::zkinterface_generated::zkinterface as fb;
+use crate::Result;
+
+/// The largest message accepted, including its size prefix. This is the limit of FlatBuffers.
+pub const MAX_MESSAGE_SIZE: usize = (1 << 31) - 1;
+
+/// The alignment in memory of the largest scalars of the schema, such as variable IDs.
+pub const MESSAGE_ALIGN: usize = 8;
+
+const FILE_IDENTIFIER: &[u8] = b"zkif";
+
+/// How strictly to verify messages.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct VerifierOptions {
+    /// Reject messages larger than this, including the size prefix.
+    pub max_message_size: usize,
+    /// Require the "zkif" file identifier after the root offset.
+    /// Off by default because some producers, such as the C++ gadgets, do not write it.
+    pub require_identifier: bool,
+}
+
+impl Default for VerifierOptions {
+    fn default() -> Self {
+        VerifierOptions {
+            max_message_size: MAX_MESSAGE_SIZE,
+            require_identifier: false,
+        }
+    }
+}
+
+/// A message that does not follow the format.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvalidMessage {
+    /// Position in the message where the problem was found.
+    pub offset: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid message at byte {}: {}", self.offset, self.reason)
+    }
+}
+
+impl Error for InvalidMessage {}
+
+/// Verify a size-prefixed message, then return its root.
+///
+/// # Example
+/// ```
+/// use zkinterface::consumers::verifier::read_root;
+/// use zkinterface::producers::examples::example_witness;
+///
+/// let mut buf = Vec::<u8>::new();
+/// example_witness().write_into(&mut buf).unwrap();
+/// assert!(read_root(&buf).unwrap().message_as_witness().is_some());
+///
+/// // Truncated messages are rejected.
+/// assert!(read_root(&buf[..buf.len() - 1]).is_err());
+/// ```
+pub fn read_root(buf: &[u8]) -> Result<fb::Root<'_>> {
+    read_root_with(buf, &VerifierOptions::default())
+}
+
+/// Like `read_root`, with custom options.
+pub fn read_root_with<'a>(buf: &'a [u8], options: &VerifierOptions) -> Result<fb::Root<'a>> {
+    let size = verify_message_with(buf, options)?;
+    Ok(fb::get_size_prefixed_root_as_root(&buf[..size]))
+}
+
+/// Verify a size-prefixed message at the start of the buffer.
+/// Return the size of the message including its prefix.
+/// The buffer may contain more data after the message.
+pub fn verify_message(buf: &[u8]) -> Result<usize> {
+    verify_message_with(buf, &VerifierOptions::default())
+}
+
+/// Like `verify_message`, with custom options.
+pub fn verify_message_with(buf: &[u8], options: &VerifierOptions) -> Result<usize> {
+    let size = verify_size_prefix(buf, options)?;
+    let buf = &buf[..size];
+
+    if options.require_identifier && buf.get(2 * SIZE_UOFFSET..3 * SIZE_UOFFSET) != Some(FILE_IDENTIFIER) {
+        return Err(invalid(2 * SIZE_UOFFSET, "missing file identifier \"zkif\""));
+    }
+
+    if !is_aligned(buf) {
+        return Err(invalid(0, format!("the message is not aligned to {} bytes in memory, copy it first", MESSAGE_ALIGN)));
+    }
+
+    let mut verifier = Verifier { buf, budget: buf.len() };
+    let root = verifier.follow_offset(SIZE_UOFFSET)?;
+    verifier.root(root)?;
+    Ok(size)
+}
+
+/// Read the type of a message from its root, without verifying the rest of the message.
+/// The buffer may hold only the start of the message; an error is returned if the root is not in it.
+/// Unlike `read_root`, the buffer may be anywhere in memory.
+pub fn read_message_type(buf: &[u8]) -> Result<fb::Message> {
+    if buf.len() < 2 * SIZE_UOFFSET {
+        return Err(invalid(buf.len(), "truncated message"));
+    }
+    let mut verifier = Verifier { buf, budget: buf.len() };
+    let root = verifier.follow_offset(SIZE_UOFFSET)?;
+    let table = verifier.table(root)?;
+
+    let message_type = match verifier.field(&table, fb::Root::VT_MESSAGE_TYPE, 1)? {
+        Some(loc) => buf[loc],
+        None => fb::Message::NONE as u8,
+    };
+    fb::ENUM_VALUES_MESSAGE.get(message_type as usize).cloned()
+        .ok_or_else(|| invalid(table.loc, format!("unknown message type {}", message_type)))
+}
+
+/// Whether a message starts at an address aligned to `MESSAGE_ALIGN`, so that it can be read in place.
+/// The buffers of a `Vec<u8>` are aligned in practice.
+pub fn is_aligned(buf: &[u8]) -> bool {
+    buf.as_ptr() as usize & (MESSAGE_ALIGN - 1) == 0
+}
+
+/// Check the size prefix of a message and return its size including the prefix.
+pub fn verify_size_prefix(buf: &[u8], options: &VerifierOptions) -> Result<usize> {
+    if buf.len() < SIZE_UOFFSET {
+        return Err(invalid(0, "missing size prefix"));
+    }
+    let size = SIZE_UOFFSET + u32::from_le_bytes(buf[..SIZE_UOFFSET].try_into().unwrap()) as usize;
+    if size > options.max_message_size {
+        return Err(invalid(0, format!("message of {} bytes is larger than the maximum of {} bytes", size, options.max_message_size)));
+    }
+    if size > buf.len() {
+        return Err(invalid(buf.len(), format!("truncated message, expected {} bytes", size)));
+    }
+    // The root offset.
+    if size < 2 * SIZE_UOFFSET {
+        return Err(invalid(0, "message too small"));
+    }
+    Ok(size)
+}
+
+fn invalid(offset: usize, reason: impl Into<String>) -> Box<dyn Error> {
+    Box::new(InvalidMessage { offset, reason: reason.into() })
+}
+
+/// A table whose header has been verified.
+struct Table {
+    loc: usize,
+    vtable: usize,
+    vtable_size: usize,
+    inline_size: usize,
+}
+
+struct Verifier<'a> {
+    buf: &'a [u8],
+    // Bytes of tables, vectors and strings left to visit. Since these do not overlap in valid
+    // messages, this bounds the work on messages that point many times to the same data.
+    budget: usize,
+}
+
+impl<'a> Verifier<'a> {
+    fn check_range(&self, loc: usize, len: usize) -> Result<()> {
+        match loc.checked_add(len) {
+            Some(end) if end <= self.buf.len() => Ok(()),
+            _ => Err(invalid(loc, format!("{} bytes out of bounds", len))),
+        }
+    }
+
+    fn check_aligned(&self, loc: usize, align: usize) -> Result<()> {
+        // Relative to the start of the message, which is aligned to MESSAGE_ALIGN.
+        if loc & (align - 1) != 0 {
+            return Err(invalid(loc, "misaligned data"));
+        }
+        Ok(())
+    }
+
+    fn consume(&mut self, loc: usize, len: usize) -> Result<()> {
+        if len > self.budget {
+            return Err(invalid(loc, "the message refers to more data than it contains"));
+        }
+        self.budget -= len;
+        Ok(())
+    }
+
+    fn read_u16(&self, loc: usize) -> Result<usize> {
+        self.check_range(loc, 2)?;
+        self.check_aligned(loc, 2)?;
+        Ok(u16::from_le_bytes(self.buf[loc..loc + 2].try_into().unwrap()) as usize)
+    }
+
+    fn read_u32(&self, loc: usize) -> Result<usize> {
+        self.check_range(loc, 4)?;
+        self.check_aligned(loc, 4)?;
+        Ok(u32::from_le_bytes(self.buf[loc..loc + 4].try_into().unwrap()) as usize)
+    }
+
+    fn follow_offset(&self, loc: usize) -> Result<usize> {
+        let offset = self.read_u32(loc)?;
+        let target = loc + offset;
+        self.check_range(target, 0)?;
+        Ok(target)
+    }
+
+    fn table(&mut self, loc: usize) -> Result<Table> {
+        let soffset = self.read_u32(loc)? as i32 as i64;
+        let vtable = loc as i64 - soffset;
+        if vtable < 0 {
+            return Err(invalid(loc, "vtable out of bounds"));
+        }
+        let vtable = vtable as usize;
+
+        let vtable_size = self.read_u16(vtable)?;
+        let inline_size = self.read_u16(vtable + 2)?;
+        if vtable_size < 4 || vtable_size % 2 != 0 {
+            return Err(invalid(vtable, "invalid vtable size"));
+        }
+        self.check_range(vtable, vtable_size)?;
+        if inline_size < 4 {
+            return Err(invalid(vtable, "invalid table size"));
+        }
+        self.check_range(loc, inline_size)?;
+        self.consume(loc, inline_size)?;
+
+        Ok(Table { loc, vtable, vtable_size, inline_size })
+    }
+
+    /// The location of a field, if present.
+    fn field(&self, table: &Table, vt_offset: VOffsetT, size: usize) -> Result<Option<usize>> {
+        let vt_offset = vt_offset as usize;
+        if vt_offset >= table.vtable_size {
+            return Ok(None);
+        }
+        let field_offset = self.read_u16(table.vtable + vt_offset)?;
+        if field_offset == 0 {
+            return Ok(None);
+        }
+        if field_offset + size > table.inline_size {
+            return Err(invalid(table.loc + field_offset, "field out of its table"));
+        }
+        self.check_aligned(table.loc + field_offset, size)?;
+        Ok(Some(table.loc + field_offset))
+    }
+
+    /// The location of the object referred to by an offset field, if present.
+    fn offset_field(&self, table: &Table, vt_offset: VOffsetT) -> Result<Option<usize>> {
+        match self.field(table, vt_offset, SIZE_UOFFSET)? {
+            Some(loc) => Ok(Some(self.follow_offset(loc)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn bool_field(&self, table: &Table, vt_offset: VOffsetT) -> Result<()> {
+        if let Some(loc) = self.field(table, vt_offset, 1)? {
+            if self.buf[loc] > 1 {
+                return Err(invalid(loc, "invalid boolean"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Verify a vector of scalars and return its length.
+    fn vector(&mut self, loc: usize, elem_size: usize) -> Result<usize> {
+        let len = self.read_u32(loc)?;
+        let byte_len = len.checked_mul(elem_size)
+            .ok_or_else(|| invalid(loc, "vector too long"))?;
+        self.check_range(loc + SIZE_UOFFSET, byte_len)?;
+        self.check_aligned(loc + SIZE_UOFFSET, elem_size)?;
+        self.consume(loc, SIZE_UOFFSET + byte_len)?;
+        Ok(len)
+    }
+
+    fn string(&mut self, loc: usize) -> Result<()> {
+        let len = self.vector(loc, 1)?;
+        let bytes = &self.buf[loc + SIZE_UOFFSET..loc + SIZE_UOFFSET + len];
+        from_utf8(bytes).map_err(|_| invalid(loc, "invalid UTF-8 string"))?;
+        Ok(())
+    }
+
+    fn table_vector(&mut self, loc: usize, verify_table: fn(&mut Self, usize) -> Result<()>) -> Result<()> {
+        let len = self.vector(loc, SIZE_UOFFSET)?;
+        for i in 0..len {
+            let table = self.follow_offset(loc + SIZE_UOFFSET * (i + 1))?;
+            verify_table(self, table)?;
+        }
+        Ok(())
+    }
+
+    fn root(&mut self, loc: usize) -> Result<()> {
+        let table = self.table(loc)?;
+
+        let message_type = match self.field(&table, fb::Root::VT_MESSAGE_TYPE, 1)? {
+            Some(loc) => self.buf[loc],
+            None => fb::Message::NONE as u8,
+        };
+        if message_type > fb::ENUM_MAX_MESSAGE {
+            return Err(invalid(table.loc, format!("unknown message type {}", message_type)));
+        }
+
+        if let Some(message) = self.offset_field(&table, fb::Root::VT_MESSAGE)? {
+            match message_type {
+                t if t == fb::Message::CircuitHeader as u8 => self.circuit_header(message)?,
+                t if t == fb::Message::ConstraintSystem as u8 => self.constraint_system(message)?,
+                t if t == fb::Message::Witness as u8 => self.witness(message)?,
+                t if t == fb::Message::Command as u8 => self.command(message)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn circuit_header(&mut self, loc: usize) -> Result<()> {
+        let table = self.table(loc)?;
+        if let Some(vars) = self.offset_field(&table, fb::CircuitHeader::VT_INSTANCE_VARIABLES)? {
+            self.variables(vars)?;
+        }
+        self.field(&table, fb::CircuitHeader::VT_FREE_VARIABLE_ID, 8)?;
+        if let Some(max) = self.offset_field(&table, fb::CircuitHeader::VT_FIELD_MAXIMUM)? {
+            self.vector(max, 1)?;
+        }
+        if let Some(conf) = self.offset_field(&table, fb::CircuitHeader::VT_CONFIGURATION)? {
+            self.table_vector(conf, Self::key_value)?;
+        }
+        Ok(())
+    }
+
+    fn constraint_system(&mut self, loc: usize) -> Result<()> {
+        let table = self.table(loc)?;
+        if let Some(constraints) = self.offset_field(&table, fb::ConstraintSystem::VT_CONSTRAINTS)? {
+            self.table_vector(constraints, Self::bilinear_constraint)?;
+        }
+        if let Some(info) = self.offset_field(&table, fb::ConstraintSystem::VT_INFO)? {
+            self.table_vector(info, Self::key_value)?;
+        }
+        Ok(())
+    }
+
+    fn witness(&mut self, loc: usize) -> Result<()> {
+        let table = self.table(loc)?;
+        if let Some(vars) = self.offset_field(&table, fb::Witness::VT_ASSIGNED_VARIABLES)? {
+            self.variables(vars)?;
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, loc: usize) -> Result<()> {
+        let table = self.table(loc)?;
+        self.bool_field(&table, fb::Command::VT_CONSTRAINTS_GENERATION)?;
+        self.bool_field(&table, fb::Command::VT_WITNESS_GENERATION)?;
+        if let Some(params) = self.offset_field(&table, fb::Command::VT_PARAMETERS)? {
+            self.table_vector(params, Self::key_value)?;
+        }
+        Ok(())
+    }
+
+    fn bilinear_constraint(&mut self, loc: usize) -> Result<()> {
+        let table = self.table(loc)?;
+        for vt_offset in &[
+            fb::BilinearConstraint::VT_LINEAR_COMBINATION_A,
+            fb::BilinearConstraint::VT_LINEAR_COMBINATION_B,
+            fb::BilinearConstraint::VT_LINEAR_COMBINATION_C,
+        ] {
+            if let Some(vars) = self.offset_field(&table, *vt_offset)? {
+                self.variables(vars)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn variables(&mut self, loc: usize) -> Result<()> {
+        let table = self.table(loc)?;
+        if let Some(ids) = self.offset_field(&table, fb::Variables::VT_VARIABLE_IDS)? {
+            self.vector(ids, 8)?;
+        }
+        if let Some(values) = self.offset_field(&table, fb::Variables::VT_VALUES)? {
+            self.vector(values, 1)?;
+        }
+        if let Some(info) = self.offset_field(&table, fb::Variables::VT_INFO)? {
+            self.table_vector(info, Self::key_value)?;
+        }
+        Ok(())
+    }
+
+    fn key_value(&mut self, loc: usize) -> Result<()> {
+        let table = self.table(loc)?;
+        if let Some(key) = self.offset_field(&table, fb::KeyValue::VT_KEY)? {
+            self.string(key)?;
+        }
+        if let Some(data) = self.offset_field(&table, fb::KeyValue::VT_DATA)? {
+            self.vector(data, 1)?;
+        }
+        if let Some(text) = self.offset_field(&table, fb::KeyValue::VT_TEXT)? {
+            self.string(text)?;
+        }
+        self.field(&table, fb::KeyValue::VT_NUMBER, 8)?;
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_verify_messages() {
+    use crate::producers::examples::*;
+    use crate::{Command, KeyValue};
+
+    let mut header = example_circuit_header();
+    header.configuration = Some(vec![
+        KeyValue::from(("text", "value")),
+        KeyValue::from(("data", vec![1, 2, 3])),
+        KeyValue::from(("number", 4)),
+    ]);
+
+    let mut buf = vec![];
+    header.write_into(&mut buf).unwrap();
+    example_witness().write_into(&mut buf).unwrap();
+    example_constraints().write_into(&mut buf).unwrap();
+    Command::default().write_into(&mut buf).unwrap();
+
+    let strict = VerifierOptions { require_identifier: true, ..VerifierOptions::default() };
+    let mut rest = &buf[..];
+    let mut count = 0;
+    while !rest.is_empty() {
+        let size = verify_message_with(rest, &strict).unwrap();
+        rest = &rest[size..];
+        count += 1;
+    }
+    assert_eq!(count, 4);
+
+    // Truncations anywhere are rejected without panicking.
+    let mut msg = vec![];
+    header.write_into(&mut msg).unwrap();
+    for len in 0..msg.len() {
+        assert!(verify_message(&msg[..len]).is_err());
+    }
+    let mut reader = crate::Reader::new();
+    assert!(reader.push_message(msg[..msg.len() - 4].to_vec()).is_err());
+    assert!(reader.read_from(&msg[..msg.len() - 4]).is_err());
+    assert!(reader.push_message(msg.clone()).is_ok());
+
+    // Corruption of any single byte does not cause a panic.
+    for i in 0..msg.len() {
+        for &b in &[0x00, 0x01, 0x7f, 0x80, 0xff] {
+            let mut corrupt = msg.clone();
+            corrupt[i] = b;
+            if let Ok(root) = read_root(&corrupt) {
+                let _ = crate::Message::from(root);
+            }
+        }
+    }
+
+    // Random bytes after a valid size prefix.
+    use rand::{Rng, SeedableRng};
+    let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+    for _ in 0..1000 {
+        let len = rng.gen_range(8, 256);
+        let mut random = vec![0u8; len];
+        rng.fill(&mut random[..]);
+        random[..4].copy_from_slice(&(len as u32 - 4).to_le_bytes());
+        let _ = crate::Message::from(&random[..]);
+    }
+
+    // Size limit.
+    let small = VerifierOptions { max_message_size: 16, ..VerifierOptions::default() };
+    assert!(verify_message_with(&msg, &small).is_err());
+
+    // File identifier.
+    let mut no_ident = msg.clone();
+    no_ident[8..12].copy_from_slice(b"xxxx");
+    assert!(verify_message(&no_ident).is_ok());
+    assert!(verify_message_with(&no_ident, &strict).is_err());
+}
+
+#[test]
+fn test_unaligned_messages() {
+    use crate::producers::examples::*;
+    use crate::{Command, KeyValue, Reader};
+
+    // A command whose size is not a multiple of 8, followed by constraints.
+    let mut buf = (0..8).map(|len| {
+        let mut buf = vec![];
+        Command {
+            constraints_generation: true,
+            witness_generation: false,
+            parameters: Some(vec![KeyValue::from(("name", "x".repeat(len).as_str()))]),
+        }.write_into(&mut buf).unwrap();
+        buf
+    }).find(|buf| buf.len() % MESSAGE_ALIGN == 4).unwrap();
+    let offset = buf.len();
+    example_constraints().write_into(&mut buf).unwrap();
+
+    // In place, the constraints are not aligned in memory, but they are valid.
+    let err = verify_message(&buf[offset..]).unwrap_err();
+    assert!(err.to_string().contains("not aligned"), "{}", err);
+    assert_eq!(read_message_type(&buf[offset..]).unwrap(), fb::Message::ConstraintSystem);
+    let copy = buf[offset..].to_vec();
+    assert!(read_root(&copy).is_ok());
+
+    let mut reader = Reader::new();
+    reader.push_message(buf).unwrap();
+    let roots = (&reader).into_iter().collect::<Vec<_>>();
+    assert_eq!(roots.len(), 2);
+    assert!(roots[0].message_as_command().is_some());
+    assert_eq!(roots[1].message_as_constraint_system().unwrap().constraints().unwrap().len(), 3);
+}
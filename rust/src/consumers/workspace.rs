@@ -1,9 +1,17 @@
 use std::path::{PathBuf, Path};
-use std::fs::{File, read_dir};
+use std::fs::{File, read_dir, read_to_string};
 use std::iter;
+use std::slice;
 use std::io::{Read, stdin};
 use std::ffi::OsStr;
-use crate::consumers::reader::read_buffer;
+use std::error::Error;
+use std::fmt;
+use flatbuffers::SIZE_UOFFSET;
+use crate::consumers::reader::{read_buffer, read_size_prefix};
+use crate::consumers::verifier::{read_root, read_message_type};
+use crate::consumers::index::{WorkspaceIndex, MessageType};
+use crate::zkinterface_generated::zkinterface as fb;
+use crate::structs::constraints::BilinearConstraint;
 use crate::{Result, Message, Messages};
 
 
@@ -45,6 +53,7 @@ use crate::{Result, Message, Messages};
 pub struct Workspace {
     paths: Vec<PathBuf>,
     stdin: bool,
+    warnings: Vec<String>,
 }
 
 impl Workspace {
@@ -52,76 +61,392 @@ impl Workspace {
         Self::from_dirs_and_files(&[path.to_path_buf()])
     }
 
+    /// Find the files in directories, or read them from a manifest; see `order_workspace_files`.
     pub fn from_dirs_and_files(paths: &[PathBuf]) -> Result<Self> {
-        let all_files = list_workspace_files(paths)?;
-        Ok(Self::from_filenames(all_files))
+        let (all_files, warnings) = order_workspace_files(paths)?;
+        Ok(Workspace { warnings, ..Self::from_ordered_filenames(all_files) })
     }
 
     pub fn from_filenames(mut paths: Vec<PathBuf>) -> Self {
+        let mut warnings = vec![];
+        if paths != vec![PathBuf::from("-")] {
+            warnings = sort_workspace_files(&mut paths);
+        }
+        Workspace { warnings, ..Self::from_ordered_filenames(paths) }
+    }
+
+    /// Read the given files in the given order, without looking into them first.
+    pub fn from_ordered_filenames(paths: Vec<PathBuf>) -> Self {
         if paths == vec![PathBuf::from("-")] {
-            Workspace { paths: vec![], stdin: true }
+            Workspace { paths: vec![], stdin: true, warnings: vec![] }
+        } else {
+            Workspace { paths, stdin: false, warnings: vec![] }
+        }
+    }
+
+    /// Warnings about the files found, such as several files starting with a header.
+    /// Only the first message of each file is looked at; see `sort_workspace_files`.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Iterate over messages. Errors are converted to `Message::Err`, with their position;
+    /// see `try_iter_messages`.
+    pub fn iter_messages<'w>(&'w self) -> impl Iterator<Item=Message> + 'w {
+        self.try_iter_messages().map(|msg|
+            msg.unwrap_or_else(|err| Message::Err(err.to_string())))
+    }
+
+    /// Iterate over messages, or errors with the position where they happened.
+    /// After an error, reading continues with the next file.
+    pub fn try_iter_messages<'w>(&'w self) -> impl Iterator<Item=std::result::Result<Message, ReadError>> + 'w {
+        let buffers: Box<dyn Iterator<Item=ReadResult<Buffer>>> = if self.stdin {
+            Box::new(try_iterate_stream(stdin(), None))
         } else {
-            paths.sort();
-            paths.sort_by_key(|path| {
-                let name = path.file_name().unwrap().to_str().unwrap();
+            Box::new(self.paths.iter().flat_map(|path| try_iterate_file(path)))
+        };
+
+        buffers.map(|buffer| {
+            let buffer = buffer?;
+            match read_root(&buffer.data) {
+                Ok(root) => Ok(Message::from(root)),
+                Err(err) => Err(ReadError {
+                    path: buffer.path,
+                    offset: buffer.offset,
+                    invalid_message: true,
+                    reason: err.to_string(),
+                }),
+            }
+        })
+    }
+
+    pub fn read_all_messages(&self) -> Messages {
+        Messages::from(self)
+    }
+
+    /// Like `read_all_messages`, but fail on the first error.
+    pub fn try_read_all_messages(&self) -> Result<Messages> {
+        let mut messages = Messages::default();
+        for msg in self.try_iter_messages() {
+            match msg? {
+                Message::Header(h) => messages.circuit_headers.push(h),
+                Message::ConstraintSystem(cs) => messages.constraint_systems.push(cs),
+                Message::Witness(w) => messages.witnesses.push(w),
+                Message::Command(_) => {}
+                Message::Err(_) => {}
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Index the files, and write the index of each file next to it.
+    pub fn write_index(&self) -> Result<WorkspaceIndex> {
+        let index = WorkspaceIndex::build(self.indexable_paths()?)?;
+        index.write()?;
+        Ok(index)
+    }
+
+    /// Read the index written by `write_index`.
+    pub fn read_index(&self) -> Result<WorkspaceIndex> {
+        WorkspaceIndex::read(self.indexable_paths()?)
+    }
+
+    /// Read a single constraint, counting constraints of all files in order.
+    pub fn get_constraint(&self, index: &WorkspaceIndex, constraint_index: u64) -> Result<BilinearConstraint> {
+        index.read_constraint(constraint_index)
+    }
+
+    /// Read the value of an instance or witness variable, or None if it has no value.
+    pub fn get_variable_value(&self, index: &WorkspaceIndex, variable_id: u64) -> Result<Option<Vec<u8>>> {
+        index.read_variable_value(variable_id)
+    }
+
+    fn indexable_paths(&self) -> Result<&[PathBuf]> {
+        if self.stdin {
+            return Err("Cannot index stdin".into());
+        }
+        Ok(&self.paths)
+    }
+}
+
+/// The name of a file listing the files of a workspace in the order to read them.
+///
+/// Each line is a path relative to the directory of the manifest.
+/// Empty lines and lines starting with # are ignored.
+pub const MANIFEST_FILENAME: &str = "zkif_manifest.txt";
+
+/// List the files of a workspace in the order to read them.
+///
+/// A directory containing a manifest, or the path of a manifest, gives the files listed in it.
+/// If there is no manifest, the files are sorted with `sort_workspace_files`.
+///
+/// Returns the files, and warnings about their content.
+pub fn order_workspace_files(paths: &[PathBuf]) -> Result<(Vec<PathBuf>, Vec<String>)> {
+    let manifests = paths.iter().map(|path| find_manifest(path)).collect::<Vec<_>>();
+
+    if manifests.iter().all(Option::is_none) {
+        let mut all_files = list_workspace_files(paths)?;
+        let mut warnings = vec![];
+        if all_files != vec![PathBuf::from("-")] {
+            warnings = sort_workspace_files(&mut all_files);
+        }
+        return Ok((all_files, warnings));
+    }
+
+    let mut typed = vec![];
+    for (path, manifest) in paths.iter().zip(manifests) {
+        match manifest {
+            Some(manifest) => typed.extend(peek_message_types(read_manifest(&manifest)?)),
+            None => {
+                if path == Path::new("-") {
+                    return Err("Cannot combine a manifest and stdin".into());
+                }
+                let mut files = peek_message_types(list_workspace_files(slice::from_ref(path))?);
+                sort_typed_files(&mut files);
+                typed.extend(files);
+            }
+        }
+    }
+
+    let warnings = check_multiple_headers(&typed);
+    Ok((typed.into_iter().map(|(_, path)| path).collect(), warnings))
+}
+
+/// Read the list of files from a manifest.
+pub fn read_manifest(path: &Path) -> Result<Vec<PathBuf>> {
+    let content = read_to_string(path)
+        .map_err(|err| format!("Cannot read the manifest {}: {}", path.display(), err))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    Ok(content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| dir.join(line))
+        .collect())
+}
+
+fn find_manifest(path: &Path) -> Option<PathBuf> {
+    if path.file_name() == Some(OsStr::new(MANIFEST_FILENAME)) {
+        return Some(path.to_path_buf());
+    }
+    let manifest = path.join(MANIFEST_FILENAME);
+    if path.is_dir() && manifest.is_file() {
+        Some(manifest)
+    } else {
+        None
+    }
+}
+
+/// Sort files with headers first, then witnesses, then constraints, then by name,
+/// with numbers in names in numerical order (constraints_2 before constraints_10).
+///
+/// The content of a file is known from the type of its first message.
+/// For files that cannot be read, it is guessed from the name of the file.
+/// Returns a warning if several files start with a header. Only the first message of each
+/// file is read, so a header after other messages in a file is not noticed here;
+/// `merge` and `split` reject it when they read the messages.
+pub fn sort_workspace_files(paths: &mut [PathBuf]) -> Vec<String> {
+    let mut typed = peek_message_types(paths.to_vec());
+    sort_typed_files(&mut typed);
+    let warnings = check_multiple_headers(&typed);
+    for (path, (_, sorted)) in paths.iter_mut().zip(typed) {
+        *path = sorted;
+    }
+    warnings
+}
+
+fn peek_message_types(paths: Vec<PathBuf>) -> Vec<(Option<MessageType>, PathBuf)> {
+    paths.into_iter().map(|path| (peek_message_type(&path), path)).collect()
+}
+
+fn sort_typed_files(typed: &mut [(Option<MessageType>, PathBuf)]) {
+    typed.sort_by_cached_key(|(_, path)| (natural_key(path), path.clone()));
+    typed.sort_by_key(|(message_type, path)| {
+        match message_type {
+            Some(MessageType::Header) => 0,
+            Some(MessageType::Witness) => 1,
+            Some(MessageType::ConstraintSystem) => 3,
+            Some(_) => 4,
+            None => {
+                let name = path.file_name().and_then(OsStr::to_str).unwrap_or("");
                 match () {
                     _ if name.contains("header") => 0,
                     _ if name.contains("witness") => 1,
                     _ if name.contains("constraint") => 3,
                     _ => 4,
                 }
-            });
-            Workspace { paths, stdin: false }
+            }
+        }
+    });
+}
+
+/// The text parts of a path, each with the number that follows it.
+fn natural_key(path: &Path) -> Vec<(String, u64)> {
+    let mut key = vec![];
+    let mut text = String::new();
+    let mut number = None;
+
+    for c in path.to_string_lossy().chars() {
+        match (c.to_digit(10), number) {
+            (Some(digit), _) => number = Some(number.unwrap_or(0u64).saturating_mul(10).saturating_add(digit as u64)),
+            (None, Some(n)) => {
+                key.push((std::mem::take(&mut text), n));
+                number = None;
+                text.push(c);
+            }
+            (None, None) => text.push(c),
         }
     }
+    key.push((text, number.unwrap_or(0)));
+    key
+}
 
-    pub fn iter_messages<'w>(&'w self) -> impl Iterator<Item=Message> + 'w {
-        let buffers: Box<dyn Iterator<Item=Vec<u8>>> = if self.stdin {
-            Box::new(iterate_stream(stdin()))
-        } else {
-            Box::new(iterate_files(&self.paths))
-        };
+/// How many bytes are read to find the type of a message. The root is normally at the start.
+const PEEK_SIZE: u64 = 256;
+
+/// The type of the first message of a file, or None if it cannot be read.
+///
+/// Only the start of the message is read, unless its root is further away.
+/// The rest of the message is not verified.
+pub fn peek_message_type(path: &Path) -> Option<MessageType> {
+    let mut file = File::open(path).ok()?;
+    let mut prefix = vec![];
+    (&mut file).take(PEEK_SIZE).read_to_end(&mut prefix).ok()?;
+    let size = read_size_prefix(&prefix);
+    if size <= SIZE_UOFFSET { return None; }
+    prefix.truncate(size);
+
+    let root_type = match read_message_type(&prefix) {
+        Err(_) if prefix.len() < size => {
+            let mut file = File::open(path).ok()?;
+            let buffer = read_buffer(&mut file).ok()?;
+            read_root(&buffer).map(|root| root.message_type())
+        }
+        result => result,
+    };
+
+    let message_type = match root_type {
+        Err(_) => MessageType::Invalid,
+        Ok(message_type) => match message_type {
+            fb::Message::CircuitHeader => MessageType::Header,
+            fb::Message::ConstraintSystem => MessageType::ConstraintSystem,
+            fb::Message::Witness => MessageType::Witness,
+            fb::Message::Command => MessageType::Command,
+            _ => MessageType::Invalid,
+        },
+    };
+    Some(message_type)
+}
+
+/// Warn if several files start with a header, from the type of their first message.
+fn check_multiple_headers(typed: &[(Option<MessageType>, PathBuf)]) -> Vec<String> {
+    let headers = typed.iter()
+        .filter(|(message_type, _)| *message_type == Some(MessageType::Header))
+        .map(|(_, path)| path.display().to_string())
+        .collect::<Vec<_>>();
 
-        buffers.map(|buffer| Message::from(&buffer[..]))
+    if headers.len() > 1 {
+        vec![format!("Several files start with a header: {}", headers.join(", "))]
+    } else {
+        vec![]
     }
+}
 
-    pub fn read_all_messages(&self) -> Messages {
-        Messages::from(self)
+/// An error while reading messages, with where it happened.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReadError {
+    /// The file being read, or None for stdin.
+    pub path: Option<PathBuf>,
+    /// Position in the file of the message that could not be read.
+    pub offset: u64,
+    /// Whether a complete message was read but is not valid. Otherwise, the file could not be read.
+    pub invalid_message: bool,
+    pub reason: String,
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "Error reading {} at byte {}: {}", path.display(), self.offset, self.reason),
+            None => write!(f, "Error reading stdin at byte {}: {}", self.offset, self.reason),
+        }
     }
 }
 
-pub fn iterate_files<'w>(paths: &'w [PathBuf]) -> impl Iterator<Item=Vec<u8>> + 'w {
+impl Error for ReadError {}
+
+type ReadResult<T> = std::result::Result<T, ReadError>;
+
+/// A size-prefixed message, and where it was read.
+pub struct Buffer {
+    pub path: Option<PathBuf>,
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+pub fn iterate_files<'w>(paths: &'w [PathBuf]) -> impl Iterator<Item=ReadResult<Vec<u8>>> + 'w {
     paths.iter().flat_map(|path|
         iterate_file(path))
 }
 
-pub fn iterate_file(path: &Path) -> Box<dyn Iterator<Item=Vec<u8>>> {
+/// Iterate over the messages of a file, without where they were read; see `try_iterate_file`.
+pub fn iterate_file(path: &Path) -> Box<dyn Iterator<Item=ReadResult<Vec<u8>>>> {
+    Box::new(buffer_data(try_iterate_file(path)))
+}
+
+pub fn iterate_stream<'s>(stream: impl Read + 's) -> impl Iterator<Item=ReadResult<Vec<u8>>> + 's {
+    buffer_data(try_iterate_stream(stream, None))
+}
+
+fn buffer_data(buffers: impl Iterator<Item=ReadResult<Buffer>>) -> impl Iterator<Item=ReadResult<Vec<u8>>> {
+    buffers.map(|buffer| buffer.map(|buffer| buffer.data))
+}
+
+/// Iterate over the messages of a file, or the error that stopped reading it.
+pub fn try_iterate_file(path: &Path) -> Box<dyn Iterator<Item=ReadResult<Buffer>>> {
     match File::open(path) {
-        Err(err) => {
-            eprintln!("Error opening workspace file {}: {}", path.display(), err);
-            Box::new(iter::empty())
-        }
+        Err(err) => Box::new(iter::once(Err(ReadError {
+            path: Some(path.to_path_buf()),
+            offset: 0,
+            invalid_message: false,
+            reason: format!("cannot open the file: {}", err),
+        }))),
         Ok(file) => Box::new(
-            iterate_stream(file)),
+            try_iterate_stream(file, Some(path.to_path_buf()))),
     }
 }
 
-pub fn iterate_stream<'s>(mut stream: impl Read + 's) -> impl Iterator<Item=Vec<u8>> + 's {
-    iter::from_fn(move ||
+/// Iterate over the messages of a stream, or the error that stopped reading it.
+/// The path is only used to report errors.
+pub fn try_iterate_stream<'s>(mut stream: impl Read + 's, path: Option<PathBuf>) -> impl Iterator<Item=ReadResult<Buffer>> + 's {
+    let mut offset = 0;
+    let mut done = false;
+
+    iter::from_fn(move || {
+        if done { return None; }
+
         match read_buffer(&mut stream) {
             Err(err) => {
-                eprintln!("Error reading: {}", err);
-                None
+                done = true;
+                Some(Err(ReadError {
+                    path: path.clone(),
+                    offset,
+                    invalid_message: false,
+                    reason: err.to_string(),
+                }))
             }
-            Ok(buffer) => {
-                if buffer.len() == 0 {
+            Ok(data) => {
+                if data.is_empty() {
+                    done = true;
                     None
                 } else {
-                    Some(buffer)
+                    let buffer = Buffer { path: path.clone(), offset, data };
+                    offset += buffer.data.len() as u64;
+                    Some(Ok(buffer))
                 }
             }
         }
-    )
+    })
 }
 
 pub fn has_zkif_extension(path: &Path) -> bool {
@@ -138,20 +463,139 @@ pub fn list_workspace_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
             if paths.len() > 1 { return Err("Cannot combine files and stdin".into()); }
             all_paths.push(path.clone());
         } else {
-            for file in read_dir(path)? {
-                match file {
-                    Ok(file) => {
-                        if has_zkif_extension(&file.path()) {
-                            all_paths.push(file.path());
-                        }
-                    }
-                    Err(err) => {
-                        eprintln!("Warning: {}", err);
-                        continue;
-                    }
+            let files = read_dir(path)
+                .map_err(|err| format!("Cannot read the workspace {}: {}", path.display(), err))?;
+            for file in files {
+                let file = file
+                    .map_err(|err| format!("Cannot read the workspace {}: {}", path.display(), err))?;
+                if has_zkif_extension(&file.path()) {
+                    all_paths.push(file.path());
                 }
             }
         }
     }
     Ok(all_paths)
 }
+
+
+#[test]
+fn test_workspace_errors() -> Result<()> {
+    use std::fs::{remove_dir_all, OpenOptions};
+    use std::io::Write;
+    use crate::{WorkspaceSink, Sink};
+    use crate::producers::examples::*;
+
+    let dir = PathBuf::from("local/test_workspace_errors");
+    let _ = remove_dir_all(&dir);
+    let mut sink = WorkspaceSink::new(&dir)?;
+    sink.push_header(example_circuit_header())?;
+    sink.push_witness(example_witness())?;
+    sink.push_constraints(example_constraints())?;
+
+    let ws = Workspace::from_dir(&dir)?;
+    assert_eq!(ws.try_iter_messages().filter(|msg| msg.is_err()).count(), 0);
+    assert_eq!(ws.try_read_all_messages()?, ws.read_all_messages());
+
+    // A missing file.
+    let missing = dir.join("missing.zkif");
+    let ws = Workspace::from_filenames(vec![missing.clone()]);
+    let err = ws.try_iter_messages().next().unwrap().unwrap_err();
+    assert_eq!(err.path, Some(missing));
+    assert!(!err.invalid_message);
+    assert!(ws.try_read_all_messages().is_err());
+    let messages = ws.iter_messages().collect::<Vec<_>>();
+    assert!(matches!(&messages[..], [Message::Err(err)] if err.contains("cannot open the file")));
+
+    // A complete message that is not valid, then a truncated message.
+    let witness_path = dir.join("witness.zkif");
+    let witness_size = std::fs::metadata(&witness_path)?.len();
+    let mut file = OpenOptions::new().append(true).open(&witness_path)?;
+    file.write_all(&[4, 0, 0, 0, 1, 2, 3, 4])?;
+    file.write_all(&[100, 0, 0, 0, 1, 2, 3])?;
+    drop(file);
+
+    let ws = Workspace::from_dir(&dir)?;
+    let results = ws.try_iter_messages().collect::<Vec<_>>();
+    assert_eq!(results.len(), 5);
+
+    let invalid = results[2].as_ref().unwrap_err();
+    assert_eq!(invalid.path, Some(witness_path.clone()));
+    assert_eq!(invalid.offset, witness_size);
+    assert!(invalid.invalid_message);
+
+    let truncated = results[3].as_ref().unwrap_err();
+    assert_eq!(truncated.offset, witness_size + 8);
+    assert!(!truncated.invalid_message);
+    assert!(truncated.to_string().starts_with(
+        &format!("Error reading {} at byte {}: ", witness_path.display(), witness_size + 8)));
+
+    // Reading continues with the next file.
+    assert!(matches!(results[4], Ok(Message::ConstraintSystem(_))));
+
+    assert!(ws.try_read_all_messages().is_err());
+
+    // The lenient iterator converts all errors to messages.
+    let messages = ws.iter_messages().collect::<Vec<_>>();
+    assert_eq!(messages.len(), 5);
+    assert!(matches!(messages[2], Message::Err(_)));
+    assert_eq!(messages[3], Message::Err(truncated.to_string()));
+    assert!(matches!(messages[4], Message::ConstraintSystem(_)));
+
+    Ok(())
+}
+
+
+#[test]
+fn test_workspace_order() -> Result<()> {
+    use std::fs::{remove_dir_all, create_dir_all, write};
+    use crate::producers::examples::*;
+
+    let dir = PathBuf::from("local/test_workspace_order");
+    let _ = remove_dir_all(&dir);
+    create_dir_all(&dir)?;
+
+    // Names that do not say what the files contain.
+    example_constraints().write_into(&mut File::create(dir.join("a.zkif"))?)?;
+    example_witness().write_into(&mut File::create(dir.join("b.zkif"))?)?;
+    example_circuit_header().write_into(&mut File::create(dir.join("c.zkif"))?)?;
+    File::create(dir.join("header_empty.zkif"))?;
+
+    assert_eq!(peek_message_type(&dir.join("c.zkif")), Some(MessageType::Header));
+    assert_eq!(peek_message_type(&dir.join("header_empty.zkif")), None);
+
+    let mut numbered = vec![dir.join("constraints_10.zkif"), dir.join("constraints_2.zkif"), dir.join("witness_1.zkif")];
+    assert!(sort_workspace_files(&mut numbered).is_empty());
+    assert_eq!(numbered, vec![dir.join("witness_1.zkif"), dir.join("constraints_2.zkif"), dir.join("constraints_10.zkif")]);
+
+    let ws = Workspace::from_dir(&dir)?;
+    assert_eq!(ws.paths, vec![
+        dir.join("c.zkif"),
+        dir.join("header_empty.zkif"),
+        dir.join("b.zkif"),
+        dir.join("a.zkif"),
+    ]);
+    let messages = ws.try_read_all_messages()?;
+    assert_eq!(messages.circuit_headers.len(), 1);
+    assert!(ws.warnings().is_empty());
+
+    // A large message, and a warning about several headers.
+    let mut large = example_constraints();
+    for _ in 0..10 {
+        large.constraints.extend(large.constraints.clone());
+    }
+    large.write_into(&mut File::create(dir.join("d.zkif"))?)?;
+    example_circuit_header().write_into(&mut File::create(dir.join("e.zkif"))?)?;
+    assert_eq!(peek_message_type(&dir.join("d.zkif")), Some(MessageType::ConstraintSystem));
+    let ws = Workspace::from_dir(&dir)?;
+    assert_eq!(ws.warnings().len(), 1);
+    std::fs::remove_file(dir.join("d.zkif"))?;
+    std::fs::remove_file(dir.join("e.zkif"))?;
+
+    // A manifest gives the order.
+    write(dir.join(MANIFEST_FILENAME), "# Statement\nc.zkif\na.zkif\n\nb.zkif\n")?;
+    let expected = vec![dir.join("c.zkif"), dir.join("a.zkif"), dir.join("b.zkif")];
+    assert_eq!(Workspace::from_dir(&dir)?.paths, expected);
+    assert_eq!(order_workspace_files(&[dir.join(MANIFEST_FILENAME)])?, (expected, vec![]));
+
+    Ok(())
+}
@@ -0,0 +1,376 @@
+use std::fmt::Debug;
+use num_bigint::BigUint;
+use num_traits::identities::{Zero, One};
+use num_traits::ToPrimitive;
+
+/// Arithmetic in a prime field, with elements represented as `Self::Element`.
+///
+/// The modulus is known at runtime, so methods take `&self`.
+pub trait Field: Clone + Debug + Send + Sync {
+    type Element: Clone + Debug + Eq + Send + Sync;
+
+    fn modulus(&self) -> BigUint;
+
+    fn zero(&self) -> Self::Element;
+
+    fn one(&self) -> Self::Element;
+
+    /// Decode a little-endian integer of any length, reduced modulo the field.
+    fn element_from_bytes_le(&self, bytes: &[u8]) -> Self::Element;
+
+    fn element_from_biguint(&self, value: &BigUint) -> Self::Element {
+        self.element_from_bytes_le(&value.to_bytes_le())
+    }
+
+    fn to_biguint(&self, element: &Self::Element) -> BigUint;
+
+    fn add(&self, a: &Self::Element, b: &Self::Element) -> Self::Element;
+
+    fn neg(&self, a: &Self::Element) -> Self::Element;
+
+    fn sub(&self, a: &Self::Element, b: &Self::Element) -> Self::Element {
+        self.add(a, &self.neg(b))
+    }
+
+    fn mul(&self, a: &Self::Element, b: &Self::Element) -> Self::Element;
+
+    fn is_zero(&self, a: &Self::Element) -> bool {
+        *a == self.zero()
+    }
+
+    /// The inverse of a nonzero element, or None for zero.
+    fn inv(&self, a: &Self::Element) -> Option<Self::Element> {
+        if self.is_zero(a) { return None; }
+        // Fermat's little theorem: a^(p-2) = a^-1
+        let exponent = self.modulus() - 2u8;
+        let mut result = self.one();
+        for i in (0..exponent.bits()).rev() {
+            result = self.mul(&result, &result);
+            if exponent.bit(i) {
+                result = self.mul(&result, a);
+            }
+        }
+        Some(result)
+    }
+}
+
+/// A field with a modulus of any size, using BigUint.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BigUintField {
+    modulus: BigUint,
+}
+
+impl BigUintField {
+    pub fn new(modulus: BigUint) -> BigUintField {
+        BigUintField { modulus }
+    }
+}
+
+impl Field for BigUintField {
+    type Element = BigUint;
+
+    fn modulus(&self) -> BigUint { self.modulus.clone() }
+
+    fn zero(&self) -> BigUint { BigUint::zero() }
+
+    fn one(&self) -> BigUint { BigUint::one() % &self.modulus }
+
+    fn element_from_bytes_le(&self, bytes: &[u8]) -> BigUint {
+        BigUint::from_bytes_le(bytes) % &self.modulus
+    }
+
+    fn to_biguint(&self, element: &BigUint) -> BigUint { element.clone() }
+
+    fn add(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a + b) % &self.modulus
+    }
+
+    fn neg(&self, a: &BigUint) -> BigUint {
+        (&self.modulus - a) % &self.modulus
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a * b) % &self.modulus
+    }
+}
+
+/// A field with a modulus that fits in 64 bits.
+#[derive(Clone, Debug)]
+pub struct U64Field {
+    modulus: u64,
+}
+
+impl U64Field {
+    /// Returns None if the modulus does not fit in 64 bits.
+    pub fn new(modulus: &BigUint) -> Option<U64Field> {
+        let modulus = modulus.to_u64()?;
+        if modulus == 0 { return None; }
+        Some(U64Field { modulus })
+    }
+}
+
+impl Field for U64Field {
+    type Element = u64;
+
+    fn modulus(&self) -> BigUint { BigUint::from(self.modulus) }
+
+    fn zero(&self) -> u64 { 0 }
+
+    fn one(&self) -> u64 { 1 % self.modulus }
+
+    fn element_from_bytes_le(&self, bytes: &[u8]) -> u64 {
+        if bytes.len() > 8 && bytes[8..].iter().any(|b| *b != 0) {
+            return (BigUint::from_bytes_le(bytes) % self.modulus).to_u64().unwrap();
+        }
+        let mut word = [0; 8];
+        let len = bytes.len().min(8);
+        word[..len].copy_from_slice(&bytes[..len]);
+        u64::from_le_bytes(word) % self.modulus
+    }
+
+    fn to_biguint(&self, element: &u64) -> BigUint { BigUint::from(*element) }
+
+    fn add(&self, a: &u64, b: &u64) -> u64 {
+        ((*a as u128 + *b as u128) % self.modulus as u128) as u64
+    }
+
+    fn neg(&self, a: &u64) -> u64 {
+        (self.modulus - a) % self.modulus
+    }
+
+    fn mul(&self, a: &u64, b: &u64) -> u64 {
+        ((*a as u128 * *b as u128) % self.modulus as u128) as u64
+    }
+}
+
+const LIMBS: usize = 4;
+
+/// A field element in Montgomery form, as little-endian 64 bits limbs.
+pub type Limbs = [u64; LIMBS];
+
+/// A field with an odd modulus of up to 256 bits, using Montgomery multiplication.
+#[derive(Clone, Debug)]
+pub struct MontgomeryField {
+    modulus: Limbs,
+    /// -modulus^-1 mod 2^64
+    inv: u64,
+    /// 2^256 mod modulus, which is one in Montgomery form.
+    r: Limbs,
+    /// 2^512 mod modulus, to convert into Montgomery form.
+    r2: Limbs,
+}
+
+impl MontgomeryField {
+    /// Returns None if the modulus is even or larger than 256 bits.
+    pub fn new(modulus: &BigUint) -> Option<MontgomeryField> {
+        if modulus.bits() > 64 * LIMBS as u64 || !modulus.bit(0) || modulus.is_one() {
+            return None;
+        }
+        let m = to_limbs(modulus);
+
+        let mut inv = 1u64;
+        for _ in 0..6 {
+            inv = inv.wrapping_mul(2u64.wrapping_sub(m[0].wrapping_mul(inv)));
+        }
+
+        Some(MontgomeryField {
+            modulus: m,
+            inv: inv.wrapping_neg(),
+            r: to_limbs(&((BigUint::one() << (64 * LIMBS)) % modulus)),
+            r2: to_limbs(&((BigUint::one() << (128 * LIMBS)) % modulus)),
+        })
+    }
+
+    /// a * b / 2^256 mod modulus, for a < 2^256 and b < modulus.
+    fn mont_mul(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        let m = &self.modulus;
+        let mut t = [0u64; LIMBS + 2];
+
+        for b_i in b.iter() {
+            let mut carry = 0;
+            for j in 0..LIMBS {
+                let (lo, hi) = mac(t[j], a[j], *b_i, carry);
+                t[j] = lo;
+                carry = hi;
+            }
+            let (sum, overflow) = t[LIMBS].overflowing_add(carry);
+            t[LIMBS] = sum;
+            t[LIMBS + 1] = overflow as u64;
+
+            let factor = t[0].wrapping_mul(self.inv);
+            let (_, mut carry) = mac(t[0], factor, m[0], 0);
+            for j in 1..LIMBS {
+                let (lo, hi) = mac(t[j], factor, m[j], carry);
+                t[j - 1] = lo;
+                carry = hi;
+            }
+            let (sum, overflow) = t[LIMBS].overflowing_add(carry);
+            t[LIMBS - 1] = sum;
+            t[LIMBS] = t[LIMBS + 1] + overflow as u64;
+        }
+
+        let mut result = [0; LIMBS];
+        result.copy_from_slice(&t[..LIMBS]);
+        if t[LIMBS] != 0 || !less_than(&result, m) {
+            sub_assign(&mut result, m);
+        }
+        result
+    }
+}
+
+impl Field for MontgomeryField {
+    type Element = Limbs;
+
+    fn modulus(&self) -> BigUint { from_limbs(&self.modulus) }
+
+    fn zero(&self) -> Limbs { [0; LIMBS] }
+
+    fn one(&self) -> Limbs { self.r }
+
+    fn element_from_bytes_le(&self, bytes: &[u8]) -> Limbs {
+        if bytes.len() > 8 * LIMBS && bytes[8 * LIMBS..].iter().any(|b| *b != 0) {
+            let reduced = BigUint::from_bytes_le(bytes) % self.modulus();
+            return self.mont_mul(&to_limbs(&reduced), &self.r2);
+        }
+        let mut limbs = [0; LIMBS];
+        for (i, chunk) in bytes.chunks(8).take(LIMBS).enumerate() {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            limbs[i] = u64::from_le_bytes(word);
+        }
+        // Any value below 2^256 is reduced by the multiplication.
+        self.mont_mul(&limbs, &self.r2)
+    }
+
+    fn to_biguint(&self, element: &Limbs) -> BigUint {
+        let mut one = [0; LIMBS];
+        one[0] = 1;
+        from_limbs(&self.mont_mul(element, &one))
+    }
+
+    fn add(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        let mut result = [0; LIMBS];
+        let mut carry = false;
+        for i in 0..LIMBS {
+            let (sum, overflow1) = a[i].overflowing_add(b[i]);
+            let (sum, overflow2) = sum.overflowing_add(carry as u64);
+            result[i] = sum;
+            carry = overflow1 || overflow2;
+        }
+        if carry || !less_than(&result, &self.modulus) {
+            sub_assign(&mut result, &self.modulus);
+        }
+        result
+    }
+
+    fn neg(&self, a: &Limbs) -> Limbs {
+        if self.is_zero(a) { return *a; }
+        let mut result = self.modulus;
+        sub_assign(&mut result, a);
+        result
+    }
+
+    fn mul(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        self.mont_mul(a, b)
+    }
+}
+
+/// a + b * c + carry, as (low, high) words.
+#[inline(always)]
+fn mac(a: u64, b: u64, c: u64, carry: u64) -> (u64, u64) {
+    let wide = a as u128 + (b as u128) * (c as u128) + carry as u128;
+    (wide as u64, (wide >> 64) as u64)
+}
+
+fn less_than(a: &Limbs, b: &Limbs) -> bool {
+    for i in (0..LIMBS).rev() {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+    }
+    false
+}
+
+/// a -= b, wrapping around 2^256.
+fn sub_assign(a: &mut Limbs, b: &Limbs) {
+    let mut borrow = false;
+    for i in 0..LIMBS {
+        let (diff, borrow1) = a[i].overflowing_sub(b[i]);
+        let (diff, borrow2) = diff.overflowing_sub(borrow as u64);
+        a[i] = diff;
+        borrow = borrow1 || borrow2;
+    }
+}
+
+fn to_limbs(value: &BigUint) -> Limbs {
+    let mut limbs = [0; LIMBS];
+    for (i, digit) in value.to_u64_digits().iter().take(LIMBS).enumerate() {
+        limbs[i] = *digit;
+    }
+    limbs
+}
+
+fn from_limbs(limbs: &Limbs) -> BigUint {
+    let mut bytes = Vec::with_capacity(8 * LIMBS);
+    for limb in limbs {
+        bytes.extend_from_slice(&limb.to_le_bytes());
+    }
+    BigUint::from_bytes_le(&bytes)
+}
+
+
+#[test]
+fn test_fields() {
+    use num_bigint::RandBigInt;
+    use num_traits::Num;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    fn check<F: Field>(field: &F, rng: &mut StdRng) {
+        let modulus = field.modulus();
+        let big = BigUintField::new(modulus.clone());
+
+        for _ in 0..200 {
+            let a = rng.gen_biguint(300);
+            let b = rng.gen_biguint_below(&modulus);
+            let fa = field.element_from_bytes_le(&a.to_bytes_le());
+            let fb = field.element_from_biguint(&b);
+            let ba = big.element_from_bytes_le(&a.to_bytes_le());
+            let bb = big.element_from_biguint(&b);
+
+            assert_eq!(field.to_biguint(&fa), ba);
+            assert_eq!(field.to_biguint(&field.add(&fa, &fb)), big.add(&ba, &bb));
+            assert_eq!(field.to_biguint(&field.sub(&fa, &fb)), big.sub(&ba, &bb));
+            assert_eq!(field.to_biguint(&field.mul(&fa, &fb)), big.mul(&ba, &bb));
+            if let Some(inv) = field.inv(&fb) {
+                assert_eq!(field.mul(&inv, &fb), field.one());
+            }
+        }
+        assert_eq!(field.to_biguint(&field.one()), BigUint::one() % &modulus);
+        assert_eq!(field.to_biguint(&field.neg(&field.zero())), BigUint::zero());
+        assert!(field.inv(&field.zero()).is_none());
+    }
+
+    let mut rng = StdRng::seed_from_u64(1);
+    let primes = [
+        "2",
+        "65",
+        "FFFFFFFFFFFFFFC5", // 2**64 - 59
+        "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFF61", // 2**128 - 159
+        "30644E72E131A029B85045B68181585D2833E84879B9709143E1F593F0000001", // BN254 scalar field
+        "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF43", // 2**256 - 189
+    ];
+    for hex in &primes {
+        let modulus = BigUint::from_str_radix(hex, 16).unwrap();
+
+        check(&BigUintField::new(modulus.clone()), &mut rng);
+        if let Some(field) = U64Field::new(&modulus) {
+            check(&field, &mut rng);
+        }
+        if let Some(field) = MontgomeryField::new(&modulus) {
+            check(&field, &mut rng);
+        }
+    }
+    assert!(U64Field::new(&BigUint::from_str_radix(primes[3], 16).unwrap()).is_none());
+    assert!(MontgomeryField::new(&BigUint::from(2u8)).is_none());
+    assert!(MontgomeryField::new(&(BigUint::one() << 257)).is_none());
+}
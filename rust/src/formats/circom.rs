@@ -0,0 +1,525 @@
+//! Reader and writer for the binary formats of circom: `.r1cs` for constraints and `.wtns` for witnesses.
+//!
+//! Wire 0 of circom is the constant one, like variable 0 of zkInterface.
+//! The public wires (outputs, then inputs) become instance variables, and the other wires are witness variables.
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Write};
+use std::iter;
+use num_bigint::BigUint;
+use num_traits::identities::One;
+
+use crate::{Result, CircuitHeader, ConstraintSystem, BilinearConstraint, Variables, Witness, Messages};
+
+const R1CS_MAGIC: &[u8; 4] = b"r1cs";
+const WTNS_MAGIC: &[u8; 4] = b"wtns";
+const R1CS_VERSION: u32 = 1;
+const WTNS_VERSION: u32 = 2;
+
+const R1CS_HEADER: u32 = 1;
+const R1CS_CONSTRAINTS: u32 = 2;
+const R1CS_WIRE_TO_LABEL: u32 = 3;
+const WTNS_HEADER: u32 = 1;
+const WTNS_VALUES: u32 = 2;
+
+/// A linear combination as (wire, coefficient) terms.
+pub type CircomLc = Vec<(u32, BigUint)>;
+
+/// The content of a circom `.r1cs` file. Each constraint is A * B - C = 0.
+#[derive(Clone, Default, Debug, Eq, PartialEq)]
+pub struct R1cs {
+    pub prime: BigUint,
+    pub n_wires: u32,
+    pub n_pub_out: u32,
+    pub n_pub_in: u32,
+    pub n_prv_in: u32,
+    pub n_labels: u64,
+    pub constraints: Vec<(CircomLc, CircomLc, CircomLc)>,
+    /// The label ID of each wire. May be empty.
+    pub wire_to_label: Vec<u64>,
+}
+
+/// The content of a circom `.wtns` file: the values of all wires, including wire 0.
+#[derive(Clone, Default, Debug, Eq, PartialEq)]
+pub struct Wtns {
+    pub prime: BigUint,
+    pub values: Vec<BigUint>,
+}
+
+impl R1cs {
+    pub fn read(reader: impl Read) -> Result<R1cs> {
+        let sections = read_sections(reader, R1CS_MAGIC, R1CS_VERSION)?;
+
+        let mut header = BinReader::new(sections.get(&R1CS_HEADER).ok_or("Missing header section in .r1cs")?);
+        let field_size = header.u32()? as usize;
+        let mut r1cs = R1cs {
+            prime: header.element(field_size)?,
+            n_wires: header.u32()?,
+            n_pub_out: header.u32()?,
+            n_pub_in: header.u32()?,
+            n_prv_in: header.u32()?,
+            n_labels: header.u64()?,
+            ..R1cs::default()
+        };
+        let n_constraints = header.u32()?;
+
+        let mut body = BinReader::new(sections.get(&R1CS_CONSTRAINTS).ok_or("Missing constraints section in .r1cs")?);
+        for _ in 0..n_constraints {
+            let a = body.lc(field_size)?;
+            let b = body.lc(field_size)?;
+            let c = body.lc(field_size)?;
+            r1cs.constraints.push((a, b, c));
+        }
+
+        if let Some(section) = sections.get(&R1CS_WIRE_TO_LABEL) {
+            let mut labels = BinReader::new(section);
+            for _ in 0..r1cs.n_wires {
+                r1cs.wire_to_label.push(labels.u64()?);
+            }
+        }
+        Ok(r1cs)
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> Result<()> {
+        let field_size = field_size(&self.prime);
+
+        let mut header = vec![];
+        header.extend_from_slice(&(field_size as u32).to_le_bytes());
+        write_element(&mut header, &self.prime, field_size);
+        for n in &[self.n_wires, self.n_pub_out, self.n_pub_in, self.n_prv_in] {
+            header.extend_from_slice(&n.to_le_bytes());
+        }
+        header.extend_from_slice(&self.n_labels.to_le_bytes());
+        header.extend_from_slice(&(self.constraints.len() as u32).to_le_bytes());
+
+        let mut body = vec![];
+        for (a, b, c) in &self.constraints {
+            for lc in &[a, b, c] {
+                body.extend_from_slice(&(lc.len() as u32).to_le_bytes());
+                for (wire, coeff) in lc.iter() {
+                    body.extend_from_slice(&wire.to_le_bytes());
+                    write_element(&mut body, coeff, field_size);
+                }
+            }
+        }
+
+        let mut labels = vec![];
+        for label in &self.wire_to_label {
+            labels.extend_from_slice(&label.to_le_bytes());
+        }
+
+        write_sections(writer, R1CS_MAGIC, R1CS_VERSION, &[
+            (R1CS_HEADER, header),
+            (R1CS_CONSTRAINTS, body),
+            (R1CS_WIRE_TO_LABEL, labels),
+        ])
+    }
+}
+
+impl Wtns {
+    pub fn read(reader: impl Read) -> Result<Wtns> {
+        let sections = read_sections(reader, WTNS_MAGIC, WTNS_VERSION)?;
+
+        let mut header = BinReader::new(sections.get(&WTNS_HEADER).ok_or("Missing header section in .wtns")?);
+        let field_size = header.u32()? as usize;
+        let prime = header.element(field_size)?;
+        let n_values = header.u32()?;
+
+        let mut body = BinReader::new(sections.get(&WTNS_VALUES).ok_or("Missing values section in .wtns")?);
+        let values = (0..n_values)
+            .map(|_| body.element(field_size))
+            .collect::<Result<Vec<BigUint>>>()?;
+        check_one(&values)?;
+
+        Ok(Wtns { prime, values })
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> Result<()> {
+        let field_size = field_size(&self.prime);
+
+        let mut header = vec![];
+        header.extend_from_slice(&(field_size as u32).to_le_bytes());
+        write_element(&mut header, &self.prime, field_size);
+        header.extend_from_slice(&(self.values.len() as u32).to_le_bytes());
+
+        let mut body = vec![];
+        for value in &self.values {
+            write_element(&mut body, value, field_size);
+        }
+
+        write_sections(writer, WTNS_MAGIC, WTNS_VERSION, &[
+            (WTNS_HEADER, header),
+            (WTNS_VALUES, body),
+        ])
+    }
+}
+
+/// Wire 0 is the constant one, so the first witness value must be 1.
+fn check_one(values: &[BigUint]) -> Result<()> {
+    match values.first() {
+        Some(first) if !first.is_one() =>
+            Err(format!("The first value in .wtns must be 1, got {}", first).into()),
+        _ => Ok(()),
+    }
+}
+
+/// Convert circom constraints, and optionally a witness, into zkInterface messages.
+///
+/// # Example
+/// ```
+/// use zkinterface::Messages;
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::formats::circom::{to_circom, from_circom};
+///
+/// let messages = Messages {
+///     circuit_headers: vec![example_circuit_header()],
+///     constraint_systems: vec![example_constraints()],
+///     witnesses: vec![example_witness()],
+/// };
+///
+/// let (r1cs, wtns) = to_circom(&messages).unwrap();
+/// assert_eq!(r1cs.n_pub_in, 3);
+/// let imported = from_circom(&r1cs, wtns.as_ref()).unwrap();
+/// assert_eq!(imported.witnesses[0].assigned_variables.variable_ids, vec![4, 5]);
+/// ```
+pub fn from_circom(r1cs: &R1cs, wtns: Option<&Wtns>) -> Result<Messages> {
+    if r1cs.prime < BigUint::from(2u8) {
+        return Err(format!("Invalid prime {} in .r1cs", r1cs.prime).into());
+    }
+    let field_size = field_size(&r1cs.prime);
+    let n_public = r1cs.n_pub_out as u64 + r1cs.n_pub_in as u64;
+    let n_wires = r1cs.n_wires as u64;
+    if n_public >= n_wires {
+        return Err(format!("Expected more than {} wires in .r1cs, got {}", n_public, n_wires).into());
+    }
+
+    if let Some(wtns) = wtns {
+        if wtns.prime != r1cs.prime {
+            return Err(format!("The .wtns field {} does not match the .r1cs field {}", wtns.prime, r1cs.prime).into());
+        }
+        if wtns.values.len() as u64 != n_wires {
+            return Err(format!("Expected {} values in .wtns, got {}", n_wires, wtns.values.len()).into());
+        }
+        check_one(&wtns.values)?;
+    }
+
+    let encode_values = |ids: &[u64]| wtns.map(|wtns| {
+        let mut values = vec![];
+        for id in ids {
+            write_element(&mut values, &wtns.values[*id as usize], field_size);
+        }
+        values
+    });
+
+    let instance_ids = (1..=n_public).collect::<Vec<u64>>();
+    let header = CircuitHeader {
+        instance_variables: Variables {
+            values: encode_values(&instance_ids),
+            variable_ids: instance_ids,
+            info: None,
+        },
+        free_variable_id: n_wires,
+        field_maximum: Some((&r1cs.prime - 1u8).to_bytes_le()),
+        configuration: None,
+    };
+
+    let encode_lc = |lc: &CircomLc| {
+        let mut values = vec![];
+        for (_, coeff) in lc {
+            write_element(&mut values, coeff, field_size);
+        }
+        Variables {
+            variable_ids: lc.iter().map(|(wire, _)| *wire as u64).collect(),
+            values: Some(values),
+            info: None,
+        }
+    };
+    for (a, b, c) in &r1cs.constraints {
+        for (wire, _) in a.iter().chain(b).chain(c) {
+            if *wire as u64 >= n_wires {
+                return Err(format!("Wire {} is beyond the {} wires of the .r1cs", wire, n_wires).into());
+            }
+        }
+    }
+    let constraints = ConstraintSystem {
+        constraints: r1cs.constraints.iter().map(|(a, b, c)|
+            BilinearConstraint {
+                linear_combination_a: encode_lc(a),
+                linear_combination_b: encode_lc(b),
+                linear_combination_c: encode_lc(c),
+            }
+        ).collect(),
+        info: None,
+    };
+
+    let witnesses = match wtns {
+        Some(_) => {
+            let witness_ids = (n_public + 1..n_wires).collect::<Vec<u64>>();
+            vec![Witness {
+                assigned_variables: Variables {
+                    values: encode_values(&witness_ids),
+                    variable_ids: witness_ids,
+                    info: None,
+                }
+            }]
+        }
+        None => vec![],
+    };
+
+    Ok(Messages {
+        circuit_headers: vec![header],
+        constraint_systems: vec![constraints],
+        witnesses,
+    })
+}
+
+/// Convert zkInterface messages into circom constraints, and a witness if the messages contain one.
+///
+/// Wires are numbered with the constant one first, then instance variables in the order of the header,
+/// then other variables in the order of their IDs. The labels of wires are the zkInterface variable IDs.
+pub fn to_circom(messages: &Messages) -> Result<(R1cs, Option<Wtns>)> {
+    let header = match &messages.circuit_headers[..] {
+        [header] => header,
+        [] => return Err("export-circom requires a header.".into()),
+        _ => return Err("export-circom requires a single header.".into()),
+    };
+    let max = header.field_maximum.as_ref().ok_or("No field_maximum specified")?;
+    let prime = BigUint::from_bytes_le(max) + 1u8;
+
+    // Number the wires.
+    let instance_ids = &header.instance_variables.variable_ids;
+    let mut other_ids = BTreeSet::new();
+    for cs in &messages.constraint_systems {
+        for constraint in &cs.constraints {
+            for lc in &[&constraint.linear_combination_a, &constraint.linear_combination_b, &constraint.linear_combination_c] {
+                other_ids.extend(lc.variable_ids.iter().cloned());
+            }
+        }
+    }
+    for witness in &messages.witnesses {
+        other_ids.extend(witness.assigned_variables.variable_ids.iter().cloned());
+    }
+    other_ids.remove(&0);
+    for id in instance_ids {
+        other_ids.remove(id);
+    }
+
+    let wire_to_label = iter::once(0)
+        .chain(instance_ids.iter().cloned())
+        .chain(other_ids)
+        .collect::<Vec<u64>>();
+    let wire_of = wire_to_label.iter().enumerate()
+        .map(|(wire, id)| (*id, wire as u32))
+        .collect::<HashMap<u64, u32>>();
+
+    let to_lc = |vars: &Variables| vars.get_variables().iter()
+        .map(|term| (wire_of[&term.id], BigUint::from_bytes_le(term.value) % &prime))
+        .collect::<CircomLc>();
+
+    let r1cs = R1cs {
+        prime: prime.clone(),
+        n_wires: wire_to_label.len() as u32,
+        n_pub_out: 0,
+        n_pub_in: instance_ids.len() as u32,
+        n_prv_in: 0,
+        n_labels: wire_to_label.len() as u64,
+        constraints: messages.constraint_systems.iter()
+            .flat_map(|cs| cs.constraints.iter())
+            .map(|constraint| (
+                to_lc(&constraint.linear_combination_a),
+                to_lc(&constraint.linear_combination_b),
+                to_lc(&constraint.linear_combination_c),
+            )).collect(),
+        wire_to_label: wire_to_label.clone(),
+    };
+
+    if messages.witnesses.is_empty() {
+        return Ok((r1cs, None));
+    }
+
+    let mut values = HashMap::new();
+    values.insert(0, BigUint::one());
+    for var in header.instance_variables.get_variables() {
+        values.insert(var.id, BigUint::from_bytes_le(var.value));
+    }
+    for witness in &messages.witnesses {
+        for var in witness.assigned_variables.get_variables() {
+            values.insert(var.id, BigUint::from_bytes_le(var.value));
+        }
+    }
+    let wtns = Wtns {
+        prime,
+        values: wire_to_label.iter()
+            .map(|id| values.remove(id).ok_or_else(|| format!("No value given for variable {}", id).into()))
+            .collect::<Result<Vec<BigUint>>>()?,
+    };
+
+    Ok((r1cs, Some(wtns)))
+}
+
+/// The size in bytes of field elements, as a multiple of 8 like circom.
+fn field_size(prime: &BigUint) -> usize {
+    prime.to_u64_digits().len().max(1) * 8
+}
+
+fn write_element(buf: &mut Vec<u8>, value: &BigUint, field_size: usize) {
+    let mut bytes = value.to_bytes_le();
+    bytes.resize(field_size, 0);
+    buf.extend_from_slice(&bytes);
+}
+
+fn read_sections(mut reader: impl Read, magic: &[u8; 4], version: u32) -> Result<HashMap<u32, Vec<u8>>> {
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf)?;
+    let mut bin = BinReader::new(&buf);
+
+    if bin.bytes(4)? != magic {
+        return Err(format!("Not a circom .{} file", String::from_utf8_lossy(magic)).into());
+    }
+    let got_version = bin.u32()?;
+    if got_version != version {
+        return Err(format!("Unsupported version {} of .{} files, expected {}", got_version, String::from_utf8_lossy(magic), version).into());
+    }
+
+    let mut sections = HashMap::new();
+    let n_sections = bin.u32()?;
+    for _ in 0..n_sections {
+        let section_type = bin.u32()?;
+        let size = bin.u64()?;
+        if size > buf.len() as u64 {
+            return Err(format!("Section of {} bytes is beyond the end of the file", size).into());
+        }
+        let content = bin.bytes(size as usize)?;
+        sections.insert(section_type, content.to_vec());
+    }
+    Ok(sections)
+}
+
+fn write_sections(writer: &mut impl Write, magic: &[u8; 4], version: u32, sections: &[(u32, Vec<u8>)]) -> Result<()> {
+    writer.write_all(magic)?;
+    writer.write_all(&version.to_le_bytes())?;
+    writer.write_all(&(sections.len() as u32).to_le_bytes())?;
+    for (section_type, content) in sections {
+        writer.write_all(&section_type.to_le_bytes())?;
+        writer.write_all(&(content.len() as u64).to_le_bytes())?;
+        writer.write_all(content)?;
+    }
+    Ok(())
+}
+
+/// Read little-endian integers from a buffer.
+struct BinReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> BinReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        BinReader { buf }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.buf.len() {
+            return Err("Unexpected end of circom file".into());
+        }
+        let (bytes, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn element(&mut self, field_size: usize) -> Result<BigUint> {
+        Ok(BigUint::from_bytes_le(self.bytes(field_size)?))
+    }
+
+    fn lc(&mut self, field_size: usize) -> Result<CircomLc> {
+        let n_terms = self.u32()?;
+        (0..n_terms)
+            .map(|_| Ok((self.u32()?, self.element(field_size)?)))
+            .collect()
+    }
+}
+
+
+#[test]
+fn test_circom_roundtrip() -> Result<()> {
+    use crate::producers::examples::*;
+    use crate::consumers::simulator::Simulator;
+    use crate::consumers::validator::Validator;
+
+    let mut header = example_circuit_header();
+    header.free_variable_id = 10;
+    let messages = Messages {
+        circuit_headers: vec![header],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness()],
+    };
+
+    let (r1cs, wtns) = to_circom(&messages)?;
+    let wtns = wtns.unwrap();
+    assert_eq!(r1cs.n_wires, 6);
+    assert_eq!(r1cs.wire_to_label, vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(wtns.values, [1u32, 3, 4, 25, 9, 16].iter().map(|v| BigUint::from(*v)).collect::<Vec<_>>());
+
+    // Through the binary formats.
+    let mut r1cs_bytes = vec![];
+    r1cs.write(&mut r1cs_bytes)?;
+    assert_eq!(&r1cs_bytes[..4], b"r1cs");
+    assert_eq!(R1cs::read(&r1cs_bytes[..])?, r1cs);
+
+    let mut wtns_bytes = vec![];
+    wtns.write(&mut wtns_bytes)?;
+    assert_eq!(Wtns::read(&wtns_bytes[..])?, wtns);
+
+    assert!(R1cs::read(&r1cs_bytes[..r1cs_bytes.len() - 1]).is_err());
+    assert!(R1cs::read(&wtns_bytes[..]).is_err());
+
+    let mut bad_wtns = wtns.clone();
+    bad_wtns.values[0] = BigUint::from(2u8);
+    assert!(from_circom(&r1cs, Some(&bad_wtns)).is_err());
+    let mut bad_bytes = vec![];
+    bad_wtns.write(&mut bad_bytes)?;
+    assert!(Wtns::read(&bad_bytes[..]).is_err());
+
+    // Back to zkInterface.
+    let imported = from_circom(&R1cs::read(&r1cs_bytes[..])?, Some(&Wtns::read(&wtns_bytes[..])?))?;
+    assert_eq!(imported.circuit_headers[0].free_variable_id, 6);
+    assert_eq!(imported.circuit_headers[0].instance_variables.variable_ids, vec![1, 2, 3]);
+    assert_eq!(imported.witnesses[0].assigned_variables.variable_ids, vec![4, 5]);
+
+    let mut simulator = Simulator::default();
+    let mut validator = Validator::new_as_prover();
+    for header in &imported.circuit_headers {
+        simulator.ingest_header(header)?;
+        validator.ingest_header(header);
+    }
+    for witness in &imported.witnesses {
+        simulator.ingest_witness(witness)?;
+        validator.ingest_witness(witness);
+    }
+    for cs in &imported.constraint_systems {
+        simulator.ingest_constraint_system(cs)?;
+        validator.ingest_constraint_system(cs);
+    }
+    assert_eq!(simulator.get_violations(), Vec::<String>::new());
+    assert_eq!(validator.get_violations(), Vec::<String>::new());
+
+    // Constraints only.
+    let (r1cs_again, _) = to_circom(&imported)?;
+    assert_eq!(r1cs_again, r1cs);
+    let imported = from_circom(&r1cs, None)?;
+    assert!(imported.witnesses.is_empty());
+    assert_eq!(imported.circuit_headers[0].instance_variables.values, None);
+
+    Ok(())
+}
@@ -34,6 +34,7 @@
 //!         instance_variables: zkinterface::Variables {
 //!            variable_ids: vec![1, 2, 3],  // x, y, zz
 //!            values: Some(serialize_small(&[x, y, zz])),
+//!            info: None,
 //!        },
 //!        free_variable_id: 6,
 //!        field_maximum: Some(serialize_small(&[NEG_ONE])),
@@ -58,6 +59,7 @@
 //!                 x * x, // var_4 = xx = x^2
 //!                 y * y, // var_5 = yy = y^2
 //!             ])),
+//!             info: None,
 //!        }
 //!    };
 //! ```
@@ -145,22 +147,46 @@
 //! - `zkif cat`         Write .zkif files to stdout.
 //! - `zkif to-json`     Convert to JSON on a single line.
 //! - `zkif to-yaml`     Convert to YAML.
+//! - `zkif from-json`   Convert from JSON back into .zkif files.
+//! - `zkif from-yaml`   Convert from YAML back into .zkif files.
+//! - `zkif import-circom` Convert from the .r1cs and .wtns formats of circom.
+//! - `zkif export-circom` Convert to the .r1cs and .wtns formats of circom.
+//! - `zkif optimize`    Eliminate linear, duplicate and trivial constraints.
+//! - `zkif renumber`    Renumber variables densely.
+//! - `zkif normalize`   Normalize constraints and values into a canonical form.
+//! - `zkif solve`       Compute missing witness values from the constraints.
+//! - `zkif merge`       Merge several workspaces into one.
+//! - `zkif split`       Split constraints and witnesses into files of a maximum size.
+//! - `zkif hash`        Print hashes of the circuit, the instance and the witness, to identify them.
+//! - `zkif diff`        Compare two statements, independently of how they are split into messages.
+//! - `zkif check-determinism` Look for witness variables that are not determined by the constraints.
 //! - `zkif explain`     Print the content in a human-readable form.
+//! - `zkif graph`       Print the variables and constraints as a graph in Graphviz DOT format.
 //! - `zkif validate`    Validate the format and semantics of a statement, as seen by a verifier.
 //! - `zkif simulate`    Simulate a proving system as prover by verifying that the statement is true.
 //! - `zkif stats`       Calculate statistics about the circuit.
-//! - `zkif clean`       Clean workspace by deleting all *.zkif files in it.
+//! - `zkif index`       Index the files for random access, into .zkif.idx files next to them.
+//! - `zkif clean`       Clean workspace by deleting all *.zkif files in it, and their indexes.
 
 #[allow(unused_imports)]
 /// All CLI related logic.
 pub mod cli;
 
-/// Various zkInterface consumers including: validator, simulator, stats, reader and a workspace
+/// Various zkInterface consumers including: validator, simulator, stats, graph, reader and a workspace
 pub mod consumers;
 
 /// Various zkInterface producers including: examples, builder, gadget_caller and workspace
 pub mod producers;
 
+/// Transformations of statements into equivalent statements including: optimizer, renumber
+pub mod transform;
+
+/// Arithmetic in prime fields, with fast implementations for moduli of up to 64 or 256 bits
+pub mod field;
+
+/// Conversions from and to other formats including: circom
+pub mod formats;
+
 /// Fully-owned version of each data structure
 /// These structures may be easier to work with than the no-copy versions found in zkinterface_generated and Reader
 pub mod structs;
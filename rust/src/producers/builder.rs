@@ -1,4 +1,7 @@
-use crate::{Result, Variables, CircuitHeader, ConstraintSystem, Witness};
+use std::mem;
+
+use crate::{Result, Variables, CircuitHeader, ConstraintSystem, BilinearConstraint, Witness, KeyValue};
+use crate::structs::constraints::NAMESPACE_SEPARATOR;
 
 pub trait Sink {
     fn push_header(&mut self, statement: CircuitHeader) -> Result<()>;
@@ -6,6 +9,21 @@ pub trait Sink {
     fn push_witness(&mut self, witness: Witness) -> Result<()>;
 }
 
+/// Push into a sink that is still owned by the caller.
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn push_header(&mut self, header: CircuitHeader) -> Result<()> {
+        (**self).push_header(header)
+    }
+
+    fn push_constraints(&mut self, cs: ConstraintSystem) -> Result<()> {
+        (**self).push_constraints(cs)
+    }
+
+    fn push_witness(&mut self, witness: Witness) -> Result<()> {
+        (**self).push_witness(witness)
+    }
+}
+
 
 /// StatementBuilder assists with constructing and storing a statement in zkInterface format.
 /// # Example
@@ -25,9 +43,36 @@ pub trait Sink {
 /// builder.push_witness(witness).unwrap();
 /// builder.push_constraints(cs).unwrap();
 /// ```
+///
+/// Constraints can also be built one at a time with `enforce`, in namespaces,
+/// together with the witness. They are batched into messages of at most `batch_size` items.
+///
+/// ```
+/// use zkinterface::{StatementBuilder, Messages};
+///
+/// let mut builder = StatementBuilder::new(Messages::default());
+/// builder.header.field_maximum = Some(vec![100]);
+/// let x = builder.allocate_instance_var(&[3]);
+///
+/// builder.push_namespace("square").unwrap();
+/// let xx = builder.allocate_witness_var("xx", Some(&[9])).unwrap();
+/// builder.enforce(x, x, xx).unwrap();
+/// builder.pop_namespace().unwrap();
+///
+/// builder.finish().unwrap();
+/// let messages = builder.sink;
+/// assert_eq!(messages.constraint_systems[0].namespace(), Some("square"));
+/// assert_eq!(messages.witnesses[0].assigned_variables.get_names(), vec![(xx, "square/xx")]);
+/// ```
 pub struct StatementBuilder<S: Sink> {
     pub sink: S,
     pub header: CircuitHeader,
+    /// The maximum number of constraints or witness variables per message built by `enforce` and `allocate_witness_var`.
+    pub batch_size: usize,
+
+    namespaces: Vec<String>,
+    constraints: Vec<BilinearConstraint>,
+    witness: Variables,
 }
 
 impl<S: Sink> StatementBuilder<S> {
@@ -38,10 +83,19 @@ impl<S: Sink> StatementBuilder<S> {
                 instance_variables: Variables {
                     variable_ids: vec![],
                     values: Some(vec![]),
+                    info: None,
                 },
                 free_variable_id: 1,
                 ..CircuitHeader::default()
             },
+            batch_size: 100_000,
+            namespaces: vec![],
+            constraints: vec![],
+            witness: Variables {
+                variable_ids: vec![],
+                values: Some(vec![]),
+                info: None,
+            },
         }
     }
 
@@ -73,6 +127,116 @@ impl<S: Sink> StatementBuilder<S> {
     pub fn finish_header(&mut self) -> Result<()> {
         self.sink.push_header(self.header.clone())
     }
+
+    /// Allocate a witness variable, named in the current namespace, with its value if known.
+    /// The name is recorded in the `info` of the witness, see `Variables::get_names`.
+    pub fn allocate_witness_var(&mut self, name: &str, value: Option<&[u8]>) -> Result<u64> {
+        if let Some(value) = value {
+            if !self.witness.variable_ids.is_empty() && value.len() != self.witness.value_size() {
+                return Err("Witness values must all be of the same size.".into());
+            }
+        }
+
+        let id = self.allocate_var();
+        let path = self.namespaced(name);
+        let names = self.witness.info.get_or_insert_with(Vec::new);
+        names.push(KeyValue::from((id, path)));
+        // Each variable has a name, so this counts variables with or without a value.
+        let batch_full = names.len() >= self.batch_size;
+
+        if let Some(value) = value {
+            self.witness.variable_ids.push(id);
+            if let Some(ref mut values) = self.witness.values {
+                values.extend_from_slice(value);
+            }
+        }
+
+        if batch_full {
+            self.flush_witness()?;
+        }
+        Ok(id)
+    }
+
+    /// Add the constraint A * B = C in the current namespace.
+    pub fn enforce(&mut self, a: impl Into<Variables>, b: impl Into<Variables>, c: impl Into<Variables>) -> Result<()> {
+        self.constraints.push(BilinearConstraint {
+            linear_combination_a: a.into(),
+            linear_combination_b: b.into(),
+            linear_combination_c: c.into(),
+        });
+        if self.constraints.len() >= self.batch_size {
+            self.flush_constraints()?;
+        }
+        Ok(())
+    }
+
+    /// Enter a nested namespace, recorded in the `info` of the next constraints, see `ConstraintSystem::namespace`.
+    pub fn push_namespace(&mut self, name: &str) -> Result<()> {
+        self.flush_constraints()?;
+        self.namespaces.push(name.to_string());
+        Ok(())
+    }
+
+    /// Leave the current namespace.
+    pub fn pop_namespace(&mut self) -> Result<()> {
+        self.flush_constraints()?;
+        self.namespaces.pop().ok_or("No namespace to leave.")?;
+        Ok(())
+    }
+
+    /// The current namespace, with nested namespaces separated by `NAMESPACE_SEPARATOR`.
+    pub fn namespace(&self) -> String {
+        self.namespaces.join(&NAMESPACE_SEPARATOR.to_string())
+    }
+
+    fn namespaced(&self, name: &str) -> String {
+        if self.namespaces.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}{}{}", self.namespace(), NAMESPACE_SEPARATOR, name)
+        }
+    }
+
+    /// Push the constraints and the witness that were not pushed yet.
+    pub fn flush(&mut self) -> Result<()> {
+        self.flush_constraints()?;
+        self.flush_witness()
+    }
+
+    /// Push the remaining constraints and witness, then the header.
+    /// Call this once all variables are allocated.
+    ///
+    /// The header comes last because its `free_variable_id` is only known then. This suits
+    /// `WorkspaceSink`, which writes the header into its own file that is read first.
+    /// For a sink that writes everything into a single stream, build into `Messages`,
+    /// then write the header before the other messages.
+    pub fn finish(&mut self) -> Result<()> {
+        self.flush()?;
+        self.finish_header()
+    }
+
+    fn flush_constraints(&mut self) -> Result<()> {
+        if self.constraints.is_empty() { return Ok(()); }
+
+        let cs = ConstraintSystem {
+            constraints: mem::take(&mut self.constraints),
+            info: None,
+        };
+        if self.namespaces.is_empty() {
+            self.sink.push_constraints(cs)
+        } else {
+            let namespace = self.namespace();
+            self.sink.push_constraints(cs.with_namespace(namespace))
+        }
+    }
+
+    fn flush_witness(&mut self) -> Result<()> {
+        if self.witness.variable_ids.is_empty() && self.witness.info.is_none() { return Ok(()); }
+
+        let empty = Variables { variable_ids: vec![], values: Some(vec![]), info: None };
+        let assigned_variables = mem::replace(&mut self.witness, empty);
+        self.sink.push_witness(Witness { assigned_variables })
+    }
 }
 
 impl<S: Sink> Sink for StatementBuilder<S> {
@@ -80,3 +244,66 @@ impl<S: Sink> Sink for StatementBuilder<S> {
     fn push_constraints(&mut self, cs: ConstraintSystem) -> Result<()> { self.sink.push_constraints(cs) }
     fn push_witness(&mut self, witness: Witness) -> Result<()> { self.sink.push_witness(witness) }
 }
+
+
+#[test]
+fn test_builder_enforce() -> Result<()> {
+    use crate::Messages;
+    use crate::consumers::simulator::Simulator;
+
+    let mut builder = StatementBuilder::new(Messages::default());
+    builder.header.field_maximum = Some(vec![100]);
+    builder.batch_size = 2;
+
+    let x = builder.allocate_instance_var(&[3]);
+    let y = builder.allocate_instance_var(&[4]);
+    let z = builder.allocate_instance_var(&[5]);
+
+    builder.push_namespace("pythagoras")?;
+    builder.push_namespace("squares")?;
+    let xx = builder.allocate_witness_var("xx", Some(&[9]))?;
+    let yy = builder.allocate_witness_var("yy", Some(&[16]))?;
+    let zz = builder.allocate_witness_var("zz", Some(&[25]))?;
+    builder.enforce(x, x, xx)?;
+    builder.enforce(y, y, yy)?;
+    builder.enforce(z, z, zz)?;
+    builder.pop_namespace()?;
+    assert_eq!(builder.namespace(), "pythagoras");
+
+    let sum = Variables { variable_ids: vec![xx, yy], values: Some(vec![1, 1]), info: None };
+    builder.enforce(0, sum, zz)?;
+    builder.pop_namespace()?;
+    assert!(builder.pop_namespace().is_err());
+    builder.finish()?;
+
+    let messages = builder.sink;
+    let namespaces = messages.constraint_systems.iter().map(|cs| cs.namespace()).collect::<Vec<_>>();
+    assert_eq!(namespaces, vec![Some("pythagoras/squares"), Some("pythagoras/squares"), Some("pythagoras")]);
+    assert_eq!(messages.witnesses.len(), 2);
+    assert_eq!(messages.witnesses[1].assigned_variables.get_names(), vec![(zz, "pythagoras/squares/zz")]);
+    assert_eq!(messages.circuit_headers[0].free_variable_id, 7);
+
+    let mut simulator = Simulator::default();
+    simulator.ingest_header(&messages.circuit_headers[0])?;
+    for witness in &messages.witnesses {
+        simulator.ingest_witness(witness)?;
+    }
+    for cs in &messages.constraint_systems {
+        simulator.ingest_constraint_system(cs)?;
+    }
+    assert_eq!(simulator.get_violations(), Vec::<String>::new());
+
+    // A value of the wrong size allocates nothing.
+    let mut builder = StatementBuilder::new(Messages::default());
+    builder.batch_size = 2;
+    builder.allocate_witness_var("a", Some(&[1]))?;
+    assert!(builder.allocate_witness_var("b", Some(&[1, 0])).is_err());
+    assert_eq!(builder.header.free_variable_id, 2);
+
+    // Variables without values count toward the batch.
+    builder.allocate_witness_var("c", None)?;
+    assert_eq!(builder.sink.witnesses.len(), 1);
+    assert_eq!(builder.sink.witnesses[0].assigned_variables.get_names(), vec![(1, "a"), (2, "c")]);
+
+    Ok(())
+}
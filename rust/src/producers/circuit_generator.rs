@@ -161,6 +161,7 @@ pub fn generate_metrics_data(sink: impl Sink, hexaprime: &str, wit_nbr: u64, ins
         assigned_variables: Variables {
             variable_ids: wit_idx, // xx, yy
             values: Some(witness_buffer),
+            info: None,
         }
     })?;
     builder.header.free_variable_id += wit_nbr;
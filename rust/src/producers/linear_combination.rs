@@ -0,0 +1,432 @@
+//! Linear combinations of variables, with coefficients in the field of a statement.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{Add, Sub, Neg, Mul};
+use std::sync::Arc;
+use num_bigint::BigUint;
+
+use crate::{Result, CircuitHeader, Variables};
+use crate::field::{Field, BigUintField};
+use crate::structs::variables::encode_minimal;
+
+/// The prime field of a statement, as given by `field_maximum` in its header.
+///
+/// Values mixing different fields cannot be combined: the operators panic,
+/// and the `checked_*` methods return an error instead.
+///
+/// # Example
+/// ```
+/// use zkinterface::Variables;
+/// use zkinterface::producers::examples::example_circuit_header;
+/// use zkinterface::producers::linear_combination::PrimeField;
+///
+/// let field = PrimeField::from_header(&example_circuit_header()).unwrap();
+/// let x = field.variable(1);
+/// let y = field.variable(2);
+///
+/// // 2x - y + 5
+/// let lc = x * 2 - y + field.element(5u32);
+/// assert_eq!(Variables::from(lc), Variables {
+///     variable_ids: vec![0, 1, 2],
+///     values: Some(vec![5, 2, 100]),
+///     info: None,
+/// });
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PrimeField {
+    field: Arc<BigUintField>,
+}
+
+impl PrimeField {
+    pub fn from_header(header: &CircuitHeader) -> Result<PrimeField> {
+        let modulus = header.modulus().ok_or("No field_maximum specified")?;
+        Ok(PrimeField::from_modulus(modulus))
+    }
+
+    pub fn from_modulus(modulus: BigUint) -> PrimeField {
+        PrimeField { field: Arc::new(BigUintField::new(modulus)) }
+    }
+
+    pub fn modulus(&self) -> BigUint {
+        self.field.modulus()
+    }
+
+    /// An element from a value, reduced modulo the field.
+    pub fn element(&self, value: impl Into<BigUint>) -> FieldElement {
+        FieldElement { value: self.field.element_from_biguint(&value.into()), field: self.clone() }
+    }
+
+    /// An element from a signed integer, where -k is encoded as p - k.
+    pub fn element_from_i64(&self, value: i64) -> FieldElement {
+        let element = self.element(value.unsigned_abs());
+        if value < 0 { -element } else { element }
+    }
+
+    pub fn variable(&self, id: u64) -> Variable {
+        Variable { id, field: self.clone() }
+    }
+
+    /// The variable 0, which is always one.
+    pub fn one(&self) -> Variable {
+        self.variable(0)
+    }
+}
+
+/// An element of a `PrimeField`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FieldElement {
+    value: BigUint,
+    field: PrimeField,
+}
+
+impl FieldElement {
+    /// The value, between 0 and the modulus excluded.
+    pub fn value(&self) -> &BigUint {
+        &self.value
+    }
+
+    pub fn field(&self) -> &PrimeField {
+        &self.field
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.field.field.is_zero(&self.value)
+    }
+
+    /// Add, or fail if the elements are in different fields.
+    pub fn checked_add(&self, other: &FieldElement) -> Result<FieldElement> {
+        check_fields(&self.field, &other.field)?;
+        Ok(FieldElement { value: self.field.field.add(&self.value, &other.value), field: self.field.clone() })
+    }
+
+    /// Subtract, or fail if the elements are in different fields.
+    pub fn checked_sub(&self, other: &FieldElement) -> Result<FieldElement> {
+        check_fields(&self.field, &other.field)?;
+        Ok(FieldElement { value: self.field.field.sub(&self.value, &other.value), field: self.field.clone() })
+    }
+
+    /// Multiply, or fail if the elements are in different fields.
+    pub fn checked_mul(&self, other: &FieldElement) -> Result<FieldElement> {
+        check_fields(&self.field, &other.field)?;
+        Ok(FieldElement { value: self.field.field.mul(&self.value, &other.value), field: self.field.clone() })
+    }
+}
+
+impl fmt::Display for FieldElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+impl Add for FieldElement {
+    type Output = FieldElement;
+
+    fn add(self, other: FieldElement) -> FieldElement {
+        self.checked_add(&other).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl Neg for FieldElement {
+    type Output = FieldElement;
+
+    fn neg(self) -> FieldElement {
+        let value = self.field.field.neg(&self.value);
+        FieldElement { value, field: self.field }
+    }
+}
+
+impl Sub for FieldElement {
+    type Output = FieldElement;
+
+    fn sub(self, other: FieldElement) -> FieldElement {
+        self.checked_sub(&other).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl Mul for FieldElement {
+    type Output = FieldElement;
+
+    fn mul(self, other: FieldElement) -> FieldElement {
+        self.checked_mul(&other).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+/// A variable of a statement, in a `PrimeField`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Variable {
+    pub id: u64,
+    field: PrimeField,
+}
+
+/// A sum of variables with coefficients in a `PrimeField`.
+///
+/// Terms are kept sorted by ID, with nonzero coefficients.
+/// A constant is a term of the variable 0.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LinearCombination {
+    terms: BTreeMap<u64, BigUint>,
+    field: PrimeField,
+}
+
+impl LinearCombination {
+    pub fn zero(field: &PrimeField) -> LinearCombination {
+        LinearCombination { terms: BTreeMap::new(), field: field.clone() }
+    }
+
+    pub fn field(&self) -> &PrimeField {
+        &self.field
+    }
+
+    /// The terms as variable IDs and coefficients, sorted by ID.
+    pub fn terms(&self) -> impl Iterator<Item=(u64, &BigUint)> {
+        self.terms.iter().map(|(id, coeff)| (*id, coeff))
+    }
+
+    /// Add, or fail if the combinations are in different fields.
+    pub fn checked_add(mut self, other: impl Into<LinearCombination>) -> Result<LinearCombination> {
+        let other = other.into();
+        check_fields(&self.field, &other.field)?;
+        for (id, coeff) in &other.terms {
+            self.add_term(*id, coeff);
+        }
+        Ok(self)
+    }
+
+    /// Subtract, or fail if the combinations are in different fields.
+    pub fn checked_sub(self, other: impl Into<LinearCombination>) -> Result<LinearCombination> {
+        self.checked_add(-other.into())
+    }
+
+    /// Multiply by a constant, or fail if it is in a different field.
+    pub fn checked_mul(mut self, factor: &FieldElement) -> Result<LinearCombination> {
+        check_fields(&self.field, &factor.field)?;
+        if factor.is_zero() {
+            self.terms.clear();
+        }
+        let field = &self.field.field;
+        for coeff in self.terms.values_mut() {
+            *coeff = field.mul(coeff, &factor.value);
+        }
+        Ok(self)
+    }
+
+    fn add_term(&mut self, id: u64, coeff: &BigUint) {
+        let field = &self.field.field;
+        let sum = field.add(&self.terms.remove(&id).unwrap_or_default(), coeff);
+        if !field.is_zero(&sum) {
+            self.terms.insert(id, sum);
+        }
+    }
+}
+
+impl From<Variable> for LinearCombination {
+    fn from(var: Variable) -> LinearCombination {
+        let mut lc = LinearCombination::zero(&var.field);
+        lc.add_term(var.id, &var.field.field.one());
+        lc
+    }
+}
+
+impl From<&Variable> for LinearCombination {
+    fn from(var: &Variable) -> LinearCombination {
+        LinearCombination::from(var.clone())
+    }
+}
+
+/// A constant, as a multiple of the variable 0.
+impl From<FieldElement> for LinearCombination {
+    fn from(constant: FieldElement) -> LinearCombination {
+        let mut lc = LinearCombination::zero(&constant.field);
+        lc.add_term(0, &constant.value);
+        lc
+    }
+}
+
+impl From<&LinearCombination> for LinearCombination {
+    fn from(lc: &LinearCombination) -> LinearCombination {
+        lc.clone()
+    }
+}
+
+/// Encode with the coefficients in little-endian, as with `Variables::normalize`.
+impl From<&LinearCombination> for Variables {
+    fn from(lc: &LinearCombination) -> Variables {
+        Variables {
+            variable_ids: lc.terms.keys().cloned().collect(),
+            values: Some(encode_minimal(lc.terms.values())),
+            info: None,
+        }
+    }
+}
+
+impl From<LinearCombination> for Variables {
+    fn from(lc: LinearCombination) -> Variables {
+        Variables::from(&lc)
+    }
+}
+
+impl From<Variable> for Variables {
+    fn from(var: Variable) -> Variables {
+        Variables::from(LinearCombination::from(var))
+    }
+}
+
+impl<T: Into<LinearCombination>> Add<T> for LinearCombination {
+    type Output = LinearCombination;
+
+    fn add(self, other: T) -> LinearCombination {
+        self.checked_add(other).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl<T: Into<LinearCombination>> Sub<T> for LinearCombination {
+    type Output = LinearCombination;
+
+    fn sub(self, other: T) -> LinearCombination {
+        self.checked_sub(other).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl Neg for LinearCombination {
+    type Output = LinearCombination;
+
+    fn neg(mut self) -> LinearCombination {
+        let field = &self.field.field;
+        for coeff in self.terms.values_mut() {
+            *coeff = field.neg(coeff);
+        }
+        self
+    }
+}
+
+impl Mul<FieldElement> for LinearCombination {
+    type Output = LinearCombination;
+
+    fn mul(self, factor: FieldElement) -> LinearCombination {
+        self * &factor
+    }
+}
+
+impl Mul<&FieldElement> for LinearCombination {
+    type Output = LinearCombination;
+
+    fn mul(self, factor: &FieldElement) -> LinearCombination {
+        self.checked_mul(factor).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl Mul<i64> for LinearCombination {
+    type Output = LinearCombination;
+
+    fn mul(self, factor: i64) -> LinearCombination {
+        let factor = self.field.element_from_i64(factor);
+        self * factor
+    }
+}
+
+impl<T: Into<LinearCombination>> Add<T> for Variable {
+    type Output = LinearCombination;
+
+    fn add(self, other: T) -> LinearCombination {
+        LinearCombination::from(self) + other
+    }
+}
+
+impl<T: Into<LinearCombination>> Sub<T> for Variable {
+    type Output = LinearCombination;
+
+    fn sub(self, other: T) -> LinearCombination {
+        LinearCombination::from(self) - other
+    }
+}
+
+impl Neg for Variable {
+    type Output = LinearCombination;
+
+    fn neg(self) -> LinearCombination {
+        -LinearCombination::from(self)
+    }
+}
+
+impl Mul<FieldElement> for Variable {
+    type Output = LinearCombination;
+
+    fn mul(self, factor: FieldElement) -> LinearCombination {
+        LinearCombination::from(self) * factor
+    }
+}
+
+impl Mul<&FieldElement> for Variable {
+    type Output = LinearCombination;
+
+    fn mul(self, factor: &FieldElement) -> LinearCombination {
+        LinearCombination::from(self) * factor
+    }
+}
+
+impl Mul<i64> for Variable {
+    type Output = LinearCombination;
+
+    fn mul(self, factor: i64) -> LinearCombination {
+        LinearCombination::from(self) * factor
+    }
+}
+
+fn check_fields(a: &PrimeField, b: &PrimeField) -> Result<()> {
+    if a != b {
+        return Err(format!("Cannot combine values of different fields, with moduli {} and {}.", a.modulus(), b.modulus()).into());
+    }
+    Ok(())
+}
+
+
+#[test]
+fn test_linear_combination() -> Result<()> {
+    use crate::{StatementBuilder, Messages};
+    use crate::producers::examples::*;
+
+    let field = PrimeField::from_header(&example_circuit_header())?;
+    let x = field.variable(1);
+    let y = field.variable(2);
+
+    // Negative coefficients.
+    assert_eq!(Variables::from(x.clone() - y.clone() * 3), Variables {
+        variable_ids: vec![1, 2],
+        values: Some(vec![1, 98]),
+        info: None,
+    });
+    assert_eq!(field.element_from_i64(-1) * field.element_from_i64(-1), field.element(1u32));
+    assert_eq!(field.element(100u32) + field.element(2u32), field.element(1u32));
+
+    // Terms that cancel out, and wide coefficients.
+    let lc = x.clone() * 50 + y.clone() * -1 + (x.clone() * 51) + y.clone();
+    assert_eq!(lc, LinearCombination::zero(&field));
+    let wide = PrimeField::from_modulus(BigUint::from(65537u32));
+    assert_eq!(Variables::from(wide.variable(1) * -1 + wide.variable(2)).values, Some(vec![0, 0, 1, 1, 0, 0]));
+
+    // The example constraints, built with the algebra.
+    let mut builder = StatementBuilder::new(Messages::default());
+    let xx = field.variable(4);
+    let yy = field.variable(5);
+    let zz = field.variable(3);
+    builder.enforce(x.clone(), x, xx.clone())?;
+    builder.enforce(y.clone(), y, yy.clone())?;
+    builder.enforce(field.one(), xx + yy, zz)?;
+    builder.flush()?;
+    assert_eq!(builder.sink.constraint_systems, vec![example_constraints()]);
+
+    Ok(())
+}
+
+#[test]
+#[should_panic]
+fn test_linear_combination_fields() {
+    let a = PrimeField::from_modulus(BigUint::from(101u32));
+    let b = PrimeField::from_modulus(BigUint::from(103u32));
+    assert!(LinearCombination::from(a.variable(1)).checked_add(b.variable(1)).is_err());
+    assert!(LinearCombination::from(a.variable(1)).checked_mul(&b.element(2u32)).is_err());
+    assert!(a.element(1u32).checked_sub(&b.element(1u32)).is_err());
+    assert!(a.element(1u32).checked_add(&a.element(1u32)).is_ok());
+    let _ = a.variable(1) + b.variable(1);
+}
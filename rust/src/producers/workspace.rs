@@ -19,10 +19,14 @@ pub fn clean_workspace(workspace: impl AsRef<Path>) -> Result<()> {
 
 
 /// Store messages into files using conventional filenames inside of a workspace.
+/// All headers pushed into the same sink are written into header.zkif, in order.
 pub struct WorkspaceSink {
     pub workspace: PathBuf,
+    header_file: Option<File>,
     pub witness_file: Option<File>,
     cs_file_counter: u32,
+    /// If set, each witness message goes into its own file.
+    witness_file_counter: Option<u32>,
 }
 
 impl WorkspaceSink {
@@ -30,17 +34,34 @@ impl WorkspaceSink {
         create_dir_all(workspace.as_ref())?;
         Ok(WorkspaceSink {
             workspace: workspace.as_ref().to_path_buf(),
+            header_file: None,
             witness_file: None,
             cs_file_counter: 0,
+            witness_file_counter: None,
+        })
+    }
+
+    /// Like `new`, but write each witness message into its own file, like constraints.
+    pub fn new_with_witness_files(workspace: impl AsRef<Path>) -> Result<WorkspaceSink> {
+        Ok(WorkspaceSink {
+            witness_file_counter: Some(0),
+            ..Self::new(workspace)?
         })
     }
 }
 
 impl Sink for WorkspaceSink {
     fn push_header(&mut self, header: CircuitHeader) -> Result<()> {
-        let mut file = File::create(
-            self.workspace.join("header.zkif"))?;
-        header.write_into(&mut file)
+        let file = match self.header_file {
+            None => {
+                self.header_file = Some(File::create(
+                    self.workspace.join("header.zkif"))?);
+                self.header_file.as_mut().unwrap()
+            }
+            Some(ref mut file) => file,
+        };
+
+        header.write_into(file)
     }
 
     fn push_constraints(&mut self, cs: ConstraintSystem) -> Result<()> {
@@ -50,6 +71,12 @@ impl Sink for WorkspaceSink {
     }
 
     fn push_witness(&mut self, witness: Witness) -> Result<()> {
+        if let Some(counter) = &mut self.witness_file_counter {
+            let mut witness_file = File::create(self.workspace.join(format!("witness_{}.zkif", counter)))?;
+            *counter += 1;
+            return witness.write_into(&mut witness_file);
+        }
+
         let file = match self.witness_file {
             None => {
                 self.witness_file = Some(File::create(
@@ -4,6 +4,8 @@ use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use std::io::Write;
 use serde::{Deserialize, Serialize};
 use crate::zkinterface_generated::zkinterface as fb;
+use super::keyvalue::KeyValue;
+use crate::consumers::verifier::read_root;
 use crate::Result;
 use std::convert::TryFrom;
 use std::error::Error;
@@ -13,7 +15,7 @@ use std::error::Error;
 pub struct Command {
     pub constraints_generation: bool,
     pub witness_generation: bool,
-    //pub parameters: Option<Vec<KeyValue>>,
+    pub parameters: Option<Vec<KeyValue>>,
 }
 
 impl<'a> From<fb::Command<'a>> for Command {
@@ -22,6 +24,7 @@ impl<'a> From<fb::Command<'a>> for Command {
         Command {
             constraints_generation: fb_command.constraints_generation(),
             witness_generation: fb_command.witness_generation(),
+            parameters: KeyValue::from_vector(fb_command.parameters()),
         }
     }
 }
@@ -31,7 +34,7 @@ impl<'a> TryFrom<&'a [u8]> for Command {
 
     fn try_from(buffer: &'a [u8]) -> Result<Self> {
         Ok(Self::from(
-            fb::get_size_prefixed_root_as_root(&buffer)
+            read_root(buffer)?
                 .message_as_command()
                 .ok_or("Not a Command message.")?))
     }
@@ -44,10 +47,13 @@ impl Command {
         builder: &'mut_bldr mut FlatBufferBuilder<'bldr>,
     ) -> WIPOffset<fb::Root<'bldr>>
     {
+        let parameters = self.parameters.as_ref().map(|parameters|
+            KeyValue::build_vector(parameters, builder));
+
         let call = fb::Command::create(builder, &fb::CommandArgs {
             constraints_generation: self.constraints_generation,
             witness_generation: self.witness_generation,
-            parameters: None,
+            parameters,
         });
 
         fb::Root::create(builder, &fb::RootArgs {
@@ -1,14 +1,26 @@
 use std::io::Write;
+use num_bigint::BigUint;
 use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use serde::{Deserialize, Serialize};
 use crate::{Result, Variables};
+use super::keyvalue::KeyValue;
 use crate::zkinterface_generated::zkinterface as fb;
+use crate::consumers::verifier::read_root;
 use std::convert::TryFrom;
 use std::error::Error;
 
+/// The key in `ConstraintSystem.info` whose text names the gadget that produced the constraints.
+///
+/// Nested namespaces are separated by `NAMESPACE_SEPARATOR`, from the outermost, as in `sha256/round_3/add`.
+/// `consumers::stats::Stats` groups the cost of constraints by namespace.
+pub const NAMESPACE_KEY: &str = "namespace";
+pub const NAMESPACE_SEPARATOR: char = '/';
+
 #[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct ConstraintSystem {
     pub constraints: Vec<BilinearConstraint>,
+
+    pub info: Option<Vec<KeyValue>>,
 }
 
 #[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
@@ -23,16 +35,18 @@ impl<'a> From<fb::ConstraintSystem<'a>> for ConstraintSystem {
     fn from(fb_cs: fb::ConstraintSystem) -> ConstraintSystem {
         let mut cs = ConstraintSystem {
             constraints: vec![],
+            info: KeyValue::from_vector(fb_cs.info()),
         };
 
-        let fb_constraints = fb_cs.constraints().unwrap();
-        for i in 0..fb_constraints.len() {
-            let fb_constraint = fb_constraints.get(i);
-            cs.constraints.push(BilinearConstraint {
-                linear_combination_a: Variables::from(fb_constraint.linear_combination_a().unwrap()),
-                linear_combination_b: Variables::from(fb_constraint.linear_combination_b().unwrap()),
-                linear_combination_c: Variables::from(fb_constraint.linear_combination_c().unwrap()),
-            });
+        if let Some(fb_constraints) = fb_cs.constraints() {
+            for i in 0..fb_constraints.len() {
+                let fb_constraint = fb_constraints.get(i);
+                cs.constraints.push(BilinearConstraint {
+                    linear_combination_a: fb_constraint.linear_combination_a().map(Variables::from).unwrap_or_default(),
+                    linear_combination_b: fb_constraint.linear_combination_b().map(Variables::from).unwrap_or_default(),
+                    linear_combination_c: fb_constraint.linear_combination_c().map(Variables::from).unwrap_or_default(),
+                });
+            }
         }
 
         cs
@@ -44,7 +58,7 @@ impl<'a> TryFrom<&'a [u8]> for ConstraintSystem {
 
     fn try_from(buffer: &'a [u8]) -> Result<Self> {
         Ok(Self::from(
-            fb::get_size_prefixed_root_as_root(&buffer)
+            read_root(buffer)?
                 .message_as_constraint_system()
                 .ok_or("Not a ConstraintSystem message.")?))
     }
@@ -67,21 +81,25 @@ impl From<&[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))]> fo
 
     fn from(constraints_vec: &[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))]) -> ConstraintSystem {
         let mut constraints = ConstraintSystem {
-            constraints: vec![]
+            constraints: vec![],
+            info: None,
         };
 
         for (lca, lcb, lcc) in constraints_vec {
             let lca = Variables {
                 variable_ids: lca.0.clone(),
                 values: Some(lca.1.clone()),
+                info: None,
             };
             let lcb = Variables {
                 variable_ids: lcb.0.clone(),
                 values: Some(lcb.1.clone()),
+                info: None,
             };
             let lcc = Variables {
                 variable_ids: lcc.0.clone(),
                 values: Some(lcc.1.clone()),
+                info: None,
             };
             constraints.constraints.push(BilinearConstraint {
                 linear_combination_a: lca,
@@ -94,6 +112,23 @@ impl From<&[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))]> fo
 }
 
 impl BilinearConstraint {
+    /// Normalize the three linear combinations with `Variables::normalize`.
+    /// With `swap_ab`, A and B are also put in a canonical order, since A * B = B * A.
+    pub fn normalize(&self, modulus: Option<&BigUint>, swap_ab: bool) -> BilinearConstraint {
+        let a = self.linear_combination_a.normalize(modulus);
+        let b = self.linear_combination_b.normalize(modulus);
+        let c = self.linear_combination_c.normalize(modulus);
+
+        let swap = swap_ab && (&b.variable_ids, &b.values) < (&a.variable_ids, &a.values);
+        let (a, b) = if swap { (b, a) } else { (a, b) };
+
+        BilinearConstraint {
+            linear_combination_a: a,
+            linear_combination_b: b,
+            linear_combination_c: c,
+        }
+    }
+
     /// Add this structure into a Flatbuffers message builder.
     pub fn build<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
         &'args self,
@@ -113,6 +148,55 @@ impl BilinearConstraint {
 }
 
 impl ConstraintSystem {
+    /// Normalize all constraints with `BilinearConstraint::normalize`. The order of constraints is kept.
+    ///
+    /// # Example
+    /// ```
+    /// use zkinterface::ConstraintSystem;
+    /// use num_bigint::BigUint;
+    ///
+    /// let cs = ConstraintSystem::from(&[
+    ///     // (2x + 0y) * 1 = 2x + 101x
+    ///     ((vec![1, 2], vec![2, 0]), (vec![0], vec![1]), (vec![1, 1], vec![2, 101])),
+    /// ][..]);
+    ///
+    /// let normalized = cs.normalize(Some(&BigUint::from(101u32)), true);
+    /// assert_eq!(normalized.constraints[0].linear_combination_a.variable_ids, vec![0]);
+    /// assert_eq!(normalized.constraints[0].linear_combination_b.variable_ids, vec![1]);
+    /// assert_eq!(normalized.constraints[0].linear_combination_c.values, Some(vec![2]));
+    /// ```
+    pub fn normalize(&self, modulus: Option<&BigUint>, swap_ab: bool) -> ConstraintSystem {
+        ConstraintSystem {
+            constraints: self.constraints.iter()
+                .map(|constraint| constraint.normalize(modulus, swap_ab))
+                .collect(),
+            info: self.info.clone(),
+        }
+    }
+
+    /// The namespace of the constraints, see `NAMESPACE_KEY`.
+    pub fn namespace(&self) -> Option<&str> {
+        self.info.iter().flatten()
+            .find(|kv| kv.key == NAMESPACE_KEY)
+            .and_then(|kv| kv.text.as_deref())
+    }
+
+    /// Set the namespace of the constraints, see `NAMESPACE_KEY`.
+    ///
+    /// # Example
+    /// ```
+    /// use zkinterface::producers::examples::example_constraints;
+    ///
+    /// let cs = example_constraints().with_namespace("pythagoras/squares");
+    /// assert_eq!(cs.namespace(), Some("pythagoras/squares"));
+    /// ```
+    pub fn with_namespace(mut self, namespace: impl ToString) -> ConstraintSystem {
+        let info = self.info.get_or_insert_with(Vec::new);
+        info.retain(|kv| kv.key != NAMESPACE_KEY);
+        info.push(KeyValue::from((NAMESPACE_KEY, namespace.to_string())));
+        self
+    }
+
     /// Add this structure into a Flatbuffers message builder.
     pub fn build<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
         &'args self,
@@ -125,9 +209,13 @@ impl ConstraintSystem {
             ).collect();
 
         let constraints_built = builder.create_vector(&constraints_built);
+
+        let info = self.info.as_ref().map(|info|
+            KeyValue::build_vector(info, builder));
+
         let r1cs = fb::ConstraintSystem::create(builder, &fb::ConstraintSystemArgs {
             constraints: Some(constraints_built),
-            info: None,
+            info,
         });
 
         fb::Root::create(builder, &fb::RootArgs {
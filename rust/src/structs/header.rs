@@ -4,12 +4,14 @@ use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use std::io::Write;
 use serde::{Deserialize, Serialize};
 use crate::zkinterface_generated::zkinterface as fb;
+use crate::consumers::verifier::read_root;
 use super::variables::Variables;
 use super::keyvalue::KeyValue;
 use crate::Result;
 use std::convert::TryFrom;
 use std::error::Error;
 use std::collections::HashSet;
+use num_bigint::BigUint;
 
 
 #[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
@@ -27,7 +29,7 @@ impl<'a> From<fb::CircuitHeader<'a>> for CircuitHeader {
     /// Convert from Flatbuffers references to owned structure.
     fn from(fb_header: fb::CircuitHeader) -> CircuitHeader {
         CircuitHeader {
-            instance_variables: Variables::from(fb_header.instance_variables().unwrap()),
+            instance_variables: fb_header.instance_variables().map(Variables::from).unwrap_or_default(),
             free_variable_id: fb_header.free_variable_id(),
             field_maximum: fb_header.field_maximum().map(Vec::from),
             configuration: KeyValue::from_vector(fb_header.configuration()),
@@ -40,13 +42,29 @@ impl<'a> TryFrom<&'a [u8]> for CircuitHeader {
 
     fn try_from(buffer: &'a [u8]) -> Result<Self> {
         Ok(Self::from(
-            fb::get_size_prefixed_root_as_root(&buffer)
+            read_root(buffer)?
                 .message_as_circuit_header()
                 .ok_or("Not a CircuitHeader message.")?))
     }
 }
 
 impl CircuitHeader {
+    /// The order of the field, which is field_maximum + 1.
+    pub fn modulus(&self) -> Option<BigUint> {
+        self.field_maximum.as_ref().map(|max| BigUint::from_bytes_le(max) + 1u8)
+    }
+
+    /// Normalize the instance values with `Variables::normalize_values`,
+    /// and encode field_maximum without trailing zero bytes.
+    pub fn normalize(&self) -> CircuitHeader {
+        let modulus = self.modulus();
+        CircuitHeader {
+            instance_variables: self.instance_variables.normalize_values(modulus.as_ref()),
+            field_maximum: self.field_maximum.as_ref().map(|max| BigUint::from_bytes_le(max).to_bytes_le()),
+            ..self.clone()
+        }
+    }
+
     /// Enumerate the IDs of witness variables based on a header.
     /// # Example
     /// ```
@@ -78,6 +96,7 @@ impl CircuitHeader {
             instance_variables: Variables {
                 variable_ids: (first_input_id..first_local_id).collect(),
                 values: None,
+                info: None,
             },
             free_variable_id: first_local_id,
             field_maximum: None,
@@ -94,6 +113,7 @@ impl CircuitHeader {
             instance_variables: Variables {
                 variable_ids: (first_output_id..first_local_id).collect(),
                 values: None,
+                info: None,
             },
             free_variable_id: first_local_id + num_locals,
             field_maximum: None,
@@ -152,6 +172,7 @@ fn test_circuit_header() {
         instance_variables: Variables {
             variable_ids: (1..3).collect(),
             values: Some(vec![6, 7]),
+            info: None,
         },
         free_variable_id: 3,
         field_maximum: Some(vec![8]),
@@ -41,7 +41,7 @@ impl<'a> From<fb::KeyValue<'a>> for KeyValue {
     /// Convert from Flatbuffers references to owned structure.
     fn from(fb_kv: fb::KeyValue) -> KeyValue {
         KeyValue {
-            key: fb_kv.key().unwrap().into(),
+            key: fb_kv.key().unwrap_or_default().into(),
             text: fb_kv.text().map(|d| String::from(d)),
             data: fb_kv.data().map(|d| Vec::from(d)),
             number: fb_kv.number(),
@@ -1,5 +1,6 @@
 use serde::{Serialize, Deserialize};
 use crate::zkinterface_generated::zkinterface as fb;
+use crate::consumers::verifier::read_root;
 use crate::{CircuitHeader, ConstraintSystem, Witness, Command};
 
 #[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
@@ -12,29 +13,40 @@ pub enum Message {
 }
 
 impl<'a> From<&'a [u8]> for Message {
+    /// Verify and convert a size-prefixed message.
+    /// Invalid messages are converted to `Message::Err`.
     fn from(buffer: &'a [u8]) -> Self {
-        let msg = fb::get_size_prefixed_root_as_root(&buffer);
+        match read_root(buffer) {
+            Ok(msg) => Message::from(msg),
+            Err(err) => Message::Err(err.to_string()),
+        }
+    }
+}
 
-        match msg.message_type() {
+impl<'a> From<fb::Root<'a>> for Message {
+    /// Convert from Flatbuffers references to owned structure.
+    fn from(msg: fb::Root<'a>) -> Self {
+        let message = match msg.message_type() {
             fb::Message::CircuitHeader => {
-                let fb_header = msg.message_as_circuit_header().unwrap();
-                Message::Header(CircuitHeader::from(fb_header))
+                msg.message_as_circuit_header().map(|fb_header|
+                    Message::Header(CircuitHeader::from(fb_header)))
             }
             fb::Message::ConstraintSystem => {
-                let fb_constraints = msg.message_as_constraint_system().unwrap();
-                Message::ConstraintSystem(ConstraintSystem::from(fb_constraints))
+                msg.message_as_constraint_system().map(|fb_constraints|
+                    Message::ConstraintSystem(ConstraintSystem::from(fb_constraints)))
             }
             fb::Message::Witness => {
-                let fb_witness = msg.message_as_witness().unwrap();
-                Message::Witness(Witness::from(fb_witness))
+                msg.message_as_witness().map(|fb_witness|
+                    Message::Witness(Witness::from(fb_witness)))
             }
             fb::Message::Command => {
-                let fb_command = msg.message_as_command().unwrap();
-                Message::Command(Command::from(fb_command))
+                msg.message_as_command().map(|fb_command|
+                    Message::Command(Command::from(fb_command)))
             }
             fb::Message::NONE => {
-                Message::Err("Invalid message type".into())
+                return Message::Err("Invalid message type".into());
             }
-        }
+        };
+        message.unwrap_or_else(|| Message::Err("Missing message content".into()))
     }
-}
\ No newline at end of file
+}
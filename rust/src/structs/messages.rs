@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
 
-use crate::Workspace;
+use crate::{Workspace, Result};
 use crate::consumers::reader::Reader;
-use crate::zkinterface_generated::zkinterface as fb;
+use crate::producers::builder::Sink;
 use super::header::CircuitHeader;
 use super::constraints::ConstraintSystem;
 use super::witness::Witness;
@@ -21,24 +22,12 @@ impl From<&Reader> for Messages {
         let mut messages = Messages::default();
 
         for msg in reader.into_iter() {
-            match msg.message_type() {
-                fb::Message::CircuitHeader => {
-                    let fb_header = msg.message_as_circuit_header().unwrap();
-                    messages.circuit_headers.push(
-                        CircuitHeader::from(fb_header));
-                }
-                fb::Message::ConstraintSystem => {
-                    let fb_constraints = msg.message_as_constraint_system().unwrap();
-                    messages.constraint_systems.push(
-                        ConstraintSystem::from(fb_constraints));
-                }
-                fb::Message::Witness => {
-                    let fb_witness = msg.message_as_witness().unwrap();
-                    messages.witnesses.push(
-                        Witness::from(fb_witness));
-                }
-                fb::Message::Command => {}
-                fb::Message::NONE => {}
+            match Message::from(msg) {
+                Message::Header(h) => messages.circuit_headers.push(h),
+                Message::ConstraintSystem(cs) => messages.constraint_systems.push(cs),
+                Message::Witness(w) => messages.witnesses.push(w),
+                Message::Command(_) => {}
+                Message::Err(_) => {}
             }
         }
         messages
@@ -61,4 +50,187 @@ impl From<&Workspace> for Messages {
         }
         messages
     }
-}
\ No newline at end of file
+}
+
+/// Collect messages in memory.
+impl Sink for Messages {
+    fn push_header(&mut self, header: CircuitHeader) -> Result<()> {
+        self.circuit_headers.push(header);
+        Ok(())
+    }
+
+    fn push_constraints(&mut self, cs: ConstraintSystem) -> Result<()> {
+        self.constraint_systems.push(cs);
+        Ok(())
+    }
+
+    fn push_witness(&mut self, witness: Witness) -> Result<()> {
+        self.witnesses.push(witness);
+        Ok(())
+    }
+}
+
+impl Messages {
+    /// Parse messages from their JSON form, as produced by `zkif to-json`.
+    pub fn from_json(reader: impl Read) -> Result<Messages> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Parse messages from their YAML form, as produced by `zkif to-yaml`.
+    pub fn from_yaml(reader: impl Read) -> Result<Messages> {
+        Ok(serde_yaml::from_reader(reader)?)
+    }
+
+    /// Push all messages into a sink, such as a `WorkspaceSink`.
+    /// Headers come first, then witnesses, then constraint systems, each in their original order.
+    /// `Messages` does not record the order between messages of different types, so messages
+    /// read from a stream that interleaves them, then pushed back, come in this order instead.
+    pub fn push_into(&self, sink: &mut impl Sink) -> Result<()> {
+        for header in &self.circuit_headers {
+            sink.push_header(header.clone())?;
+        }
+        for witness in &self.witnesses {
+            sink.push_witness(witness.clone())?;
+        }
+        for cs in &self.constraint_systems {
+            sink.push_constraints(cs.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Writes all messages as a single stream of Flatbuffers messages into the provided buffer.
+    /// Headers come first, then witnesses, then constraint systems, each in their original order.
+    ///
+    /// # Examples
+    /// ```
+    /// use zkinterface::{Messages, Reader};
+    /// use zkinterface::producers::examples::*;
+    ///
+    /// let messages = Messages {
+    ///     circuit_headers: vec![example_circuit_header()],
+    ///     constraint_systems: vec![example_constraints()],
+    ///     witnesses: vec![example_witness()],
+    /// };
+    ///
+    /// let mut buf = Vec::<u8>::new();
+    /// messages.write_into(&mut buf).unwrap();
+    ///
+    /// let mut reader = Reader::new();
+    /// reader.push_message(buf).unwrap();
+    /// assert_eq!(Messages::from(&reader), messages);
+    /// ```
+    pub fn write_into(&self, writer: &mut impl Write) -> Result<()> {
+        for header in &self.circuit_headers {
+            header.write_into(writer)?;
+        }
+        for witness in &self.witnesses {
+            witness.write_into(writer)?;
+        }
+        for cs in &self.constraint_systems {
+            cs.write_into(writer)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_messages_json_roundtrip() -> Result<()> {
+    use std::fs::remove_dir_all;
+    use std::path::PathBuf;
+    use crate::WorkspaceSink;
+    use crate::producers::examples::*;
+
+    let messages = Messages {
+        circuit_headers: vec![example_circuit_header()],
+        constraint_systems: vec![example_constraints(), example_constraints()],
+        witnesses: vec![example_witness(), example_witness_inputs(5, 6)],
+    };
+
+    // Through a single stream.
+    let json = serde_json::to_string(&messages)?;
+    let parsed = Messages::from_json(json.as_bytes())?;
+    assert_eq!(parsed, messages);
+
+    let mut buf = vec![];
+    parsed.write_into(&mut buf)?;
+    let mut reader = Reader::new();
+    reader.push_message(buf)?;
+    assert_eq!(serde_json::to_string(&Messages::from(&reader))?, json);
+
+    // Through a workspace.
+    let yaml = serde_yaml::to_string(&messages)?;
+    let parsed = Messages::from_yaml(yaml.as_bytes())?;
+    assert_eq!(parsed, messages);
+
+    let workspace = PathBuf::from("local/test_messages_roundtrip");
+    let _ = remove_dir_all(&workspace);
+    parsed.push_into(&mut WorkspaceSink::new(&workspace)?)?;
+    let ws = Workspace::from_dir(&workspace)?;
+    assert_eq!(serde_yaml::to_string(&ws.read_all_messages())?, yaml);
+
+    // All headers are written.
+    let mut other_header = example_circuit_header();
+    other_header.free_variable_id = 10;
+    let mut two_headers = messages;
+    two_headers.circuit_headers.push(other_header);
+    let _ = remove_dir_all(&workspace);
+    two_headers.push_into(&mut WorkspaceSink::new(&workspace)?)?;
+    assert_eq!(Workspace::from_dir(&workspace)?.read_all_messages(), two_headers);
+
+    Ok(())
+}
+
+#[test]
+fn test_messages_info_roundtrip() -> Result<()> {
+    use std::convert::TryFrom;
+    use crate::{Command, KeyValue};
+    use crate::producers::examples::*;
+
+    let mut header = example_circuit_header();
+    header.instance_variables.info = Some(vec![
+        KeyValue::from(("1", "x")),
+        KeyValue::from(("2", "y")),
+    ]);
+    let mut constraints = example_constraints();
+    constraints.info = Some(vec![KeyValue::from(("gadget", "pythagoras"))]);
+    constraints.constraints[2].linear_combination_c.info = Some(vec![KeyValue::from(("3", "zz"))]);
+    let mut witness = example_witness();
+    witness.assigned_variables.info = Some(vec![KeyValue::from(("4", vec![1, 2, 3]))]);
+
+    let messages = Messages {
+        circuit_headers: vec![header],
+        constraint_systems: vec![constraints],
+        witnesses: vec![witness],
+    };
+
+    // Through Flatbuffers.
+    let mut buf = vec![];
+    messages.write_into(&mut buf)?;
+    let mut reader = Reader::new();
+    reader.push_message(buf)?;
+    assert_eq!(Messages::from(&reader), messages);
+
+    // Through JSON and YAML.
+    let json = serde_json::to_string(&messages)?;
+    assert_eq!(Messages::from_json(json.as_bytes())?, messages);
+    let yaml = serde_yaml::to_string(&messages)?;
+    assert_eq!(Messages::from_yaml(yaml.as_bytes())?, messages);
+
+    let command = Command {
+        constraints_generation: true,
+        witness_generation: false,
+        parameters: Some(vec![
+            KeyValue::from(("curve", "bls12-381")),
+            KeyValue::from(("depth", 20)),
+        ]),
+    };
+    let mut buf = vec![];
+    command.write_into(&mut buf)?;
+    assert_eq!(Command::try_from(&buf[..])?, command);
+    assert_eq!(Message::from(&buf[..]), Message::Command(command.clone()));
+
+    let json = serde_json::to_string(&command)?;
+    assert_eq!(serde_json::from_str::<Command>(&json)?, command);
+
+    Ok(())
+}
@@ -1,14 +1,18 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use num_bigint::BigUint;
+use num_traits::Zero;
 
 use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use crate::zkinterface_generated::zkinterface as fb;
 use crate::consumers::reader::{Variable, get_value_size};
+use super::keyvalue::KeyValue;
 
 #[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Variables {
     pub variable_ids: Vec<u64>,
     pub values: Option<Vec<u8>>,
-    // pub info: Option<Vec<(String, &'a [u8])>>,
+    pub info: Option<Vec<KeyValue>>,
 }
 
 impl<'a> From<fb::Variables<'a>> for Variables {
@@ -23,6 +27,18 @@ impl<'a> From<fb::Variables<'a>> for Variables {
                 Some(bytes) => Some(Vec::from(bytes)),
                 None => None,
             },
+            info: KeyValue::from_vector(fb_variables.info()),
+        }
+    }
+}
+
+/// A single variable with the coefficient 1, as a linear combination.
+impl From<u64> for Variables {
+    fn from(id: u64) -> Self {
+        Variables {
+            variable_ids: vec![id],
+            values: Some(vec![1]),
+            info: None,
         }
     }
 }
@@ -46,6 +62,85 @@ impl Variables {
             ).collect()
     }
 
+    /// Human-readable names of variables, found in `info` as a variable ID in the key and a name in the text.
+    ///
+    /// # Example
+    /// ```
+    /// use zkinterface::{Variables, KeyValue};
+    ///
+    /// let vars = Variables {
+    ///     variable_ids: vec![1, 2],
+    ///     values: None,
+    ///     info: Some(vec![KeyValue::from(("1", "x")), KeyValue::from(("comment", "y is unnamed"))]),
+    /// };
+    /// assert_eq!(vars.get_names(), vec![(1, "x")]);
+    /// ```
+    pub fn get_names(&self) -> Vec<(u64, &str)> {
+        match self.info {
+            Some(ref info) => info.iter()
+                .filter_map(|kv| {
+                    let id = kv.key.parse::<u64>().ok()?;
+                    let name = kv.text.as_ref()?;
+                    Some((id, name.as_str()))
+                }).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Normalize as a linear combination: merge duplicate IDs, reduce coefficients modulo the field,
+    /// drop zero terms, sort by ID, and encode coefficients with the smallest common width.
+    ///
+    /// # Example
+    /// ```
+    /// use zkinterface::Variables;
+    /// use num_bigint::BigUint;
+    ///
+    /// let lc = Variables {
+    ///     variable_ids: vec![3, 1, 3, 2],
+    ///     values: Some(vec![
+    ///         60, 0,
+    ///         2, 0,
+    ///         50, 0,
+    ///         0, 0,
+    ///     ]),
+    ///     info: None,
+    /// };
+    /// let normalized = lc.normalize(Some(&BigUint::from(101u32)));
+    /// assert_eq!(normalized.variable_ids, vec![1, 3]);
+    /// assert_eq!(normalized.values, Some(vec![2, 9]));
+    /// ```
+    pub fn normalize(&self, modulus: Option<&BigUint>) -> Variables {
+        let mut terms = BTreeMap::<u64, BigUint>::new();
+        for var in self.get_variables() {
+            *terms.entry(var.id).or_default() += BigUint::from_bytes_le(var.value);
+        }
+        let terms = terms.into_iter()
+            .map(|(id, coeff)| (id, reduce(coeff, modulus)))
+            .filter(|(_, coeff)| !coeff.is_zero())
+            .collect::<Vec<_>>();
+
+        Variables {
+            variable_ids: terms.iter().map(|(id, _)| *id).collect(),
+            values: Some(encode_minimal(terms.iter().map(|(_, coeff)| coeff))),
+            info: self.info.clone(),
+        }
+    }
+
+    /// Normalize as assigned values: reduce values modulo the field, sort by ID,
+    /// and encode values with the smallest common width. Zero values are kept.
+    pub fn normalize_values(&self, modulus: Option<&BigUint>) -> Variables {
+        let mut vars = self.get_variables().iter()
+            .map(|var| (var.id, reduce(BigUint::from_bytes_le(var.value), modulus)))
+            .collect::<Vec<_>>();
+        vars.sort_by_key(|(id, _)| *id);
+
+        Variables {
+            variable_ids: vars.iter().map(|(id, _)| *id).collect(),
+            values: self.values.as_ref().map(|_| encode_minimal(vars.iter().map(|(_, value)| value))),
+            info: self.info.clone(),
+        }
+    }
+
     pub fn value_size(&self) -> usize {
         match self.values {
             Some(ref values) => get_value_size(&self.variable_ids, values),
@@ -64,10 +159,35 @@ impl Variables {
         let values = self.values.as_ref().map(|values|
             builder.create_vector(values));
 
+        let info = self.info.as_ref().map(|info|
+            KeyValue::build_vector(info, builder));
+
         fb::Variables::create(builder, &fb::VariablesArgs {
             variable_ids,
             values,
-            info: None,
+            info,
         })
     }
 }
+
+fn reduce(value: BigUint, modulus: Option<&BigUint>) -> BigUint {
+    match modulus {
+        Some(modulus) => value % modulus,
+        None => value,
+    }
+}
+
+/// Encode values with the width of the largest one, of at least one byte.
+pub(crate) fn encode_minimal<'a>(values: impl Iterator<Item=&'a BigUint> + Clone) -> Vec<u8> {
+    let width = values.clone()
+        .map(|value| value.to_bytes_le().len())
+        .max().unwrap_or(0).max(1);
+
+    let mut encoded = vec![];
+    for value in values {
+        let mut bytes = value.to_bytes_le();
+        bytes.resize(width, 0);
+        encoded.extend_from_slice(&bytes);
+    }
+    encoded
+}
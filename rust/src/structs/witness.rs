@@ -1,7 +1,9 @@
 use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use std::io::Write;
+use num_bigint::BigUint;
 use serde::{Deserialize, Serialize};
 use crate::zkinterface_generated::zkinterface as fb;
+use crate::consumers::verifier::read_root;
 use super::variables::Variables;
 use crate::Result;
 use std::convert::TryFrom;
@@ -16,7 +18,7 @@ impl<'a> From<fb::Witness<'a>> for Witness {
     /// Convert from Flatbuffers references to owned structure.
     fn from(fb_witness: fb::Witness) -> Witness {
         Witness {
-            assigned_variables: Variables::from(fb_witness.assigned_variables().unwrap()),
+            assigned_variables: fb_witness.assigned_variables().map(Variables::from).unwrap_or_default(),
         }
     }
 }
@@ -26,13 +28,18 @@ impl<'a> TryFrom<&'a [u8]> for Witness {
 
     fn try_from(buffer: &'a [u8]) -> Result<Self> {
         Ok(Self::from(
-            fb::get_size_prefixed_root_as_root(&buffer)
+            read_root(buffer)?
                 .message_as_witness()
                 .ok_or("Not a Witness message.")?))
     }
 }
 
 impl Witness {
+    /// Normalize the assigned values with `Variables::normalize_values`.
+    pub fn normalize(&self, modulus: Option<&BigUint>) -> Witness {
+        Witness { assigned_variables: self.assigned_variables.normalize_values(modulus) }
+    }
+
     /// Add this structure into a Flatbuffers message builder.
     pub fn build<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
         &'args self,
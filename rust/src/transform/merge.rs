@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use num_bigint::BigUint;
+
+use crate::{Result, CircuitHeader, Variables, Message, Workspace};
+use crate::producers::builder::Sink;
+
+type Var = u64;
+
+/// Merge the headers of parts of a statement into one header.
+///
+/// The headers must have the same field and configuration. Instance variables are combined,
+/// and a variable must have the same value in all headers that assign it.
+/// The free_variable_id is the largest of all headers.
+///
+/// # Example
+/// ```
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::transform::merge::merge_headers;
+///
+/// let mut other = example_circuit_header();
+/// other.free_variable_id = 10;
+///
+/// let header = merge_headers(&[example_circuit_header(), other]).unwrap();
+/// assert_eq!(header.free_variable_id, 10);
+///
+/// let mut incompatible = example_circuit_header();
+/// incompatible.field_maximum = Some(vec![200]);
+/// assert!(merge_headers(&[example_circuit_header(), incompatible]).is_err());
+/// ```
+pub fn merge_headers(headers: &[CircuitHeader]) -> Result<CircuitHeader> {
+    let first = headers.first().ok_or("No header to merge.")?;
+    let field = first.field_maximum.as_deref().map(BigUint::from_bytes_le);
+    let with_values = first.instance_variables.values.is_some();
+
+    let mut ids = vec![];
+    let mut values = BTreeMap::<Var, BigUint>::new();
+    let mut width = 0;
+
+    for header in headers {
+        if header.field_maximum.as_deref().map(BigUint::from_bytes_le) != field {
+            return Err("Cannot merge headers with different fields.".into());
+        }
+        if header.configuration != first.configuration {
+            return Err("Cannot merge headers with different configurations.".into());
+        }
+        if header.instance_variables.values.is_some() != with_values {
+            return Err("Cannot merge headers with and without instance values.".into());
+        }
+
+        width = width.max(header.instance_variables.value_size());
+        for var in header.instance_variables.get_variables() {
+            let value = BigUint::from_bytes_le(var.value);
+            match values.get(&var.id) {
+                None => {
+                    ids.push(var.id);
+                    values.insert(var.id, value);
+                }
+                Some(previous) if *previous != value =>
+                    return Err(format!("Cannot merge headers with different values for instance variable {}.", var.id).into()),
+                Some(_) => {}
+            }
+        }
+    }
+
+    let values = if with_values {
+        let mut encoded = Vec::with_capacity(ids.len() * width);
+        for id in &ids {
+            let mut bytes = values[id].to_bytes_le();
+            bytes.resize(width, 0);
+            encoded.extend_from_slice(&bytes);
+        }
+        Some(encoded)
+    } else {
+        None
+    };
+
+    Ok(CircuitHeader {
+        instance_variables: Variables {
+            variable_ids: ids,
+            values,
+            info: first.instance_variables.info.clone(),
+        },
+        free_variable_id: headers.iter().map(|header| header.free_variable_id).max().unwrap_or(0),
+        field_maximum: first.field_maximum.clone(),
+        configuration: first.configuration.clone(),
+    })
+}
+
+/// Read the headers at the beginning of a workspace.
+/// Headers must come before other messages, and files with headers are read first;
+/// see `check_header_position` for headers found later.
+pub(crate) fn read_headers(workspace: &Workspace) -> Result<Vec<CircuitHeader>> {
+    let mut headers = vec![];
+    for msg in workspace.try_iter_messages() {
+        match msg? {
+            Message::Header(header) => headers.push(header),
+            _ => break,
+        }
+    }
+    Ok(headers)
+}
+
+/// Fail on a header that comes after other messages, which `read_headers` would have missed.
+/// `in_headers` must be true before the first message.
+pub(crate) fn check_header_position(msg: &Message, in_headers: &mut bool) -> Result<()> {
+    match msg {
+        Message::Header(_) if !*in_headers =>
+            Err("A header was found after other messages; headers must come first.".into()),
+        Message::Header(_) => Ok(()),
+        _ => {
+            *in_headers = false;
+            Ok(())
+        }
+    }
+}
+
+/// Merge several workspaces into one statement, for instance constraints produced in separate runs.
+///
+/// The headers are merged with `merge_headers`, and pushed first.
+/// Then the witnesses and constraints of all workspaces are pushed in order, one message at a time.
+/// The variable IDs must refer to the same variables in all workspaces.
+pub fn merge(workspaces: &[Workspace], mut sink: impl Sink) -> Result<()> {
+    let mut headers = vec![];
+    for workspace in workspaces {
+        headers.extend(read_headers(workspace)?);
+    }
+    sink.push_header(merge_headers(&headers)?)?;
+
+    for workspace in workspaces {
+        let mut in_headers = true;
+        for msg in workspace.try_iter_messages() {
+            let msg = msg?;
+            check_header_position(&msg, &mut in_headers)?;
+            match msg {
+                Message::Header(_) => {}
+                Message::Witness(witness) => sink.push_witness(witness)?,
+                Message::ConstraintSystem(cs) => sink.push_constraints(cs)?,
+                Message::Command(_) => {}
+                Message::Err(err) => return Err(err.into()),
+            }
+        }
+    }
+    Ok(())
+}
+
+
+#[test]
+fn test_merge() -> Result<()> {
+    use std::fs::remove_dir_all;
+    use std::path::PathBuf;
+    use crate::{Messages, WorkspaceSink};
+    use crate::producers::examples::*;
+    use crate::consumers::simulator::Simulator;
+
+    let dir = PathBuf::from("local/test_merge");
+    let _ = remove_dir_all(&dir);
+
+    // Two parts of the example statement, each with part of the instance.
+    let constraints = example_constraints().constraints;
+    let mut header_1 = example_circuit_header();
+    header_1.instance_variables = Variables { variable_ids: vec![1, 2], values: Some(vec![3, 4]), info: None };
+    let mut sink = WorkspaceSink::new(dir.join("part_1"))?;
+    sink.push_header(header_1)?;
+    sink.push_witness(example_witness())?;
+    sink.push_constraints(crate::ConstraintSystem { constraints: constraints[..2].to_vec(), info: None })?;
+
+    let mut header_2 = example_circuit_header();
+    header_2.instance_variables = Variables { variable_ids: vec![3, 1], values: Some(vec![25, 0, 3, 0]), info: None };
+    let mut sink = WorkspaceSink::new(dir.join("part_2"))?;
+    sink.push_header(header_2.clone())?;
+    sink.push_constraints(crate::ConstraintSystem { constraints: constraints[2..].to_vec(), info: None })?;
+
+    let parts = vec![
+        Workspace::from_dir(&dir.join("part_1"))?,
+        Workspace::from_dir(&dir.join("part_2"))?,
+    ];
+    let mut merged = Messages::default();
+    merge(&parts, &mut merged)?;
+
+    assert_eq!(merged.circuit_headers.len(), 1);
+    let instance = &merged.circuit_headers[0].instance_variables;
+    assert_eq!(instance.variable_ids, vec![1, 2, 3]);
+    assert_eq!(instance.values, Some(vec![3, 0, 4, 0, 25, 0]));
+    assert_eq!(merged.constraint_systems.len(), 2);
+    assert_eq!(merged.witnesses.len(), 1);
+
+    let mut simulator = Simulator::default();
+    simulator.ingest_header(&merged.circuit_headers[0])?;
+    simulator.ingest_witness(&merged.witnesses[0])?;
+    for cs in &merged.constraint_systems {
+        simulator.ingest_constraint_system(cs)?;
+    }
+    assert_eq!(simulator.get_violations(), Vec::<String>::new());
+
+    // A conflicting instance value.
+    header_2.instance_variables.values = Some(vec![25, 0, 4, 0]);
+    WorkspaceSink::new(dir.join("part_2"))?.push_header(header_2)?;
+    let parts = vec![
+        Workspace::from_dir(&dir.join("part_1"))?,
+        Workspace::from_dir(&dir.join("part_2"))?,
+    ];
+    assert!(merge(&parts, &mut Messages::default()).is_err());
+
+    // A header after other messages.
+    let late = dir.join("late.zkif");
+    let mut file = std::fs::File::create(&late)?;
+    example_constraints().write_into(&mut file)?;
+    example_circuit_header().write_into(&mut file)?;
+    drop(file);
+    let late = Workspace::from_filenames(vec![late]);
+    assert!(merge(&[late], &mut Messages::default()).is_err());
+
+    Ok(())
+}
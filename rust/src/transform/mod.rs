@@ -0,0 +1,5 @@
+pub mod optimizer;
+pub mod renumber;
+pub mod merge;
+pub mod split;
+pub mod normalize;
@@ -0,0 +1,99 @@
+use num_bigint::BigUint;
+
+use crate::{Result, Message, Workspace};
+use crate::producers::builder::Sink;
+
+/// Normalize all messages of a workspace into a canonical form, one message at a time.
+///
+/// Values and coefficients are reduced modulo the field of the first header, which must come
+/// before other messages. See `Variables::normalize`, `BilinearConstraint::normalize`,
+/// `CircuitHeader::normalize` and `Witness::normalize`.
+///
+/// # Example
+/// ```
+/// use zkinterface::{Workspace, WorkspaceSink, Messages, Sink};
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::transform::normalize::normalize;
+///
+/// let mut sink = WorkspaceSink::new("local/test_normalize_example").unwrap();
+/// sink.push_header(example_circuit_header()).unwrap();
+/// sink.push_constraints(example_constraints()).unwrap();
+///
+/// let workspace = Workspace::from_dir("local/test_normalize_example".as_ref()).unwrap();
+/// let mut normalized = Messages::default();
+/// normalize(&workspace, true, &mut normalized).unwrap();
+///
+/// // Instance values are encoded with a single byte.
+/// assert_eq!(normalized.circuit_headers[0].instance_variables.values, Some(vec![3, 4, 25]));
+/// ```
+pub fn normalize(workspace: &Workspace, swap_ab: bool, mut sink: impl Sink) -> Result<()> {
+    let mut modulus: Option<BigUint> = None;
+    let mut got_header = false;
+
+    for msg in workspace.try_iter_messages() {
+        match msg? {
+            Message::Header(header) => {
+                if !got_header {
+                    modulus = header.modulus();
+                    got_header = true;
+                }
+                sink.push_header(header.normalize())?;
+            }
+            Message::Witness(witness) => sink.push_witness(witness.normalize(modulus.as_ref()))?,
+            Message::ConstraintSystem(cs) => sink.push_constraints(cs.normalize(modulus.as_ref(), swap_ab))?,
+            Message::Command(_) => {}
+            Message::Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+
+#[test]
+fn test_normalize() -> Result<()> {
+    use std::fs::remove_dir_all;
+    use std::path::PathBuf;
+    use crate::{Messages, WorkspaceSink, ConstraintSystem, Variables};
+    use crate::producers::examples::*;
+
+    let dir = PathBuf::from("local/test_normalize");
+    let _ = remove_dir_all(&dir);
+
+    // The example constraints, written differently.
+    let constraints = ConstraintSystem::from(&[
+        // x * x = xx
+        ((vec![1], vec![1]), (vec![1], vec![1]), (vec![4], vec![1])),
+        // y * (50y + 52y) = yy + 0x
+        ((vec![2, 2], vec![50, 52]), (vec![2], vec![1]), (vec![5, 1], vec![1, 0])),
+        // (xx + yy) * 1 = zz, with 2-byte coefficients.
+        ((vec![5, 4], vec![1, 0, 1, 0]), (vec![0], vec![102]), (vec![3], vec![1])),
+    ][..]);
+    let mut sink = WorkspaceSink::new(&dir)?;
+    sink.push_header(example_circuit_header())?;
+    sink.push_witness(example_witness())?;
+    sink.push_constraints(constraints)?;
+
+    let mut normalized = Messages::default();
+    normalize(&Workspace::from_dir(&dir)?, true, &mut normalized)?;
+
+    let expected = example_constraints();
+    assert_eq!(normalized.constraint_systems, vec![ConstraintSystem {
+        constraints: expected.constraints,
+        info: None,
+    }]);
+    assert_eq!(normalized.witnesses[0].assigned_variables, Variables {
+        variable_ids: vec![4, 5],
+        values: Some(vec![9, 16]),
+        info: None,
+    });
+    assert_eq!(normalized.circuit_headers[0].field_maximum, Some(vec![100]));
+
+    // Normalizing is idempotent.
+    let dir_2 = dir.join("normalized");
+    normalized.push_into(&mut WorkspaceSink::new(&dir_2)?)?;
+    let mut twice = Messages::default();
+    normalize(&Workspace::from_dir(&dir_2)?, true, &mut twice)?;
+    assert_eq!(twice, normalized);
+
+    Ok(())
+}
@@ -0,0 +1,354 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use num_bigint::BigUint;
+use num_traits::identities::{Zero, One};
+
+use crate::{Result, CircuitHeader, ConstraintSystem, BilinearConstraint, Variables, Witness, Messages};
+
+type Var = u64;
+type Field = BigUint;
+/// A sparse linear combination, with reduced and nonzero coefficients.
+type Lc = BTreeMap<Var, Field>;
+
+/// What the optimizer removed from a statement.
+#[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct OptimizationReport {
+    pub constraints_before: u64,
+    pub constraints_after: u64,
+    /// Linear constraints used to substitute a witness variable.
+    pub linear_constraints_eliminated: u64,
+    pub duplicate_constraints_removed: u64,
+    /// Constraints that hold for any assignment, such as 0 = 0.
+    pub trivial_constraints_removed: u64,
+    /// Witness variables replaced by a linear combination of other variables.
+    pub eliminated_variables: Vec<Var>,
+    /// Witness assignments dropped because no constraint uses the variable anymore.
+    pub removed_witness_variables: Vec<Var>,
+}
+
+/// Optimize a statement for backends that charge per constraint.
+///
+/// Linear constraints, where A or B is a constant, are used to substitute away a witness variable
+/// in all other constraints. Constraints that became trivially true and duplicates are removed,
+/// and the witness is rewritten accordingly. Instance variables and variable IDs are unchanged.
+///
+/// # Example
+/// ```
+/// use zkinterface::Messages;
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::transform::optimizer::optimize;
+///
+/// let messages = Messages {
+///     circuit_headers: vec![example_circuit_header()],
+///     constraint_systems: vec![example_constraints()],
+///     witnesses: vec![example_witness()],
+/// };
+///
+/// let (optimized, report) = optimize(&messages).unwrap();
+/// // 1 * (xx + yy) = zz is used to replace yy by zz - xx.
+/// assert_eq!(optimized.constraint_systems[0].constraints.len(), 2);
+/// assert_eq!(report.eliminated_variables, vec![5]);
+/// ```
+pub fn optimize(messages: &Messages) -> Result<(Messages, OptimizationReport)> {
+    let header = match &messages.circuit_headers[..] {
+        [header] => header,
+        [] => return Err("optimize requires a header.".into()),
+        _ => return Err("optimize requires a single header.".into()),
+    };
+    let optimizer = Optimizer::new(header)?;
+
+    let mut report = OptimizationReport::default();
+    let mut constraints = vec![];
+    let mut used_in = HashMap::<Var, Vec<usize>>::new();
+    for (message_index, cs) in messages.constraint_systems.iter().enumerate() {
+        report.constraints_before += cs.constraints.len() as u64;
+        for constraint in &cs.constraints {
+            let (message_index, a, b, c) = optimizer.lc_constraint(message_index, constraint);
+            // The constraints that use each variable.
+            for id in a.keys().chain(b.keys()).chain(c.keys()) {
+                used_in.entry(*id).or_default().push(constraints.len());
+            }
+            constraints.push(Some((message_index, a, b, c)));
+        }
+    }
+
+    // Eliminate linear constraints until no more substitution is possible.
+    // A substitution is applied to the constraints that use the variable,
+    // which are checked again because they may have become linear.
+    let mut queue = (0..constraints.len()).collect::<VecDeque<_>>();
+    let mut queued = vec![true; constraints.len()];
+
+    while let Some(index) = queue.pop_front() {
+        queued[index] = false;
+        let linear = match constraints[index].as_ref().and_then(|constraint| optimizer.as_linear(constraint)) {
+            Some(linear) => linear,
+            None => continue,
+        };
+
+        if linear.is_empty() {
+            report.trivial_constraints_removed += 1;
+            constraints[index] = None;
+        } else if let Some((var, value)) = optimizer.eliminate(linear) {
+            report.linear_constraints_eliminated += 1;
+            report.eliminated_variables.push(var);
+            constraints[index] = None;
+
+            for user in used_in.remove(&var).unwrap_or_default() {
+                let constraint = match &mut constraints[user] {
+                    Some(constraint) => constraint,
+                    None => continue,
+                };
+                if !optimizer.substitute(constraint, var, &value) { continue; }
+                for id in value.keys() {
+                    used_in.entry(*id).or_default().push(user);
+                }
+                if !queued[user] {
+                    queued[user] = true;
+                    queue.push_back(user);
+                }
+            }
+        }
+    }
+
+    // Rewrite the remaining constraints and drop duplicates.
+    let mut optimized_systems: Vec<ConstraintSystem> = messages.constraint_systems.iter()
+        .map(|cs| ConstraintSystem { constraints: vec![], info: cs.info.clone() })
+        .collect();
+    let mut seen = HashSet::new();
+    let mut used_vars = HashSet::new();
+
+    for (message_index, a, b, c) in constraints.into_iter().flatten() {
+
+        let (first, second) = if a <= b { (&a, &b) } else { (&b, &a) };
+        if !seen.insert((first.clone(), second.clone(), c.clone())) {
+            report.duplicate_constraints_removed += 1;
+            continue;
+        }
+
+        used_vars.extend(a.keys().chain(b.keys()).chain(c.keys()).cloned());
+        optimized_systems[message_index].constraints.push(BilinearConstraint {
+            linear_combination_a: optimizer.encode(&a),
+            linear_combination_b: optimizer.encode(&b),
+            linear_combination_c: optimizer.encode(&c),
+        });
+    }
+    optimized_systems.retain(|cs| !cs.constraints.is_empty());
+    report.constraints_after = optimized_systems.iter()
+        .map(|cs| cs.constraints.len() as u64)
+        .sum();
+
+    // Keep only the assignments of variables still in use.
+    let witnesses = messages.witnesses.iter().map(|witness| {
+        let mut assigned_variables = Variables {
+            variable_ids: vec![],
+            values: witness.assigned_variables.values.as_ref().map(|_| vec![]),
+            info: witness.assigned_variables.info.clone(),
+        };
+        for var in witness.assigned_variables.get_variables() {
+            if used_vars.contains(&var.id) {
+                assigned_variables.variable_ids.push(var.id);
+                if let Some(ref mut values) = assigned_variables.values {
+                    values.extend_from_slice(var.value);
+                }
+            } else {
+                report.removed_witness_variables.push(var.id);
+            }
+        }
+        Witness { assigned_variables }
+    }).collect();
+
+    report.eliminated_variables.sort_unstable();
+    report.removed_witness_variables.sort_unstable();
+
+    let optimized = Messages {
+        circuit_headers: vec![header.clone()],
+        constraint_systems: optimized_systems,
+        witnesses,
+    };
+    Ok((optimized, report))
+}
+
+/// A constraint A * B = C, and the index of the message it comes from.
+type LcConstraint = (usize, Lc, Lc, Lc);
+
+struct Optimizer {
+    modulus: Field,
+    /// Byte width of encoded coefficients.
+    width: usize,
+    instance_ids: HashSet<Var>,
+}
+
+impl Optimizer {
+    fn new(header: &CircuitHeader) -> Result<Optimizer> {
+        let max = header.field_maximum.as_ref().ok_or("No field_maximum specified")?;
+        let max = Field::from_bytes_le(max);
+        Ok(Optimizer {
+            width: max.to_bytes_le().len(),
+            modulus: max + 1u8,
+            instance_ids: header.instance_variables.variable_ids.iter().cloned().collect(),
+        })
+    }
+
+    fn lc_constraint(&self, message_index: usize, constraint: &BilinearConstraint) -> LcConstraint {
+        (message_index,
+         self.lc(&constraint.linear_combination_a),
+         self.lc(&constraint.linear_combination_b),
+         self.lc(&constraint.linear_combination_c))
+    }
+
+    fn lc(&self, terms: &Variables) -> Lc {
+        let mut lc = Lc::new();
+        for term in terms.get_variables() {
+            let coeff = Field::from_bytes_le(term.value);
+            self.add_term(&mut lc, term.id, &coeff);
+        }
+        lc
+    }
+
+    fn add_term(&self, lc: &mut Lc, id: Var, coeff: &Field) {
+        let sum = (lc.remove(&id).unwrap_or_default() + coeff) % &self.modulus;
+        if !sum.is_zero() {
+            lc.insert(id, sum);
+        }
+    }
+
+    fn add_scaled(&self, lc: &mut Lc, other: &Lc, factor: &Field) {
+        for (id, coeff) in other {
+            self.add_term(lc, *id, &(coeff * factor));
+        }
+    }
+
+    fn neg(&self, value: &Field) -> Field {
+        (&self.modulus - value % &self.modulus) % &self.modulus
+    }
+
+    /// Inverse of a nonzero element, assuming a prime modulus.
+    fn inv(&self, value: &Field) -> Field {
+        let exponent = &self.modulus - 2u8;
+        value.modpow(&exponent, &self.modulus)
+    }
+
+    /// Replace a variable by its value in a constraint. Returns whether the constraint used it.
+    fn substitute(&self, (_, a, b, c): &mut LcConstraint, var: Var, value: &Lc) -> bool {
+        let mut changed = false;
+        for lc in [a, b, c].iter_mut() {
+            if let Some(coeff) = lc.remove(&var) {
+                self.add_scaled(lc, value, &coeff);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// If the constraint is linear, return it as a combination equal to zero.
+    fn as_linear(&self, (_, a, b, c): &LcConstraint) -> Option<Lc> {
+        let (factor, lc) = if let Some(factor) = constant_value(a) {
+            (factor, b)
+        } else if let Some(factor) = constant_value(b) {
+            (factor, a)
+        } else {
+            return None;
+        };
+
+        // factor * lc - c = 0
+        let mut linear = Lc::new();
+        self.add_scaled(&mut linear, lc, &factor);
+        self.add_scaled(&mut linear, c, &self.neg(&Field::one()));
+        Some(linear)
+    }
+
+    /// Use a linear combination equal to zero to express one of its witness variables
+    /// in terms of the other variables. The variable with the highest ID is chosen, if any.
+    fn eliminate(&self, mut linear: Lc) -> Option<(Var, Lc)> {
+        let var = *linear.keys().rev()
+            .find(|id| **id != 0 && !self.instance_ids.contains(id))?;
+
+        // var = -1/coeff * (rest)
+        let coeff = linear.remove(&var)?;
+        let factor = self.neg(&self.inv(&coeff));
+        let mut value = Lc::new();
+        self.add_scaled(&mut value, &linear, &factor);
+        Some((var, value))
+    }
+
+    fn encode(&self, lc: &Lc) -> Variables {
+        let mut values = Vec::with_capacity(lc.len() * self.width);
+        for coeff in lc.values() {
+            let mut bytes = coeff.to_bytes_le();
+            bytes.resize(self.width, 0);
+            values.extend_from_slice(&bytes);
+        }
+        Variables {
+            variable_ids: lc.keys().cloned().collect(),
+            values: Some(values),
+            info: None,
+        }
+    }
+}
+
+/// The value of a combination of the constant variable 0 only, or None if it uses other variables.
+fn constant_value(lc: &Lc) -> Option<Field> {
+    match lc.iter().next() {
+        None => Some(Field::zero()),
+        Some((0, value)) if lc.len() == 1 => Some(value.clone()),
+        _ => None,
+    }
+}
+
+
+#[test]
+fn test_optimize() -> Result<()> {
+    use crate::producers::examples::*;
+    use crate::consumers::simulator::Simulator;
+    use crate::consumers::validator::Validator;
+
+    let mut constraints = example_constraints();
+    constraints.constraints.extend(ConstraintSystem::from(&[
+        // A duplicate written differently: x * (50x + 52x) = xx
+        ((vec![1], vec![1]), (vec![1, 1], vec![50, 52]), (vec![4], vec![1])),
+        // A constraint made trivial by the substitution: 1 * yy = zz - xx
+        ((vec![0], vec![1]), (vec![5], vec![1]), (vec![3, 4], vec![1, 100])),
+    ][..]).constraints);
+
+    let messages = Messages {
+        circuit_headers: vec![example_circuit_header()],
+        constraint_systems: vec![constraints],
+        witnesses: vec![example_witness()],
+    };
+
+    let (optimized, report) = optimize(&messages)?;
+    assert_eq!(report, OptimizationReport {
+        constraints_before: 5,
+        constraints_after: 2,
+        linear_constraints_eliminated: 1,
+        duplicate_constraints_removed: 1,
+        trivial_constraints_removed: 1,
+        eliminated_variables: vec![5],
+        removed_witness_variables: vec![5],
+    });
+
+    // y * y = zz - xx
+    let cs = &optimized.constraint_systems[0];
+    assert_eq!(cs.constraints[1].linear_combination_c.variable_ids, vec![3, 4]);
+    assert_eq!(cs.constraints[1].linear_combination_c.values, Some(vec![1, 100]));
+    assert_eq!(optimized.witnesses[0].assigned_variables.variable_ids, vec![4]);
+
+    let mut simulator = Simulator::default();
+    let mut validator = Validator::new_as_prover();
+    for header in &optimized.circuit_headers {
+        simulator.ingest_header(header)?;
+        validator.ingest_header(header);
+    }
+    for witness in &optimized.witnesses {
+        simulator.ingest_witness(witness)?;
+        validator.ingest_witness(witness);
+    }
+    for cs in &optimized.constraint_systems {
+        simulator.ingest_constraint_system(cs)?;
+        validator.ingest_constraint_system(cs);
+    }
+    assert_eq!(simulator.get_violations(), Vec::<String>::new());
+    assert_eq!(validator.get_violations(), Vec::<String>::new());
+
+    Ok(())
+}
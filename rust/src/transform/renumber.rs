@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{Result, CircuitHeader, ConstraintSystem, BilinearConstraint, Variables, Witness, Messages, KeyValue};
+
+type Var = u64;
+
+/// Renumber variables densely, and set free_variable_id to the number of variables.
+///
+/// The constant 0 is kept, then instance variables get IDs from 1 in the order of the header,
+/// then witness variables in the order of their first use in constraints,
+/// then variables that are assigned in a witness but not used in constraints.
+/// Other IDs below free_variable_id are dropped.
+/// Variable names in `info` (see `Variables::get_names`) follow their variables, or are dropped with them.
+///
+/// Returns the renumbered messages and the mapping from old to new IDs.
+///
+/// # Example
+/// ```
+/// use zkinterface::Messages;
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::transform::renumber::renumber;
+///
+/// let mut header = example_circuit_header();
+/// header.free_variable_id = 100;
+///
+/// let messages = Messages {
+///     circuit_headers: vec![header],
+///     constraint_systems: vec![example_constraints()],
+///     witnesses: vec![example_witness()],
+/// };
+///
+/// let (renumbered, mapping) = renumber(&messages).unwrap();
+/// assert_eq!(renumbered.circuit_headers[0].free_variable_id, 6);
+/// assert_eq!(mapping[&5], 5);
+/// ```
+pub fn renumber(messages: &Messages) -> Result<(Messages, BTreeMap<Var, Var>)> {
+    let header = match &messages.circuit_headers[..] {
+        [header] => header,
+        [] => return Err("renumber requires a header.".into()),
+        _ => return Err("renumber requires a single header.".into()),
+    };
+
+    let mut renumbering = Renumbering::default();
+    renumbering.assign(0);
+    for id in &header.instance_variables.variable_ids {
+        renumbering.assign(*id);
+    }
+    for cs in &messages.constraint_systems {
+        for constraint in &cs.constraints {
+            for lc in &[&constraint.linear_combination_a, &constraint.linear_combination_b, &constraint.linear_combination_c] {
+                for id in &lc.variable_ids {
+                    renumbering.assign(*id);
+                }
+            }
+        }
+    }
+    for witness in &messages.witnesses {
+        for id in &witness.assigned_variables.variable_ids {
+            renumbering.assign(*id);
+        }
+    }
+
+    let renumbered = Messages {
+        circuit_headers: vec![CircuitHeader {
+            instance_variables: renumbering.rewrite(&header.instance_variables),
+            free_variable_id: renumbering.free_variable_id,
+            ..header.clone()
+        }],
+        constraint_systems: messages.constraint_systems.iter().map(|cs|
+            ConstraintSystem {
+                constraints: cs.constraints.iter().map(|constraint|
+                    BilinearConstraint {
+                        linear_combination_a: renumbering.rewrite(&constraint.linear_combination_a),
+                        linear_combination_b: renumbering.rewrite(&constraint.linear_combination_b),
+                        linear_combination_c: renumbering.rewrite(&constraint.linear_combination_c),
+                    }
+                ).collect(),
+                info: cs.info.clone(),
+            }
+        ).collect(),
+        witnesses: messages.witnesses.iter().map(|witness|
+            Witness {
+                assigned_variables: renumbering.rewrite(&witness.assigned_variables),
+            }
+        ).collect(),
+    };
+
+    Ok((renumbered, renumbering.mapping.into_iter().collect()))
+}
+
+#[derive(Default)]
+struct Renumbering {
+    mapping: HashMap<Var, Var>,
+    free_variable_id: Var,
+}
+
+impl Renumbering {
+    fn assign(&mut self, id: Var) {
+        let free_variable_id = &mut self.free_variable_id;
+        self.mapping.entry(id).or_insert_with(|| {
+            *free_variable_id += 1;
+            *free_variable_id - 1
+        });
+    }
+
+    fn rewrite(&self, variables: &Variables) -> Variables {
+        Variables {
+            variable_ids: variables.variable_ids.iter()
+                .map(|id| self.mapping[id])
+                .collect(),
+            values: variables.values.clone(),
+            info: variables.info.as_ref().map(|info| self.rewrite_names(info)),
+        }
+    }
+
+    /// Rewrite the keys that are variable IDs, and drop those of dropped variables.
+    fn rewrite_names(&self, info: &[KeyValue]) -> Vec<KeyValue> {
+        info.iter().filter_map(|kv| {
+            match kv.key.parse::<Var>() {
+                Ok(id) => self.mapping.get(&id).map(|new_id|
+                    KeyValue { key: new_id.to_string(), ..kv.clone() }),
+                Err(_) => Some(kv.clone()),
+            }
+        }).collect()
+    }
+}
+
+
+#[test]
+fn test_renumber() -> Result<()> {
+    use crate::producers::examples::*;
+    use crate::transform::optimizer::optimize;
+    use crate::consumers::simulator::Simulator;
+    use crate::consumers::validator::Validator;
+
+    // Optimizing away yy leaves a gap between xx and free_variable_id.
+    let messages = Messages {
+        circuit_headers: vec![example_circuit_header()],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness()],
+    };
+    let (optimized, _) = optimize(&messages)?;
+    assert_eq!(optimized.circuit_headers[0].free_variable_id, 6);
+
+    let (renumbered, mapping) = renumber(&optimized)?;
+    assert_eq!(mapping.into_iter().collect::<Vec<_>>(), vec![(0, 0), (1, 1), (2, 2), (3, 3), (4, 4)]);
+    assert_eq!(renumbered.circuit_headers[0].free_variable_id, 5);
+
+    // Witness variables are numbered in the order of first use.
+    let mut constraints = example_constraints();
+    constraints.constraints.swap(0, 1);
+    let mut header = example_circuit_header();
+    header.instance_variables.variable_ids = vec![10, 20, 30];
+    header.free_variable_id = 100;
+    let witness = Witness {
+        assigned_variables: Variables {
+            variable_ids: vec![40, 50, 60],
+            values: Some(serialize_small(&[9, 16, 0])),
+            // Names of variables, including one that is dropped, and another key.
+            info: Some(vec![
+                KeyValue::from((40, "xx")),
+                KeyValue::from((60, "unused")),
+                KeyValue::from((70, "dropped")),
+                KeyValue::from(("comment", "not a variable")),
+            ]),
+        }
+    };
+    let mut messages = Messages {
+        circuit_headers: vec![header],
+        constraint_systems: vec![constraints],
+        witnesses: vec![witness],
+    };
+    for constraint in &mut messages.constraint_systems[0].constraints {
+        for lc in &mut [&mut constraint.linear_combination_a, &mut constraint.linear_combination_b, &mut constraint.linear_combination_c] {
+            for id in &mut lc.variable_ids {
+                *id *= 10;
+            }
+        }
+    }
+
+    let (renumbered, mapping) = renumber(&messages)?;
+    assert_eq!(mapping.into_iter().collect::<Vec<_>>(), vec![(0, 0), (10, 1), (20, 2), (30, 3), (40, 5), (50, 4), (60, 6)]);
+    assert_eq!(renumbered.circuit_headers[0].instance_variables.variable_ids, vec![1, 2, 3]);
+    assert_eq!(renumbered.circuit_headers[0].free_variable_id, 7);
+    assert_eq!(renumbered.witnesses[0].assigned_variables.variable_ids, vec![5, 4, 6]);
+    assert_eq!(renumbered.witnesses[0].assigned_variables.get_names(), vec![(5, "xx"), (6, "unused")]);
+    assert_eq!(renumbered.witnesses[0].assigned_variables.info.as_ref().unwrap()[2].key, "comment");
+
+    let mut simulator = Simulator::default();
+    let mut validator = Validator::new_as_prover();
+    for header in &renumbered.circuit_headers {
+        simulator.ingest_header(header)?;
+        validator.ingest_header(header);
+    }
+    for witness in &renumbered.witnesses {
+        simulator.ingest_witness(witness)?;
+        validator.ingest_witness(witness);
+    }
+    for cs in &renumbered.constraint_systems {
+        simulator.ingest_constraint_system(cs)?;
+        validator.ingest_constraint_system(cs);
+    }
+    assert_eq!(simulator.get_violations(), Vec::<String>::new());
+    // Variable 60 was assigned but never used.
+    assert_eq!(validator.get_violations(), vec!["variable_6 was defined but not used.".to_string()]);
+
+    Ok(())
+}
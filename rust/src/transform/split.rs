@@ -0,0 +1,181 @@
+use std::mem;
+
+use crate::{Result, ConstraintSystem, BilinearConstraint, Variables, Witness, Message, Workspace};
+use crate::producers::builder::Sink;
+use crate::transform::merge::{merge_headers, read_headers, check_header_position};
+
+/// The maximum size of messages written by `split`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SplitLimits {
+    /// The number of constraints in a ConstraintSystem, or of variables in a Witness.
+    pub max_items: Option<u64>,
+    /// The size of a message in bytes, as estimated conservatively before writing it.
+    pub max_bytes: Option<u64>,
+}
+
+impl SplitLimits {
+    fn allows(&self, items: u64, bytes: u64) -> bool {
+        !matches!(self.max_items, Some(max) if items > max) &&
+            !matches!(self.max_bytes, Some(max) if bytes > max)
+    }
+}
+
+/// Split the constraint systems and witnesses of a workspace into messages within the limits.
+///
+/// The headers are merged with `merge::merge_headers`, and the header is pushed first.
+/// Then each message is split in order, and pushed. A single constraint or variable
+/// that does not fit within `max_bytes` is pushed in its own message.
+///
+/// With a `WorkspaceSink`, each constraint system goes into its own file;
+/// use `WorkspaceSink::new_with_witness_files` to do the same with witnesses.
+pub fn split(workspace: &Workspace, limits: &SplitLimits, mut sink: impl Sink) -> Result<()> {
+    sink.push_header(merge_headers(&read_headers(workspace)?)?)?;
+
+    let mut in_headers = true;
+    for msg in workspace.try_iter_messages() {
+        let msg = msg?;
+        check_header_position(&msg, &mut in_headers)?;
+        match msg {
+            Message::Header(_) => {}
+            Message::Witness(witness) => split_witness(witness, limits, &mut sink)?,
+            Message::ConstraintSystem(cs) => split_constraints(cs, limits, &mut sink)?,
+            Message::Command(_) => {}
+            Message::Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+fn split_constraints(cs: ConstraintSystem, limits: &SplitLimits, sink: &mut impl Sink) -> Result<()> {
+    let empty = ConstraintSystem { constraints: vec![], info: cs.info };
+    let base_size = message_size(|buf| empty.write_into(buf))?;
+
+    let mut chunk = empty.clone();
+    let mut chunk_size = base_size;
+
+    for constraint in cs.constraints {
+        let size = constraint_size(&constraint);
+        let items = chunk.constraints.len() as u64 + 1;
+        if !chunk.constraints.is_empty() && !limits.allows(items, chunk_size + size) {
+            sink.push_constraints(mem::replace(&mut chunk, empty.clone()))?;
+            chunk_size = base_size;
+        }
+        chunk.constraints.push(constraint);
+        chunk_size += size;
+    }
+
+    if !chunk.constraints.is_empty() {
+        sink.push_constraints(chunk)?;
+    }
+    Ok(())
+}
+
+fn split_witness(witness: Witness, limits: &SplitLimits, sink: &mut impl Sink) -> Result<()> {
+    let vars = witness.assigned_variables;
+    let value_size = vars.value_size();
+    let empty = Variables {
+        variable_ids: vec![],
+        values: vars.values.as_ref().map(|_| vec![]),
+        info: vars.info.clone(),
+    };
+    // With padding after the values.
+    let base_size = message_size(|buf| Witness { assigned_variables: empty.clone() }.write_into(buf))? + 8;
+    let var_size = 8 + value_size as u64;
+
+    let mut chunk = empty.clone();
+    let mut chunk_size = base_size;
+
+    for var in vars.get_variables() {
+        let items = chunk.variable_ids.len() as u64 + 1;
+        if !chunk.variable_ids.is_empty() && !limits.allows(items, chunk_size + var_size) {
+            sink.push_witness(Witness { assigned_variables: mem::replace(&mut chunk, empty.clone()) })?;
+            chunk_size = base_size;
+        }
+        chunk.variable_ids.push(var.id);
+        if let Some(values) = &mut chunk.values {
+            values.extend_from_slice(var.value);
+        }
+        chunk_size += var_size;
+    }
+
+    if !chunk.variable_ids.is_empty() {
+        sink.push_witness(Witness { assigned_variables: chunk })?;
+    }
+    Ok(())
+}
+
+fn message_size(write: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> Result<u64> {
+    let mut buf = vec![];
+    write(&mut buf)?;
+    Ok(buf.len() as u64)
+}
+
+/// An upper bound of the size of a constraint in a message.
+fn constraint_size(constraint: &BilinearConstraint) -> u64 {
+    let lc_size = |lc: &Variables| {
+        let values = lc.values.as_ref().map_or(0, |values| values.len());
+        let info = lc.info.iter().flatten()
+            .map(|kv| 48 + kv.key.len() + kv.text.as_ref().map_or(0, |text| text.len()) + kv.data.as_ref().map_or(0, |data| data.len()))
+            .sum::<usize>();
+        // Table, vtable, and vectors with their length and padding.
+        44 + 8 * lc.variable_ids.len() as u64 + values as u64 + info as u64
+    };
+    // Table, vtable, and offset in the vector of constraints.
+    32 + lc_size(&constraint.linear_combination_a)
+        + lc_size(&constraint.linear_combination_b)
+        + lc_size(&constraint.linear_combination_c)
+}
+
+
+#[test]
+fn test_split() -> Result<()> {
+    use std::fs::{read_dir, metadata, remove_dir_all};
+    use std::path::PathBuf;
+    use crate::{Messages, WorkspaceSink};
+    use crate::producers::examples::*;
+    use crate::consumers::diff::diff;
+
+    let dir = PathBuf::from("local/test_split");
+    let _ = remove_dir_all(&dir);
+
+    let mut constraints = example_constraints();
+    for _ in 0..3 {
+        constraints.constraints.extend(example_constraints().constraints);
+    }
+    let original = Messages {
+        circuit_headers: vec![example_circuit_header()],
+        constraint_systems: vec![constraints],
+        witnesses: vec![example_witness()],
+    };
+    original.push_into(&mut WorkspaceSink::new(dir.join("original"))?)?;
+    let workspace = Workspace::from_dir(&dir.join("original"))?;
+
+    // At most 5 constraints or variables per message.
+    let mut split_messages = Messages::default();
+    split(&workspace, &SplitLimits { max_items: Some(5), max_bytes: None }, &mut split_messages)?;
+    let sizes = split_messages.constraint_systems.iter().map(|cs| cs.constraints.len()).collect::<Vec<_>>();
+    assert_eq!(sizes, vec![5, 5, 2]);
+    assert_eq!(split_messages.witnesses.len(), 1);
+    assert!(diff(&original, &split_messages, false)?.is_empty());
+
+    // At most 600 bytes per file.
+    let out = dir.join("split");
+    let limits = SplitLimits { max_items: None, max_bytes: Some(600) };
+    split(&workspace, &limits, WorkspaceSink::new_with_witness_files(&out)?)?;
+
+    let files = read_dir(&out)?.map(|file| file.map(|file| file.path())).collect::<std::io::Result<Vec<_>>>()?;
+    assert!(files.len() > 3);
+    for file in &files {
+        assert!(metadata(file)?.len() <= 600, "{} is too large", file.display());
+    }
+
+    let split_messages = Workspace::from_dir(&out)?.try_read_all_messages()?;
+    assert!(split_messages.constraint_systems.len() > 1);
+    assert!(diff(&original, &split_messages, false)?.is_empty());
+
+    // Each variable in its own witness.
+    split(&workspace, &SplitLimits { max_items: Some(1), max_bytes: None }, WorkspaceSink::new_with_witness_files(&out)?)?;
+    assert!(out.join("witness_1.zkif").exists());
+
+    Ok(())
+}